@@ -0,0 +1,330 @@
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+
+// How control leaves a basic block
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Terminator {
+    // JUMP
+    Jump,
+    // JUMPI, with a taken and a fallthrough edge
+    ConditionalJump,
+    // STOP, RETURN, REVERT, INVALID, SELFDESTRUCT
+    Halt,
+    // runs into the next block (which starts with a JUMPDEST) or off the end of the code,
+    // which stops like STOP
+    Fallthrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeKind {
+    // the taken side of a JUMP / JUMPI
+    Jump,
+    // execution continuing with the next block
+    Fallthrough,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Edge {
+    pub from: usize,
+    pub to: usize,
+    pub kind: EdgeKind,
+}
+
+// A maximal straight-line run of instructions, [start, end) in instruction indices
+#[derive(Debug, Clone)]
+pub struct BasicBlock {
+    pub id: usize,
+    pub start: usize,
+    pub end: usize,
+    pub start_offset: usize,
+    pub end_offset: usize,
+    pub terminator: Terminator,
+    pub successors: Vec<usize>,
+    pub predecessors: Vec<usize>,
+    // the block ends in a jump whose destination is not a constant known here
+    pub unresolved: bool,
+}
+
+impl BasicBlock {
+    // index of the last instruction of the block
+    pub fn last(&self) -> usize {
+        self.end - 1
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Cfg {
+    pub blocks: Vec<BasicBlock>,
+    pub edges: Vec<Edge>,
+}
+
+fn is_halt(opcode: u8) -> bool {
+    matches!(opcode, 0x00 | 0xf3 | 0xfd | 0xfe | 0xff)
+}
+
+impl Cfg {
+    // split a program into basic blocks and connect the edges known from adjacent
+    // PUSH-JUMP(I) pairs; other jumps are marked as unresolved
+    pub fn build(program: &Program) -> Cfg {
+        let instructions: &[Instruction] = &program.instructions;
+        let mut cfg: Cfg = Cfg::default();
+        if instructions.is_empty() {
+            return cfg;
+        }
+
+        // 1) collect block leaders
+        let mut leaders: Vec<bool> = vec![false; instructions.len() + 1];
+        leaders[0] = true;
+        // the last block ends with the code, in an implicit STOP when nothing halts it
+        leaders[instructions.len()] = true;
+        for (index, instruction) in instructions.iter().enumerate() {
+            if instruction.opcode == 0x5b {
+                leaders[index] = true;
+            }
+            if matches!(instruction.opcode, 0x56 | 0x57) || is_halt(instruction.opcode) {
+                leaders[index + 1] = true;
+            }
+        }
+
+        // 2) cut blocks at every leader
+        let mut start: usize = 0;
+        for index in 1..=instructions.len() {
+            if !leaders[index] {
+                continue;
+            }
+            let last_opcode: u8 = instructions[index - 1].opcode;
+            let terminator: Terminator = match last_opcode {
+                0x56 => Terminator::Jump,
+                0x57 => Terminator::ConditionalJump,
+                opcode if is_halt(opcode) => Terminator::Halt,
+                _ => Terminator::Fallthrough,
+            };
+            let end_offset: usize = match instructions.get(index) {
+                Some(next) => next.offset,
+                None => program.len(),
+            };
+            cfg.blocks.push(BasicBlock {
+                id: cfg.blocks.len(),
+                start,
+                end: index,
+                start_offset: instructions[start].offset,
+                end_offset,
+                terminator,
+                successors: Vec::new(),
+                predecessors: Vec::new(),
+                unresolved: false,
+            });
+            start = index;
+        }
+
+        // 3) connect edges
+        for id in 0..cfg.blocks.len() {
+            let block: BasicBlock = cfg.blocks[id].clone();
+            let has_next: bool = id + 1 < cfg.blocks.len();
+
+            if matches!(
+                block.terminator,
+                Terminator::Jump | Terminator::ConditionalJump
+            ) {
+                let target: Option<usize> = if block.end - block.start >= 2 {
+                    instructions[block.last() - 1].push_value()
+                } else {
+                    None
+                };
+                match target {
+                    // a constant target that is not a JUMPDEST would fail at runtime, no edge
+                    Some(offset) => {
+                        if let Some(to) = cfg.jumpdest_block(program, offset) {
+                            cfg.add_edge(id, to, EdgeKind::Jump);
+                        }
+                    }
+                    None => cfg.blocks[id].unresolved = true,
+                }
+            }
+
+            if matches!(
+                block.terminator,
+                Terminator::ConditionalJump | Terminator::Fallthrough
+            ) && has_next
+            {
+                cfg.add_edge(id, id + 1, EdgeKind::Fallthrough);
+            }
+        }
+
+        cfg
+    }
+
+    // record an edge, ignoring duplicates
+    pub fn add_edge(&mut self, from: usize, to: usize, kind: EdgeKind) {
+        let edge: Edge = Edge { from, to, kind };
+        if self.edges.contains(&edge) {
+            return;
+        }
+        self.edges.push(edge);
+        if !self.blocks[from].successors.contains(&to) {
+            self.blocks[from].successors.push(to);
+        }
+        if !self.blocks[to].predecessors.contains(&from) {
+            self.blocks[to].predecessors.push(from);
+        }
+    }
+
+    pub fn successors(&self, id: usize) -> &[usize] {
+        &self.blocks[id].successors
+    }
+
+    pub fn predecessors(&self, id: usize) -> &[usize] {
+        &self.blocks[id].predecessors
+    }
+
+    // block that starts at a byte offset
+    pub fn block_at_offset(&self, offset: usize) -> Option<usize> {
+        self.blocks
+            .binary_search_by_key(&offset, |block| block.start_offset)
+            .ok()
+    }
+
+    // block containing an instruction index
+    pub fn block_of_instruction(&self, index: usize) -> Option<usize> {
+        let position: usize = self.blocks.partition_point(|block| block.start <= index);
+        let id: usize = position.checked_sub(1)?;
+        (index < self.blocks[id].end).then_some(id)
+    }
+
+    // block starting with a JUMPDEST at `offset`, i.e. a valid jump destination
+    pub fn jumpdest_block(&self, program: &Program, offset: usize) -> Option<usize> {
+        let id: usize = self.block_at_offset(offset)?;
+        (program.instructions[self.blocks[id].start].opcode == 0x5b).then_some(id)
+    }
+
+    // blocks ending in a jump with an unknown destination
+    pub fn unresolved_blocks(&self) -> Vec<usize> {
+        self.blocks
+            .iter()
+            .filter(|block| block.unresolved)
+            .map(|block| block.id)
+            .collect()
+    }
+
+    // blocks reachable from the entry; an unresolved jump may reach any JUMPDEST block
+    pub fn reachable(&self, program: &Program) -> Vec<bool> {
+        let mut reachable: Vec<bool> = vec![false; self.blocks.len()];
+        if self.blocks.is_empty() {
+            return reachable;
+        }
+
+        let mut worklist: Vec<usize> = vec![0];
+        let mut jumpdests_added: bool = false;
+        while let Some(id) = worklist.pop() {
+            if reachable[id] {
+                continue;
+            }
+            reachable[id] = true;
+            worklist.extend(self.blocks[id].successors.iter().copied());
+
+            if self.blocks[id].unresolved && !jumpdests_added {
+                jumpdests_added = true;
+                worklist.extend(
+                    self.blocks
+                        .iter()
+                        .filter(|block| program.instructions[block.start].opcode == 0x5b)
+                        .map(|block| block.id),
+                );
+            }
+        }
+        reachable
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::hex::decode_hex;
+
+    fn build(hex: &str) -> (Program, Cfg) {
+        let program: Program = Program::decode(&decode_hex(hex).unwrap());
+        let cfg: Cfg = Cfg::build(&program);
+        (program, cfg)
+    }
+
+    fn terminators(cfg: &Cfg) -> Vec<Terminator> {
+        cfg.blocks.iter().map(|block| block.terminator).collect()
+    }
+
+    #[test]
+    fn code_running_off_its_end_ends_a_block() {
+        // PUSH1 7 JUMP | JUMPDEST STOP | JUMPDEST STOP | JUMPDEST PUSH1 1 PUSH1 0 SSTORE
+        let (program, cfg) = build("6007565b005b005b6001600055");
+        assert_eq!(
+            terminators(&cfg),
+            vec![
+                Terminator::Jump,
+                Terminator::Halt,
+                Terminator::Halt,
+                Terminator::Fallthrough,
+            ]
+        );
+        assert_eq!(cfg.blocks[3].end, program.instructions.len());
+        assert_eq!(cfg.blocks[3].end_offset, 13);
+        assert_eq!(
+            cfg.edges,
+            vec![Edge {
+                from: 0,
+                to: 3,
+                kind: EdgeKind::Jump,
+            }]
+        );
+        assert!(cfg.blocks[3].successors.is_empty());
+        assert_eq!(cfg.reachable(&program), vec![true, false, false, true]);
+    }
+
+    #[test]
+    fn conditional_jumps_have_two_edges() {
+        // PUSH1 1 PUSH1 8 JUMPI | PUSH1 0 STOP | JUMPDEST STOP
+        let (program, cfg) = build("60016008576000005b00");
+        assert_eq!(
+            terminators(&cfg),
+            vec![
+                Terminator::ConditionalJump,
+                Terminator::Halt,
+                Terminator::Halt
+            ]
+        );
+        assert_eq!(cfg.successors(0), &[2, 1]);
+        assert_eq!(cfg.predecessors(2), &[0]);
+        assert_eq!(cfg.block_of_instruction(3), Some(1));
+        assert_eq!(cfg.block_at_offset(8), Some(2));
+        assert_eq!(cfg.jumpdest_block(&program, 8), Some(2));
+        assert_eq!(cfg.jumpdest_block(&program, 5), None);
+    }
+
+    #[test]
+    fn jumpdests_start_blocks_reached_by_fallthrough() {
+        // PUSH1 1 | JUMPDEST POP STOP
+        let (_, cfg) = build("60015b5000");
+        assert_eq!(
+            terminators(&cfg),
+            vec![Terminator::Fallthrough, Terminator::Halt]
+        );
+        assert_eq!(cfg.edges[0].kind, EdgeKind::Fallthrough);
+    }
+
+    #[test]
+    fn computed_jumps_are_unresolved() {
+        // CALLDATALOAD-driven jump: PUSH0 CALLDATALOAD JUMP | JUMPDEST STOP | JUMPDEST STOP
+        let (program, cfg) = build("5f35565b005b00");
+        assert_eq!(cfg.unresolved_blocks(), vec![0]);
+        assert!(cfg.edges.is_empty());
+        // any JUMPDEST may be the destination
+        assert_eq!(cfg.reachable(&program), vec![true, true, true]);
+    }
+
+    #[test]
+    fn jumps_to_non_jumpdests_have_no_edge() {
+        let (program, cfg) = build("600356600100");
+        assert!(cfg.edges.is_empty());
+        assert!(cfg.unresolved_blocks().is_empty());
+        assert_eq!(cfg.reachable(&program), vec![true, false]);
+        assert!(Cfg::build(&Program::default()).blocks.is_empty());
+    }
+}
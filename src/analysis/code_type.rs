@@ -1,31 +1,509 @@
-use crate::constant::opcodes;
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use std::collections::BTreeMap;
+use std::fmt;
 
-pub fn seperate_bytecode(bytecode: &String) -> Option<(String, String)> {
-    let mut skip_to_index: i32 = 0;
-    let mut init_code_seperation_counter: i32 = 0;
-    let mut has_code_copy_passed: bool = false;
+// instructions executed over all paths before the initcode interpreter gives up
+const MAX_STEPS: usize = 100_000;
+// times a JUMPDEST may be entered, bounds loops in constructors
+const MAX_VISITS: usize = 16;
 
-    for (index, _) in bytecode.chars().enumerate() {
-        if index as i32 == skip_to_index && index % 2 == 0 {
-            let current_instruction: String = bytecode[index..index + 2].to_string();
+// How the runtime was located inside the creation code
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitHeuristic {
+    // the CODECOPY / RETURN arguments of the deploying path were resolved to constants
+    ConstantPropagation,
+    // legacy solc layout: the runtime starts four instructions after the first CODECOPY
+    FirstCodecopy,
+}
+
+impl SplitHeuristic {
+    pub fn name(&self) -> &'static str {
+        match self {
+            SplitHeuristic::ConstantPropagation => "constant-propagation",
+            SplitHeuristic::FirstCodecopy => "first-codecopy",
+        }
+    }
+}
+
+impl fmt::Display for SplitHeuristic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// A value known to be constant and, when it was pushed as-is, the PUSH it came from;
+// a sum also remembers the PUSHes that were added, so one term can be patched later
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Constant {
+    pub value: usize,
+    pub origin: Option<usize>,
+    pub addends: [Option<usize>; 2],
+}
 
-            let current_instruction_size: i32 =
-                opcodes::get_opcode_size(&current_instruction).unwrap() as i32;
+impl Constant {
+    fn pushed(value: usize, index: usize) -> Self {
+        Constant {
+            value,
+            origin: Some(index),
+            addends: [None, None],
+        }
+    }
+
+    fn computed(value: usize) -> Self {
+        Constant {
+            value,
+            origin: None,
+            addends: [None, None],
+        }
+    }
+}
+
+// The CODECOPY that copies the runtime into memory and the RETURN that deploys it;
+// instruction indices refer to the complete creation program
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DeploySite {
+    pub codecopy: usize,
+    pub ret: usize,
+    pub memory_offset: Constant,
+    pub runtime_offset: Constant,
+    pub runtime_length: Constant,
+    // can exceed the copied length when the constructor appends immutables in memory
+    pub returned_length: Constant,
+}
 
-            skip_to_index = index as i32 + current_instruction_size;
+// Creation code cut into its parts
+#[derive(Debug, Clone)]
+pub struct CreationSplit {
+    pub init_code: Program,
+    pub runtime: Program,
+    // bytes following the runtime, e.g. ABI encoded constructor arguments
+    pub trailing: Vec<u8>,
+    pub heuristic: SplitHeuristic,
+    // resolved deploy instructions, only for SplitHeuristic::ConstantPropagation
+    pub deploy: Option<DeploySite>,
+}
+
+// split creation code into initcode, runtime and trailing data; None when no runtime
+// could be located, i.e. the input presumably is runtime code already
+pub fn split_creation_code(program: &Program) -> Option<CreationSplit> {
+    let bytes: Vec<u8> = program.encode();
+
+    if let Some(deploy) = find_deploy_site(program) {
+        let start: usize = deploy.runtime_offset.value;
+        let end: usize = start
+            .saturating_add(deploy.runtime_length.value)
+            .min(bytes.len());
+        if start > 0 && start < end {
+            return Some(CreationSplit {
+                init_code: Program::decode(&bytes[..start]),
+                runtime: Program::decode(&bytes[start..end]),
+                trailing: bytes[end..].to_vec(),
+                heuristic: SplitHeuristic::ConstantPropagation,
+                deploy: Some(deploy),
+            });
+        }
+    }
+
+    // the runtime starts four instructions after the first CODECOPY (CODECOPY PUSH0 RETURN INVALID)
+    let codecopy_index: usize = program
+        .instructions
+        .iter()
+        .position(|instruction| instruction.opcode == 0x39)?;
+    let runtime_index: usize = codecopy_index + 4;
+    if runtime_index >= program.instructions.len() {
+        return None;
+    }
+
+    let mut init_code: Program = program.clone();
+    let runtime: Program = init_code.split_off(runtime_index);
+    Some(CreationSplit {
+        init_code,
+        runtime,
+        trailing: Vec::new(),
+        heuristic: SplitHeuristic::FirstCodecopy,
+        deploy: None,
+    })
+}
+
+// A CODECOPY executed on some path, with constant arguments
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CodeCopy {
+    pub instruction: usize,
+    pub memory_offset: Constant,
+    pub code_offset: Constant,
+    pub length: Constant,
+}
+
+// Everything the initcode interpreter learned about how the code refers to itself
+#[derive(Debug, Clone, Default)]
+pub struct InitcodeTrace {
+    pub deploy: Option<DeploySite>,
+    // every CODECOPY with constant arguments on the explored paths, deploying one included
+    pub copies: Vec<CodeCopy>,
+    // constants subtracted from CODESIZE, i.e. `CODESIZE - x` where x usually is the
+    // offset of data appended to the creation code (constructor arguments)
+    pub codesize_offsets: Vec<Constant>,
+    // MSTOREs into the deployed copy of the runtime after it was copied, i.e. immutables
+    pub runtime_writes: Vec<MemoryWrite>,
+}
+
+// An MSTORE to a constant address
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub instruction: usize,
+    pub address: Constant,
+}
+
+#[derive(Debug, Clone)]
+struct PathState {
+    index: usize,
+    stack: Vec<Option<Constant>>,
+    // no call has happened yet, so RETURNDATASIZE is still 0
+    returndata_empty: bool,
+    copies: Vec<CodeCopy>,
+    // 32 byte words at constant addresses, anything missing is unknown
+    memory: BTreeMap<usize, Constant>,
+    // MSTOREs on this path, with the number of copies done before each
+    writes: Vec<(usize, MemoryWrite)>,
+}
 
-            // divide bytecode into 0 -> current_instruction + 3(instructions) and current_instruction + 4(instructions) -> end
-            if current_instruction == String::from("39") || has_code_copy_passed {
-                init_code_seperation_counter += 1;
-                has_code_copy_passed = true;
+impl PathState {
+    fn pop(&mut self) -> Option<Constant> {
+        self.stack.pop().flatten()
+    }
+
+    fn push(&mut self, value: Option<Constant>) {
+        self.stack.push(value);
+    }
+
+    // forget the words overlapping [address, address + length), or everything if unknown
+    fn clobber(&mut self, address: Option<Constant>, length: Option<Constant>) {
+        match (address, length) {
+            (Some(address), Some(length)) => {
+                let start: usize = address.value.saturating_sub(31);
+                let end: usize = address.value.saturating_add(length.value);
+                self.memory.retain(|word, _| *word < start || *word >= end);
             }
+            _ => self.memory.clear(),
+        }
+    }
+}
+
+// the CODECOPY / RETURN pair deploying the runtime
+pub fn find_deploy_site(program: &Program) -> Option<DeploySite> {
+    trace_initcode(program).deploy
+}
 
-            if init_code_seperation_counter == 5 {
-                let bytecode_ref = bytecode;
-                let (init_bytecode, runtime_bytecode) = bytecode_ref.split_at(index);
-                return Some((init_bytecode.to_string(), runtime_bytecode.to_string()));
+/*  INITCODE TRACE
+1) execute the initcode abstractly from its first instruction, tracking constant stack values
+    (pushes, CODESIZE, RETURNDATASIZE before any call, ADD/SUB/MUL of constants) and memory
+    words (MSTORE / MLOAD at constant addresses, e.g. the free memory pointer), following
+    every side of a branch whose condition is not constant
+2) remember each CODECOPY whose arguments are constant
+3) at a RETURN with constant arguments, the latest CODECOPY into the returned memory is
+    the one deploying the runtime, and the MSTOREs into the copy after it write immutables;
+    the search ends there
+*/
+pub fn trace_initcode(program: &Program) -> InitcodeTrace {
+    let mut trace: InitcodeTrace = InitcodeTrace::default();
+    let code_length: usize = program.len();
+    let mut visits: Vec<usize> = vec![0; program.instructions.len()];
+    let mut steps: usize = 0;
+    let mut worklist: Vec<PathState> = vec![PathState {
+        index: 0,
+        stack: Vec::new(),
+        returndata_empty: true,
+        copies: Vec::new(),
+        memory: BTreeMap::new(),
+        writes: Vec::new(),
+    }];
+
+    while let Some(mut state) = worklist.pop() {
+        // 1) run the path until it halts, jumps somewhere unknown or forks
+        while let Some(instruction) = program.instructions.get(state.index) {
+            steps += 1;
+            if steps > MAX_STEPS {
+                return trace;
+            }
+            let index: usize = state.index;
+            state.index += 1;
+
+            match instruction.opcode {
+                0x5f..=0x7f => {
+                    let value: Option<Constant> = instruction
+                        .push_value()
+                        .map(|value| Constant::pushed(value, index));
+                    state.push(value);
+                }
+                0x80..=0x8f => {
+                    let depth: usize = (instruction.opcode - 0x80) as usize;
+                    let value: Option<Constant> = state
+                        .stack
+                        .len()
+                        .checked_sub(depth + 1)
+                        .and_then(|slot| state.stack[slot]);
+                    state.push(value);
+                }
+                0x90..=0x9f => {
+                    let depth: usize = (instruction.opcode - 0x8f) as usize;
+                    let top: usize = state.stack.len().wrapping_sub(1);
+                    match state.stack.len().checked_sub(depth + 1) {
+                        Some(slot) => state.stack.swap(slot, top),
+                        None => break,
+                    }
+                }
+                0x01..=0x03 => {
+                    let (a, b) = (state.pop(), state.pop());
+                    let value: Option<usize> = match (instruction.opcode, a, b) {
+                        (0x01, Some(a), Some(b)) => a.value.checked_add(b.value),
+                        (0x02, Some(a), Some(b)) => a.value.checked_mul(b.value),
+                        (0x03, Some(a), Some(b)) => a.value.checked_sub(b.value),
+                        _ => None,
+                    };
+                    if let (0x03, Some(a), Some(b)) = (instruction.opcode, a, b)
+                        && a.value == code_length
+                        && a.origin.is_none()
+                    {
+                        trace.codesize_offsets.push(b);
+                    }
+                    let mut result: Option<Constant> = value.map(Constant::computed);
+                    if let (0x01, Some(sum), Some(a), Some(b)) =
+                        (instruction.opcode, &mut result, a, b)
+                    {
+                        sum.addends = [a.origin, b.origin];
+                    }
+                    state.push(result);
+                }
+                0x38 => state.push(Some(Constant::computed(code_length))),
+                // Huff and hand written constructors use RETURNDATASIZE as a cheap zero
+                0x3d if state.returndata_empty => state.push(Some(Constant::computed(0))),
+                0x51 => {
+                    let address: Option<Constant> = state.pop();
+                    let value: Option<Constant> = address
+                        .and_then(|address| state.memory.get(&address.value))
+                        .map(|value| Constant::computed(value.value));
+                    state.push(value);
+                }
+                0x52 => {
+                    let (address, value) = (state.pop(), state.pop());
+                    state.clobber(address, Some(Constant::computed(32)));
+                    if let Some(address) = address {
+                        if let Some(value) = value {
+                            state.memory.insert(address.value, value);
+                        }
+                        state.writes.push((
+                            state.copies.len(),
+                            MemoryWrite {
+                                instruction: index,
+                                address,
+                            },
+                        ));
+                    }
+                }
+                // 2) CODECOPY(memory offset, code offset, length)
+                0x39 => {
+                    let arguments: [Option<Constant>; 3] = [state.pop(), state.pop(), state.pop()];
+                    state.clobber(arguments[0], arguments[2]);
+                    if let [Some(memory_offset), Some(code_offset), Some(length)] = arguments {
+                        let copy: CodeCopy = CodeCopy {
+                            instruction: index,
+                            memory_offset,
+                            code_offset,
+                            length,
+                        };
+                        if !trace.copies.contains(&copy) {
+                            trace.copies.push(copy.clone());
+                        }
+                        state.copies.push(copy);
+                    }
+                }
+                // 3) RETURN(memory offset, length)
+                0xf3 => {
+                    if let (Some(offset), Some(length)) = (state.pop(), state.pop()) {
+                        let copy: Option<(usize, &CodeCopy)> = state
+                            .copies
+                            .iter()
+                            .enumerate()
+                            .rev()
+                            .find(|(_, copy)| copy.memory_offset.value == offset.value);
+                        if let Some((position, copy)) = copy {
+                            let start: usize = copy.memory_offset.value;
+                            let end: usize = start.saturating_add(copy.length.value);
+                            trace.runtime_writes = state
+                                .writes
+                                .iter()
+                                .filter(|(copies_before, write)| {
+                                    *copies_before > position
+                                        && (start..end).contains(&write.address.value)
+                                })
+                                .map(|(_, write)| write.clone())
+                                .collect();
+                            trace.deploy = Some(DeploySite {
+                                codecopy: copy.instruction,
+                                ret: index,
+                                memory_offset: copy.memory_offset,
+                                runtime_offset: copy.code_offset,
+                                runtime_length: copy.length,
+                                returned_length: length,
+                            });
+                            return trace;
+                        }
+                    }
+                    break;
+                }
+                0x56 => {
+                    match state
+                        .pop()
+                        .and_then(|target| jumpdest_index(program, target.value))
+                    {
+                        Some(target) => state.index = target,
+                        None => break,
+                    }
+                }
+                0x57 => {
+                    let target: Option<usize> = state
+                        .pop()
+                        .and_then(|target| jumpdest_index(program, target.value));
+                    let condition: Option<Constant> = state.pop();
+                    match (condition, target) {
+                        (Some(condition), Some(target)) if condition.value != 0 => {
+                            state.index = target
+                        }
+                        (Some(_), _) => {}
+                        // unknown condition: the taken side is explored later
+                        (None, Some(target)) => {
+                            let mut taken: PathState = state.clone();
+                            taken.index = target;
+                            worklist.push(taken);
+                        }
+                        (None, None) => {}
+                    }
+                }
+                opcode => {
+                    let Some(info) = instruction.info() else {
+                        break;
+                    };
+                    if info.terminating {
+                        break;
+                    }
+                    let mut inputs: Vec<Option<Constant>> = Vec::new();
+                    for _ in 0..info.stack_inputs {
+                        inputs.push(state.pop());
+                    }
+                    for _ in 0..info.stack_outputs {
+                        state.push(None);
+                    }
+                    match opcode {
+                        // CALLDATACOPY, RETURNDATACOPY, MCOPY(destination, source, length)
+                        0x37 | 0x3e | 0x5e => state.clobber(inputs[0], inputs[2]),
+                        // EXTCODECOPY(address, destination, offset, length)
+                        0x3c => state.clobber(inputs[1], inputs[3]),
+                        0x53 => state.clobber(inputs[0], Some(Constant::computed(1))),
+                        0xf0 | 0xf1 | 0xf2 | 0xf4 | 0xf5 | 0xfa => {
+                            state.returndata_empty = false;
+                            state.memory.clear();
+                        }
+                        _ => {}
+                    }
+                }
+            }
+
+            // bound loops: stop a path entering a JUMPDEST too often
+            if let Some(next) = program.instructions.get(state.index)
+                && next.opcode == 0x5b
+            {
+                visits[state.index] += 1;
+                if visits[state.index] > MAX_VISITS {
+                    break;
+                }
             }
         }
     }
-    return None;
+
+    trace
+}
+
+// index of the JUMPDEST at a byte offset
+fn jumpdest_index(program: &Program, offset: usize) -> Option<usize> {
+    let index: usize = program.index_at_offset(offset)?;
+    let instruction: &Instruction = &program.instructions[index];
+    (instruction.opcode == 0x5b).then_some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::metadata::decode_metadata;
+    use crate::helper::hex::decode_hex;
+
+    fn program(hex: &str) -> Program {
+        Program::decode(&decode_hex(hex).unwrap())
+    }
+
+    #[test]
+    fn splits_by_the_deploying_codecopy() {
+        // 0x11 bytes copied from 0x0c and returned; the runtime copies out a data blob
+        let split: CreationSplit = split_creation_code(&program(
+            "601180600c6000396000f3fe6003600c60003960036000f3600f565b00",
+        ))
+        .unwrap();
+        assert_eq!(split.heuristic, SplitHeuristic::ConstantPropagation);
+        assert_eq!(split.init_code.len(), 0x0c);
+        assert_eq!(split.runtime.len(), 0x11);
+        assert!(split.trailing.is_empty());
+
+        let deploy: DeploySite = split.deploy.unwrap();
+        assert_eq!(deploy.runtime_offset.value, 0x0c);
+        assert_eq!(deploy.runtime_length.value, 0x11);
+        // the length is pushed once and DUPed for the RETURN
+        assert_eq!(deploy.runtime_length.origin, Some(0));
+        assert_eq!(deploy.returned_length.origin, Some(0));
+    }
+
+    #[test]
+    fn splits_solc_creation_code() {
+        let hex: String = std::fs::read_to_string("samples/erc20_creation_code.hex").unwrap();
+        let split: CreationSplit = split_creation_code(&program(hex.trim())).unwrap();
+        assert_eq!(split.heuristic, SplitHeuristic::ConstantPropagation);
+        assert!(split.trailing.is_empty());
+        // the runtime ends exactly at the end of its metadata trailer
+        assert!(decode_metadata(&split.runtime.encode()).is_some());
+    }
+
+    #[test]
+    fn keeps_constructor_arguments_as_trailing_data() {
+        let split: CreationSplit =
+            split_creation_code(&program("600380600b6000396000f3600100c0ffee")).unwrap();
+        assert_eq!(split.runtime.encode(), vec![0x60, 0x01, 0x00]);
+        assert_eq!(split.trailing, vec![0xc0, 0xff, 0xee]);
+    }
+
+    #[test]
+    fn falls_back_to_the_first_codecopy() {
+        // the copied length is CALLDATASIZE, so the deploy site cannot be resolved
+        let split: CreationSplit =
+            split_creation_code(&program("3660006000395ff3fe600100")).unwrap();
+        assert_eq!(split.heuristic, SplitHeuristic::FirstCodecopy);
+        assert_eq!(split.runtime.encode(), vec![0x60, 0x01, 0x00]);
+        assert!(split.deploy.is_none());
+    }
+
+    #[test]
+    fn runtime_code_does_not_split() {
+        assert!(split_creation_code(&program("6001600101600055")).is_none());
+    }
+
+    #[test]
+    fn traces_reads_of_appended_data() {
+        // copies 0x20 bytes of constructor arguments from 0x1a, CODESIZE - 0x1a is their length
+        let trace: InitcodeTrace = trace_initcode(&program(
+            "6020601a600039601a38035060038060176000396000f3600100",
+        ));
+        assert_eq!(trace.copies.len(), 2);
+        assert_eq!(trace.copies[0].code_offset.value, 0x1a);
+        assert_eq!(trace.codesize_offsets.len(), 1);
+        assert_eq!(trace.codesize_offsets[0].value, 0x1a);
+        assert_eq!(trace.deploy.unwrap().runtime_offset.value, 0x17);
+    }
 }
@@ -0,0 +1,95 @@
+use crate::analysis::code_type::{CreationSplit, split_creation_code};
+use crate::analysis::metadata::metadata_length;
+use crate::constant::hardfork::Hardfork;
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+
+/*
+    List the instructions of creation code that do not exist on the target hardfork, at
+    their offsets in `program`:
+    1. split it into initcode, runtime and the data behind them (constructor arguments);
+       without a recognisable initcode the whole input is runtime
+    2. scan the initcode, and the runtime up to its metadata trailer
+    the trailer and the appended data are skipped since their bytes are data, not
+    instructions
+*/
+pub fn find_unsupported_opcodes(program: &Program, fork: Hardfork) -> Vec<Instruction> {
+    // 1)
+    let (init_code, runtime): (Program, Program) = match split_creation_code(program) {
+        Some(CreationSplit {
+            init_code, runtime, ..
+        }) => (init_code, runtime),
+        None => (Program::default(), program.clone()),
+    };
+
+    // 2)
+    let runtime_bytes: Vec<u8> = runtime.encode();
+    let code_end: usize = runtime_bytes.len() - metadata_length(&runtime_bytes).unwrap_or(0);
+    let runtime_start: usize = init_code.len();
+    let runtime_code = runtime
+        .instructions
+        .into_iter()
+        .take_while(|instruction| instruction.offset < code_end)
+        .map(|mut instruction| {
+            instruction.offset += runtime_start;
+            instruction
+        });
+
+    init_code
+        .instructions
+        .into_iter()
+        .chain(runtime_code)
+        .filter(|instruction| match instruction.info() {
+            Some(info) => !info.is_available(fork),
+            None => false,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::hex::decode_hex;
+
+    fn unsupported(hex: &str, fork: Hardfork) -> Vec<(usize, String)> {
+        let program: Program = Program::decode(&decode_hex(hex).unwrap());
+        find_unsupported_opcodes(&program, fork)
+            .iter()
+            .map(|instruction| (instruction.offset, instruction.name()))
+            .collect()
+    }
+
+    #[test]
+    fn finds_opcodes_of_later_forks() {
+        // PUSH0 in the initcode and in the runtime `5f5f5d00`, which uses TSTORE too
+        let creation_code: &str = "6004600a5f3960045ff35f5f5d00";
+        assert_eq!(
+            unsupported(creation_code, Hardfork::Paris),
+            vec![
+                (4, String::from("PUSH0")),
+                (8, String::from("PUSH0")),
+                (10, String::from("PUSH0")),
+                (11, String::from("PUSH0")),
+                (12, String::from("TSTORE")),
+            ]
+        );
+        assert_eq!(
+            unsupported(creation_code, Hardfork::Shanghai),
+            vec![(12, String::from("TSTORE"))]
+        );
+        assert!(unsupported(creation_code, Hardfork::Cancun).is_empty());
+    }
+
+    #[test]
+    fn skips_metadata_and_constructor_arguments() {
+        // the runtime `600100` with a trailer holding 0x5f, followed by arguments reading as
+        // TLOAD PUSH0; the initcode is PUSH1-only
+        let creation_code: &str = "6009600c60003960096000f3600100a1615ff500045c5f";
+        assert!(unsupported(creation_code, Hardfork::Paris).is_empty());
+        // as a runtime on its own, every byte is code
+        assert_eq!(
+            unsupported("5c5f", Hardfork::Paris),
+            vec![(0, String::from("TLOAD")), (1, String::from("PUSH0"))]
+        );
+    }
+}
@@ -0,0 +1,371 @@
+use crate::analysis::code_type::{Constant, DeploySite, InitcodeTrace, MemoryWrite};
+use crate::error::ObfuscatorError;
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use serde_json::Value;
+
+// A placeholder in the runtime that the constructor overwrites with an immutable's value,
+// as listed in solc's `immutableReferences` (byte offset of the PUSH data and its length)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImmutableReference {
+    pub id: String,
+    pub start: usize,
+    pub length: usize,
+}
+
+// A reference whose placeholder PUSH carries a label, so it can be found after code moved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelledReference {
+    pub reference: ImmutableReference,
+    pub label: usize,
+}
+
+// parse solc's `immutableReferences` map, `{"<ast id>": [{"start": N, "length": 32}, ...]}`;
+// the `evm.deployedBytecode` object holding it is accepted as well
+pub fn parse_immutable_references(json: &str) -> Result<Vec<ImmutableReference>, ObfuscatorError> {
+    let invalid = |reason: String| ObfuscatorError::InvalidImmutableReferences(reason);
+    let value: Value = serde_json::from_str(json).map_err(|error| invalid(error.to_string()))?;
+    let map: &Value = value.get("immutableReferences").unwrap_or(&value);
+    let Some(map) = map.as_object() else {
+        return Err(invalid(String::from("expected an object of references")));
+    };
+
+    let mut references: Vec<ImmutableReference> = Vec::new();
+    for (id, entries) in map {
+        let Some(entries) = entries.as_array() else {
+            return Err(invalid(format!("references of {} are not a list", id)));
+        };
+        for entry in entries {
+            let field = |name: &str| -> Result<usize, ObfuscatorError> {
+                entry
+                    .get(name)
+                    .and_then(Value::as_u64)
+                    .and_then(|number| usize::try_from(number).ok())
+                    .ok_or_else(|| invalid(format!("a reference of {} has no {}", id, name)))
+            };
+            references.push(ImmutableReference {
+                id: id.clone(),
+                start: field("start")?,
+                length: field("length")?,
+            });
+        }
+    }
+    references.sort_by_key(|reference| reference.start);
+    Ok(references)
+}
+
+// without a map, every word the constructor writes into the deployed copy that lands on the
+// data of a PUSH32 is taken to be an immutable; the id is the original offset
+pub fn infer_immutable_references(
+    runtime: &Program,
+    deploy: &DeploySite,
+    writes: &[MemoryWrite],
+) -> Vec<ImmutableReference> {
+    let mut references: Vec<ImmutableReference> = writes
+        .iter()
+        .filter_map(|write| write.address.value.checked_sub(deploy.memory_offset.value))
+        .filter(|start| placeholder_index(runtime, *start, 32).is_some())
+        .map(|start| ImmutableReference {
+            id: start.to_string(),
+            start,
+            length: 32,
+        })
+        .collect();
+    references.sort_by_key(|reference| reference.start);
+    references.dedup();
+    references
+}
+
+// label the PUSH of every reference so its data can be found once the runtime is assembled
+pub fn label_immutable_references(
+    runtime: &mut Program,
+    references: &[ImmutableReference],
+) -> Result<Vec<LabelledReference>, ObfuscatorError> {
+    let mut labelled: Vec<LabelledReference> = Vec::new();
+    for reference in references {
+        let index: usize = placeholder_index(runtime, reference.start, reference.length)
+            .ok_or_else(|| {
+                ObfuscatorError::InvalidImmutableReferences(format!(
+                    "no PUSH{} data starts at {:#x} for {}",
+                    reference.length, reference.start, reference.id
+                ))
+            })?;
+        labelled.push(LabelledReference {
+            reference: reference.clone(),
+            label: runtime.label_at(index),
+        });
+    }
+    Ok(labelled)
+}
+
+/*  IMMUTABLE WRITES
+1) the new start of a reference is right behind the opcode of its labelled PUSH
+2) each MSTORE into the deployed copy at the old start is moved to the new one: an address
+    pushed as-is is rewritten whole, a sum (copy base + start) has its start term rewritten
+3) a reference that moved without any write found for it would leave the immutable unset
+returns how many references moved
+*/
+pub fn patch_immutable_writes(
+    init_code: &mut Program,
+    runtime: &Program,
+    deploy: &DeploySite,
+    trace: &InitcodeTrace,
+    references: &[LabelledReference],
+) -> Result<usize, ObfuscatorError> {
+    let base: usize = deploy.memory_offset.value;
+    let mut moved: usize = 0;
+
+    for labelled in references {
+        // 1) new start
+        let old_start: usize = labelled.reference.start;
+        let new_start: usize = relocated_start(runtime, labelled)?;
+        if new_start == old_start {
+            continue;
+        }
+        moved += 1;
+
+        // 2) move the writes
+        // a write executed more than once is only patched once
+        let mut addresses: Vec<Constant> = Vec::new();
+        for write in &trace.runtime_writes {
+            if write.address.value == base + old_start && !addresses.contains(&write.address) {
+                addresses.push(write.address);
+            }
+        }
+        for address in &addresses {
+            patch_write_address(init_code, *address, old_start, new_start)?;
+        }
+
+        // 3) nothing to move
+        if addresses.is_empty() {
+            return Err(ObfuscatorError::UnexpectedInitcode(format!(
+                "no constructor write to immutable {} at {:#x} was found",
+                labelled.reference.id, old_start
+            )));
+        }
+    }
+    Ok(moved)
+}
+
+// whether the constructor writes an immutable through a PUSH1 (directly or as the term
+// patched by patch_write_address), which cannot take an offset past 0xff; wider ones hold
+// any offset of a deployable runtime
+pub fn narrow_immutable_write(
+    init_code: &Program,
+    deploy: &DeploySite,
+    trace: &InitcodeTrace,
+) -> bool {
+    let base: usize = deploy.memory_offset.value;
+    trace.runtime_writes.iter().any(|write| {
+        let address: Constant = write.address;
+        let patched: Option<usize> = address.origin.or_else(|| {
+            address.addends.iter().flatten().copied().find(|index| {
+                init_code.instructions[*index].push_value() == address.value.checked_sub(base)
+            })
+        });
+        patched.is_none_or(|index| init_code.instructions[index].push_width() < 2)
+    })
+}
+
+// the references at their offsets in the assembled runtime
+pub fn relocate_immutable_references(
+    runtime: &Program,
+    references: &[LabelledReference],
+) -> Result<Vec<ImmutableReference>, ObfuscatorError> {
+    references
+        .iter()
+        .map(|labelled| {
+            Ok(ImmutableReference {
+                start: relocated_start(runtime, labelled)?,
+                ..labelled.reference.clone()
+            })
+        })
+        .collect()
+}
+
+fn relocated_start(
+    runtime: &Program,
+    labelled: &LabelledReference,
+) -> Result<usize, ObfuscatorError> {
+    runtime
+        .label_offset(labelled.label)
+        .map(|offset| offset + 1)
+        .ok_or(ObfuscatorError::UndefinedLabel(labelled.label))
+}
+
+fn patch_write_address(
+    init_code: &mut Program,
+    address: Constant,
+    old_start: usize,
+    new_start: usize,
+) -> Result<(), ObfuscatorError> {
+    let new_address: usize = address.value - old_start + new_start;
+    if let Some(index) = address.origin {
+        return init_code.instructions[index].set_push_value(new_address);
+    }
+    let term: Option<usize> = address
+        .addends
+        .iter()
+        .flatten()
+        .copied()
+        .find(|index| init_code.instructions[*index].push_value() == Some(old_start));
+    match term {
+        Some(index) => init_code.instructions[index].set_push_value(new_start),
+        None => Err(ObfuscatorError::UnexpectedInitcode(format!(
+            "the immutable write to {:#x} is computed and cannot be moved to {:#x}",
+            address.value, new_address
+        ))),
+    }
+}
+
+// index of the PUSH<length> whose data starts at byte `start`
+fn placeholder_index(runtime: &Program, start: usize, length: usize) -> Option<usize> {
+    let index: usize = runtime.index_at_offset(start.checked_sub(1)?)?;
+    let instruction: &Instruction = &runtime.instructions[index];
+    (instruction.is_push() && instruction.push_width() == length && length > 0).then_some(index)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::code_type::{CreationSplit, split_creation_code, trace_initcode};
+    use crate::helper::hex::decode_hex;
+
+    // copies the 0x25 byte runtime from 0x10 to memory 0, writes 0x2a over the PUSH32 data
+    // at 3 and deploys it; the runtime stores that word
+    fn creation_code() -> String {
+        format!(
+            "6025806010600039602a6003526000f360007f{}5500",
+            "00".repeat(32)
+        )
+    }
+
+    fn reference(id: &str, start: usize) -> ImmutableReference {
+        ImmutableReference {
+            id: String::from(id),
+            start,
+            length: 32,
+        }
+    }
+
+    #[test]
+    fn parses_both_forms_of_the_map() {
+        let map: &str = r#"{"12": [{"start": 80, "length": 32}, {"start": 3, "length": 32}]}"#;
+        let expected: Vec<ImmutableReference> = vec![reference("12", 3), reference("12", 80)];
+        assert_eq!(parse_immutable_references(map).unwrap(), expected);
+        let deployed: String = format!(r#"{{"object": "", "immutableReferences": {}}}"#, map);
+        assert_eq!(parse_immutable_references(&deployed).unwrap(), expected);
+        assert!(parse_immutable_references("{}").unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_malformed_maps() {
+        for json in [
+            "[]",
+            r#"{"12": {"start": 3}}"#,
+            r#"{"12": [{"start": 3}]}"#,
+            r#"{"12": [{"start": -1, "length": 32}]}"#,
+            "{",
+        ] {
+            assert!(
+                matches!(
+                    parse_immutable_references(json),
+                    Err(ObfuscatorError::InvalidImmutableReferences(_))
+                ),
+                "{}",
+                json
+            );
+        }
+    }
+
+    #[test]
+    fn infers_writes_over_push32_placeholders() {
+        let program: Program = Program::decode(&decode_hex(&creation_code()).unwrap());
+        let trace: InitcodeTrace = trace_initcode(&program);
+        let split: CreationSplit = split_creation_code(&program).unwrap();
+        let deploy: DeploySite = split.deploy.unwrap();
+        assert_eq!(
+            infer_immutable_references(&split.runtime, &deploy, &trace.runtime_writes),
+            vec![reference("3", 3)]
+        );
+        assert!(narrow_immutable_write(&split.init_code, &deploy, &trace));
+    }
+
+    #[test]
+    fn placeholders_have_to_match_a_push() {
+        let program: Program = Program::decode(&decode_hex(&creation_code()).unwrap());
+        let mut runtime: Program = split_creation_code(&program).unwrap().runtime;
+        for (start, length) in [(4, 32), (3, 20), (0, 32)] {
+            let references: Vec<ImmutableReference> = vec![ImmutableReference {
+                length,
+                ..reference("7", start)
+            }];
+            assert!(matches!(
+                label_immutable_references(&mut runtime, &references),
+                Err(ObfuscatorError::InvalidImmutableReferences(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn writes_follow_their_placeholder() {
+        let program: Program = Program::decode(&decode_hex(&creation_code()).unwrap());
+        let trace: InitcodeTrace = trace_initcode(&program);
+        let CreationSplit {
+            mut init_code,
+            mut runtime,
+            deploy,
+            ..
+        } = split_creation_code(&program).unwrap();
+        let deploy: DeploySite = deploy.unwrap();
+        let references: Vec<LabelledReference> =
+            label_immutable_references(&mut runtime, &[reference("12", 3)]).unwrap();
+
+        // unmoved, nothing is patched
+        assert_eq!(
+            patch_immutable_writes(&mut init_code, &runtime, &deploy, &trace, &references),
+            Ok(0)
+        );
+
+        // two bytes in front of the placeholder move it and its write to 5
+        runtime.instructions.insert(0, Instruction::new(0x5b));
+        runtime.instructions.insert(0, Instruction::new(0x5b));
+        runtime.assemble().unwrap();
+        assert_eq!(
+            relocate_immutable_references(&runtime, &references).unwrap(),
+            vec![reference("12", 5)]
+        );
+        assert_eq!(
+            patch_immutable_writes(&mut init_code, &runtime, &deploy, &trace, &references),
+            Ok(1)
+        );
+        assert_eq!(init_code.instructions[6].push_value(), Some(5));
+    }
+
+    #[test]
+    fn moved_placeholders_need_a_write() {
+        let program: Program = Program::decode(&decode_hex(&creation_code()).unwrap());
+        let CreationSplit {
+            mut init_code,
+            mut runtime,
+            deploy,
+            ..
+        } = split_creation_code(&program).unwrap();
+        let references: Vec<LabelledReference> =
+            label_immutable_references(&mut runtime, &[reference("12", 3)]).unwrap();
+        runtime.instructions.insert(0, Instruction::new(0x5b));
+        runtime.assemble().unwrap();
+        // a trace without the constructor's MSTORE
+        let trace: InitcodeTrace = InitcodeTrace::default();
+        assert!(matches!(
+            patch_immutable_writes(
+                &mut init_code,
+                &runtime,
+                &deploy.unwrap(),
+                &trace,
+                &references
+            ),
+            Err(ObfuscatorError::UnexpectedInitcode(_))
+        ));
+    }
+}
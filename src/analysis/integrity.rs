@@ -0,0 +1,134 @@
+use crate::analysis::code_type::{CreationSplit, split_creation_code};
+use crate::analysis::fork_support::find_unsupported_opcodes;
+use crate::analysis::jump_seq::find_jump_seq;
+use crate::analysis::push_codecopy_seq::get_runtime_length;
+use crate::analysis::stack::analyze_stack;
+use crate::constant::hardfork::Hardfork;
+use crate::ir::program::Program;
+use std::fmt;
+
+// Structural problems that make creation code undeployable or broken at runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IntegrityIssue {
+    // no CODECOPY based initcode could be separated from the runtime
+    MissingInitcode,
+    // the initcode has no PUSH feeding the runtime length into CODECOPY
+    MissingRuntimeLength,
+    // the initcode copies a different number of bytes than the runtime has
+    RuntimeLengthMismatch {
+        declared: usize,
+        actual: usize,
+    },
+    // the initcode returns fewer bytes than it copies, cutting the runtime short
+    ReturnedLengthMismatch {
+        returned: usize,
+        copied: usize,
+    },
+    // a PUSH-JUMP in the runtime does not land on a JUMPDEST
+    InvalidJumpTarget {
+        offset: usize,
+        target: usize,
+    },
+    // an instruction that does not exist on the target fork
+    UnsupportedOpcode {
+        offset: usize,
+        mnemonic: String,
+        fork: Hardfork,
+    },
+}
+
+impl fmt::Display for IntegrityIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IntegrityIssue::MissingInitcode => write!(f, "initcode could not be separated"),
+            IntegrityIssue::MissingRuntimeLength => {
+                write!(f, "initcode does not push the runtime length")
+            }
+            IntegrityIssue::RuntimeLengthMismatch { declared, actual } => write!(
+                f,
+                "initcode copies {} runtime bytes but the runtime has {}",
+                declared, actual
+            ),
+            IntegrityIssue::ReturnedLengthMismatch { returned, copied } => write!(
+                f,
+                "initcode returns {} bytes but copies a runtime of {}",
+                returned, copied
+            ),
+            IntegrityIssue::InvalidJumpTarget { offset, target } => write!(
+                f,
+                "jump at runtime offset {:#x} targets {:#x} which is not a JUMPDEST",
+                offset, target
+            ),
+            IntegrityIssue::UnsupportedOpcode {
+                offset,
+                mnemonic,
+                fork,
+            } => write!(
+                f,
+                "{} at offset {:#x} is not available on {}",
+                mnemonic, offset, fork
+            ),
+        }
+    }
+}
+
+// check creation code for the invariants the obfuscator has to preserve
+pub fn check_creation_code(creation_program: &Program, fork: Hardfork) -> Vec<IntegrityIssue> {
+    let mut issues: Vec<IntegrityIssue> = Vec::new();
+
+    for instruction in find_unsupported_opcodes(creation_program, fork) {
+        issues.push(IntegrityIssue::UnsupportedOpcode {
+            offset: instruction.offset,
+            mnemonic: instruction.name(),
+            fork,
+        });
+    }
+
+    let split: CreationSplit = match split_creation_code(creation_program) {
+        Some(split) => split,
+        None => {
+            issues.push(IntegrityIssue::MissingInitcode);
+            return issues;
+        }
+    };
+
+    let runtime_bytecode: Program = split.runtime;
+    // the copy may run past the end of the code, in which case the runtime is cut short
+    let declared_length: Option<usize> = match &split.deploy {
+        Some(deploy) => Some(deploy.runtime_length.value),
+        None => get_runtime_length(&split.init_code),
+    };
+    match declared_length {
+        Some(declared) if declared != runtime_bytecode.len() => {
+            issues.push(IntegrityIssue::RuntimeLengthMismatch {
+                declared,
+                actual: runtime_bytecode.len(),
+            })
+        }
+        Some(_) => {}
+        None => issues.push(IntegrityIssue::MissingRuntimeLength),
+    }
+
+    if let Some(deploy) = &split.deploy
+        && deploy.returned_length.value < deploy.runtime_length.value
+    {
+        issues.push(IntegrityIssue::ReturnedLengthMismatch {
+            returned: deploy.returned_length.value,
+            copied: deploy.runtime_length.value,
+        });
+    }
+
+    for push_jump in find_jump_seq(&runtime_bytecode, &analyze_stack(&runtime_bytecode)) {
+        let lands_on_jumpdest: bool = runtime_bytecode
+            .index_at_offset(push_jump.value)
+            .is_some_and(|index| runtime_bytecode.instructions[index].opcode == 0x5b);
+        if !lands_on_jumpdest {
+            issues.push(IntegrityIssue::InvalidJumpTarget {
+                offset: push_jump.byte_offset,
+                target: push_jump.value,
+            });
+        }
+    }
+
+    issues
+}
@@ -1,87 +1,94 @@
-use crate::constant::opcodes;
+use crate::analysis::stack::StackAnalysis;
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
 
 #[derive(Debug)]
 pub struct PushPositions {
-    pub byteoffset_decimal: i32,
-    pub byteoffset_hex: String,
-    pub instruction_position: i32,
-    pub instruction_bits: String,
-    pub value_decimal: i32,
-    pub value_hex: String,
-    pub next_instruction_code: String,
+    pub instruction_index: usize,
+    pub byte_offset: usize,
+    pub value: usize,
+    pub next_opcode: u8,
 }
 
-pub fn find_jump_seq(bytecode: &String) -> Vec<PushPositions> {
-    let mut skip_to_index: i32 = 0;
-    let mut instruction_position: i32 = 1;
-
-    let mut jump_sequences: Vec<PushPositions> = Vec::new();
-
-    for (index, _) in bytecode.chars().enumerate() {
-        if index as i32 == skip_to_index && index % 2 == 0 {
-            let current_instruction: String = bytecode[index..index + 2].to_string();
+// PUSH-JUMP sequences (unconditional jumps to a constant destination) in reachable code
+pub fn find_jump_seq(program: &Program, analysis: &StackAnalysis) -> Vec<PushPositions> {
+    find_push_seq(program, analysis, 0x56)
+}
 
-            let current_instruction_size: i32 =
-                opcodes::get_opcode_size(&current_instruction).unwrap() as i32;
+// PUSH-JUMPI sequences (conditional branches to a constant destination) in reachable code
+pub fn find_jumpi_seq(program: &Program, analysis: &StackAnalysis) -> Vec<PushPositions> {
+    find_push_seq(program, analysis, 0x57)
+}
 
-            skip_to_index = index as i32 + current_instruction_size;
+// code pointers pushed away from the jump that consumes them (internal function return
+// addresses, function pointers), as found by the abstract stack interpreter
+pub fn find_code_pointer_seq(program: &Program, analysis: &StackAnalysis) -> Vec<PushPositions> {
+    let mut pointers: Vec<PushPositions> = Vec::new();
 
-            let current_params: String = bytecode[index + 2..skip_to_index as usize].to_string();
+    for index in analysis.code_pointers.iter().copied() {
+        let push: &Instruction = &program.instructions[index];
+        let next_opcode: u8 = match program.instructions.get(index + 1) {
+            Some(next) => next.opcode,
+            None => continue,
+        };
+        // adjacent PUSH-JUMP(I) pairs are covered by find_jump_seq / find_jumpi_seq
+        if matches!(next_opcode, 0x56 | 0x57) || push.push_width() == 0 {
+            continue;
+        }
+        if let Some(value) = push.push_value() {
+            pointers.push(PushPositions {
+                instruction_index: index,
+                byte_offset: push.offset,
+                value,
+                next_opcode,
+            });
+        }
+    }
 
-            let mut next_instruction: String = String::from("00");
-            if skip_to_index as usize + 2 <= bytecode.len() {
-                next_instruction =
-                    bytecode[skip_to_index as usize..skip_to_index as usize + 2].to_string();
-            }
+    pointers
+}
 
-            let push_jmp_seq: Option<PushPositions> = check_push_jump_seq(
-                current_instruction,
-                &next_instruction,
-                &instruction_position,
-                &index,
-                &current_params,
-            );
+// bytes no execution reaches may be data (a blob copied out with CODECOPY, the creation
+// code of a child contract) that only decodes as PUSH-JUMP by accident, so they are skipped
+fn find_push_seq(
+    program: &Program,
+    analysis: &StackAnalysis,
+    jump_opcode: u8,
+) -> Vec<PushPositions> {
+    let mut jump_sequences: Vec<PushPositions> = Vec::new();
 
-            match push_jmp_seq {
-                Some(val) => jump_sequences.push(val),
-                None => {}
-            }
-            instruction_position += 1;
+    for (index, pair) in program.instructions.windows(2).enumerate() {
+        let reachable: bool = analysis
+            .cfg
+            .block_of_instruction(index)
+            .is_some_and(|block| analysis.entry_states[block].is_some());
+        if !reachable {
+            continue;
+        }
+        if let Some(push_jmp_seq) = check_push_jump_seq(&pair[0], &pair[1], index, jump_opcode) {
+            jump_sequences.push(push_jmp_seq);
         }
     }
 
-    return jump_sequences;
+    jump_sequences
 }
 
 fn check_push_jump_seq(
-    current_instruction: String,
-    next_instruction: &String,
-    ins_position: &i32,
-    push_index: &usize,
-    push_value: &String,
+    current_instruction: &Instruction,
+    next_instruction: &Instruction,
+    ins_index: usize,
+    jump_opcode: u8,
 ) -> Option<PushPositions> {
-    if next_instruction == &String::from("56")
-        && (current_instruction.starts_with("6") || current_instruction.starts_with('7'))
-    {
-        let (value_decimal, value_hex) = if push_value == "" {
-            (0, "00".to_string())
-        } else {
-            (
-                i32::from_str_radix(push_value, 16).unwrap(),
-                push_value.clone(),
-            )
-        };
-        let push_seq: PushPositions = PushPositions {
-            byteoffset_decimal: *push_index as i32,
-            byteoffset_hex: format!("{:x}", push_index),
-            instruction_position: ins_position.clone(),
-            instruction_bits: current_instruction.clone(),
-            value_decimal,
-            value_hex,
-            next_instruction_code: current_instruction,
-        };
-        Some(push_seq)
-    } else {
-        None
+    // PUSH1..PUSH32 directly followed by the jump opcode
+    if next_instruction.opcode != jump_opcode || current_instruction.push_width() == 0 {
+        return None;
     }
+
+    let value: usize = current_instruction.push_value()?;
+    Some(PushPositions {
+        instruction_index: ins_index,
+        byte_offset: current_instruction.offset,
+        value,
+        next_opcode: next_instruction.opcode,
+    })
 }
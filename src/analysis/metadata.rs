@@ -0,0 +1,309 @@
+// Solidity/Vyper append a CBOR encoded metadata map to the runtime code, followed
+// by its length as a big-endian u16. Returns the length of the whole trailer
+// (map + two length bytes) when the tail of `bytes` looks like such a trailer.
+pub fn metadata_length(bytes: &[u8]) -> Option<usize> {
+    if bytes.len() < 2 {
+        return None;
+    }
+    let cbor_len: usize =
+        u16::from_be_bytes([bytes[bytes.len() - 2], bytes[bytes.len() - 1]]) as usize;
+    let trailer_len: usize = cbor_len + 2;
+    if cbor_len == 0 || trailer_len > bytes.len() {
+        return None;
+    }
+
+    // the CBOR payload has to start with a small map header (0xa1..=0xb7)
+    let map_header: u8 = bytes[bytes.len() - trailer_len];
+    if (0xa1..=0xb7).contains(&map_header) {
+        Some(trailer_len)
+    } else {
+        None
+    }
+}
+
+// A value of the metadata map; solc only emits byte strings, text and booleans
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MetadataValue {
+    Bytes(Vec<u8>),
+    Text(String),
+    Bool(bool),
+    Unsigned(u64),
+}
+
+// Decoded metadata trailer, entries in their encoded order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Metadata {
+    pub entries: Vec<(String, MetadataValue)>,
+}
+
+impl Metadata {
+    pub fn get(&self, key: &str) -> Option<&MetadataValue> {
+        self.entries
+            .iter()
+            .find(|(entry_key, _)| entry_key == key)
+            .map(|(_, value)| value)
+    }
+
+    // IPFS hash of the source metadata JSON, as the usual base58 CIDv0 ("Qm...")
+    pub fn ipfs(&self) -> Option<String> {
+        match self.get("ipfs") {
+            Some(MetadataValue::Bytes(hash)) => Some(encode_base58(hash)),
+            _ => None,
+        }
+    }
+
+    // Swarm hash of older compilers (bzzr0 before 0.5.9, bzzr1 after)
+    pub fn swarm(&self) -> Option<&[u8]> {
+        ["bzzr1", "bzzr0"]
+            .iter()
+            .find_map(|key| match self.get(key) {
+                Some(MetadataValue::Bytes(hash)) => Some(hash.as_slice()),
+                _ => None,
+            })
+    }
+
+    // compiler version, three bytes for releases and a full string for nightlies
+    pub fn solc_version(&self) -> Option<String> {
+        match self.get("solc") {
+            Some(MetadataValue::Bytes(version)) if version.len() == 3 => {
+                Some(format!("{}.{}.{}", version[0], version[1], version[2]))
+            }
+            Some(MetadataValue::Text(version)) => Some(version.clone()),
+            _ => None,
+        }
+    }
+
+    pub fn is_experimental(&self) -> bool {
+        matches!(self.get("experimental"), Some(MetadataValue::Bool(true)))
+    }
+
+    // the same metadata without the content hashes that point at the contract's sources
+    pub fn without_source_hashes(&self) -> Metadata {
+        Metadata {
+            entries: self
+                .entries
+                .iter()
+                .filter(|(key, _)| !matches!(key.as_str(), "ipfs" | "bzzr0" | "bzzr1"))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    // encode as a trailer: CBOR map followed by its big-endian u16 length
+    pub fn encode(&self) -> Vec<u8> {
+        let mut cbor: Vec<u8> = Vec::new();
+        encode_head(5, self.entries.len() as u64, &mut cbor);
+        for (key, value) in &self.entries {
+            encode_head(3, key.len() as u64, &mut cbor);
+            cbor.extend_from_slice(key.as_bytes());
+            match value {
+                MetadataValue::Bytes(bytes) => {
+                    encode_head(2, bytes.len() as u64, &mut cbor);
+                    cbor.extend_from_slice(bytes);
+                }
+                MetadataValue::Text(text) => {
+                    encode_head(3, text.len() as u64, &mut cbor);
+                    cbor.extend_from_slice(text.as_bytes());
+                }
+                MetadataValue::Bool(flag) => cbor.push(if *flag { 0xf5 } else { 0xf4 }),
+                MetadataValue::Unsigned(number) => encode_head(0, *number, &mut cbor),
+            }
+        }
+        let length: [u8; 2] = (cbor.len() as u16).to_be_bytes();
+        cbor.extend_from_slice(&length);
+        cbor
+    }
+}
+
+// decode the metadata trailer at the end of `bytes`, None when there is none or it is
+// not a CBOR map of the shape compilers emit
+pub fn decode_metadata(bytes: &[u8]) -> Option<Metadata> {
+    let trailer_len: usize = metadata_length(bytes)?;
+    let cbor: &[u8] = &bytes[bytes.len() - trailer_len..bytes.len() - 2];
+    let mut position: usize = 0;
+
+    let (major, entries) = decode_head(cbor, &mut position)?;
+    if major != 5 {
+        return None;
+    }
+    let mut metadata: Metadata = Metadata {
+        entries: Vec::new(),
+    };
+    for _ in 0..entries {
+        let key: String = match decode_value(cbor, &mut position)? {
+            MetadataValue::Text(key) => key,
+            _ => return None,
+        };
+        let value: MetadataValue = decode_value(cbor, &mut position)?;
+        metadata.entries.push((key, value));
+    }
+
+    // the map has to span the whole declared length
+    (position == cbor.len()).then_some(metadata)
+}
+
+// major type and argument of the CBOR data item at `position`
+fn decode_head(cbor: &[u8], position: &mut usize) -> Option<(u8, u64)> {
+    let initial: u8 = *cbor.get(*position)?;
+    *position += 1;
+    let major: u8 = initial >> 5;
+    let argument_len: usize = match initial & 0x1f {
+        info if info < 24 => return Some((major, info as u64)),
+        24 => 1,
+        25 => 2,
+        26 => 4,
+        27 => 8,
+        _ => return None,
+    };
+    let argument: &[u8] = cbor.get(*position..*position + argument_len)?;
+    *position += argument_len;
+    Some((
+        major,
+        argument
+            .iter()
+            .fold(0, |value, byte| (value << 8) | *byte as u64),
+    ))
+}
+
+fn decode_value(cbor: &[u8], position: &mut usize) -> Option<MetadataValue> {
+    let start: usize = *position;
+    let (major, argument) = decode_head(cbor, position)?;
+    match major {
+        0 => Some(MetadataValue::Unsigned(argument)),
+        2 | 3 => {
+            let end: usize = position.checked_add(usize::try_from(argument).ok()?)?;
+            let data: Vec<u8> = cbor.get(*position..end)?.to_vec();
+            *position = end;
+            if major == 2 {
+                Some(MetadataValue::Bytes(data))
+            } else {
+                String::from_utf8(data).ok().map(MetadataValue::Text)
+            }
+        }
+        // simple values false / true
+        7 => match cbor[start] {
+            0xf4 => Some(MetadataValue::Bool(false)),
+            0xf5 => Some(MetadataValue::Bool(true)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn encode_head(major: u8, argument: u64, cbor: &mut Vec<u8>) {
+    let tag: u8 = major << 5;
+    if argument < 24 {
+        cbor.push(tag | argument as u8);
+    } else if argument <= 0xff {
+        cbor.extend_from_slice(&[tag | 24, argument as u8]);
+    } else if argument <= 0xffff {
+        cbor.push(tag | 25);
+        cbor.extend_from_slice(&(argument as u16).to_be_bytes());
+    } else if argument <= 0xffff_ffff {
+        cbor.push(tag | 26);
+        cbor.extend_from_slice(&(argument as u32).to_be_bytes());
+    } else {
+        cbor.push(tag | 27);
+        cbor.extend_from_slice(&argument.to_be_bytes());
+    }
+}
+
+fn encode_base58(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 58] = b"123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz";
+
+    // repeated division of the big-endian number by 58, least significant digit first
+    let mut digits: Vec<u8> = Vec::new();
+    for byte in bytes {
+        let mut carry: u32 = *byte as u32;
+        for digit in &mut digits {
+            carry += (*digit as u32) << 8;
+            *digit = (carry % 58) as u8;
+            carry /= 58;
+        }
+        while carry > 0 {
+            digits.push((carry % 58) as u8);
+            carry /= 58;
+        }
+    }
+
+    // every leading zero byte is written as a '1'
+    let zeros: usize = bytes.iter().take_while(|byte| **byte == 0).count();
+    let mut encoded: String = "1".repeat(zeros);
+    encoded.extend(
+        digits
+            .iter()
+            .rev()
+            .map(|digit| ALPHABET[*digit as usize] as char),
+    );
+    encoded
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::hex::decode_hex;
+
+    // the trailer solc 0.8.27 appended to the runtime of samples/erc20_creation_code.hex
+    const TRAILER: &str = "a2646970667358221220f8bef253fb06780b7b0a4bd8c7f18a07ca1216c01aa67c1c073660c31499829564736f6c634300081b0033";
+
+    #[test]
+    fn decodes_solc_trailer() {
+        let mut code: Vec<u8> = vec![0x60, 0x80, 0x60, 0x40, 0x52, 0xfe];
+        code.extend(decode_hex(TRAILER).unwrap());
+        assert_eq!(metadata_length(&code), Some(TRAILER.len() / 2));
+
+        let metadata: Metadata = decode_metadata(&code).unwrap();
+        assert_eq!(metadata.solc_version().as_deref(), Some("0.8.27"));
+        assert_eq!(
+            metadata.ipfs().as_deref(),
+            Some("Qmf5eh8ZJAvgTRPdsmFB5cKVVUo3QCkoWmMyWyjYtp1Wui")
+        );
+        assert_eq!(metadata.swarm(), None);
+        assert!(!metadata.is_experimental());
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let trailer: Vec<u8> = decode_hex(TRAILER).unwrap();
+        assert_eq!(decode_metadata(&trailer).unwrap().encode(), trailer);
+
+        let metadata: Metadata = Metadata {
+            entries: vec![
+                (String::from("bzzr1"), MetadataValue::Bytes(vec![0xab; 32])),
+                (String::from("experimental"), MetadataValue::Bool(true)),
+                (
+                    String::from("solc"),
+                    MetadataValue::Text(String::from("0.8.28-nightly")),
+                ),
+                (String::from("version"), MetadataValue::Unsigned(0x1_0000)),
+            ],
+        };
+        let encoded: Vec<u8> = metadata.encode();
+        assert_eq!(decode_metadata(&encoded), Some(metadata));
+    }
+
+    #[test]
+    fn strips_source_hashes() {
+        let trailer: Vec<u8> = decode_hex(TRAILER).unwrap();
+        let stripped: Metadata = decode_metadata(&trailer).unwrap().without_source_hashes();
+        assert_eq!(stripped.ipfs(), None);
+        assert_eq!(stripped.solc_version().as_deref(), Some("0.8.27"));
+        assert_eq!(
+            decode_hex("a164736f6c634300081b000a").unwrap(),
+            stripped.encode()
+        );
+    }
+
+    #[test]
+    fn rejects_other_tails() {
+        assert_eq!(metadata_length(&[0x00]), None);
+        // a length pointing past the start, and a payload that is not a map
+        assert_eq!(metadata_length(&[0xa1, 0x00, 0x10]), None);
+        assert_eq!(metadata_length(&[0x60, 0x00, 0x00, 0x02]), None);
+        // a map header whose entries do not fill the declared length
+        assert_eq!(
+            decode_metadata(&[0xa1, 0x61, 0x61, 0xf5, 0x00, 0x00, 0x05]),
+            None
+        );
+    }
+}
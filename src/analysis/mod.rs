@@ -1,3 +1,10 @@
+pub mod cfg;
 pub mod code_type;
+pub mod fork_support;
+pub mod immutables;
+pub mod integrity;
 pub mod jump_seq;
+pub mod metadata;
 pub mod push_codecopy_seq;
+pub mod source_map;
+pub mod stack;
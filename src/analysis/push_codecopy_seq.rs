@@ -1,60 +1,260 @@
-use crate::constant::opcodes;
-use crate::helper::bytecode::modify_push_val;
-use crate::helper::bytecode::{get_instruction_at_index, get_last_instruction_position};
+use crate::analysis::code_type::{Constant, DeploySite, InitcodeTrace, find_deploy_site};
+use crate::error::ObfuscatorError;
+use crate::ir::program::Program;
 
 /*
 Find PUSHx -> x1 -> x2 -> x3 -> CODECOPY sequence and return index of push to modify
 */
 
-pub fn update_runtime_offset(creation_code: &mut String, runtime_bytecode: &String) {
-    let (push_index, push_instruction) = get_push_codecopy_index(creation_code).unwrap();
-    let updated_push_val = calculate_runtime_offset(runtime_bytecode) + 1;
+pub fn update_runtime_offset(
+    init_code: &mut Program,
+    runtime_bytecode: &Program,
+) -> Result<(), ObfuscatorError> {
+    let push_index: usize = get_push_codecopy_index(init_code).ok_or_else(|| {
+        ObfuscatorError::UnexpectedInitcode(String::from(
+            "no PUSH -> x -> x -> x -> CODECOPY sequence holding the runtime length",
+        ))
+    })?;
+    let updated_push_val: usize = calculate_runtime_offset(runtime_bytecode);
 
-    modify_push_val(
-        creation_code,
-        push_index,
-        updated_push_val,
-        &push_instruction,
-    );
+    init_code.instructions[push_index].set_push_value(updated_push_val)
 }
 
-fn get_push_codecopy_index(creation_code: &mut String) -> Option<(i32, String)> {
-    // Checks for PUSHx -> x1 -> x2 -> x3 -> CODECOPY sequence in the bytecode and retuens its
-    let mut skip_to_index: i32 = 0;
-    let mut instruction_position: i32 = 0;
+fn get_push_codecopy_index(init_code: &Program) -> Option<usize> {
+    // Checks for PUSHx -> x1 -> x2 -> x3 -> CODECOPY sequence in the bytecode and returns the PUSH's index
+    init_code.instructions.windows(5).position(|window| {
+        (window[0].opcode == 0x5f || (0x60..=0x6f).contains(&window[0].opcode))
+            && window[4].opcode == 0x39
+    })
+}
 
-    for (index, _) in creation_code.chars().enumerate() {
-        if index as i32 == skip_to_index && index % 2 == 0 {
-            let current_instruction: String = creation_code[index..index + 2].to_string();
+// length in bytes of the runtime bytecode the initcode has to copy
+pub fn calculate_runtime_offset(runtime_bytecode: &Program) -> usize {
+    runtime_bytecode.len()
+}
 
-            let current_instruction_size: i32 =
-                opcodes::get_opcode_size(&current_instruction).unwrap() as i32;
+// runtime length currently declared by the initcode's PUSH before CODECOPY
+pub fn get_runtime_length(init_code: &Program) -> Option<usize> {
+    let push_index: usize = get_push_codecopy_index(init_code)?;
+    init_code.instructions[push_index].push_value()
+}
 
-            skip_to_index = index as i32 + current_instruction_size;
+/*  DEPLOY PATCHING
+the deploying CODECOPY(memory, offset, length) / RETURN(memory, length) found by the splitter
+1) CODECOPY length: the new runtime length
+2) CODECOPY offset: where the runtime now starts, i.e. the initcode length
+3) RETURN length: the new runtime length plus whatever the constructor appends behind the
+    copy (immutables); when it is the same PUSH as the CODECOPY length it is already done
+every value is written into its PUSH in place, so the initcode length does not change
+*/
+pub fn patch_deploy_site(
+    init_code: &mut Program,
+    deploy: &DeploySite,
+    runtime_length: usize,
+) -> Result<(), ObfuscatorError> {
+    let appended: usize = deploy
+        .returned_length
+        .value
+        .saturating_sub(deploy.runtime_length.value);
 
-            instruction_position += 1;
-            // println!("Ins: {}, pos: {}",current_instruction,instruction_position);
+    // 1) CODECOPY length
+    patch_constant(
+        init_code,
+        deploy.runtime_length,
+        runtime_length,
+        "CODECOPY length",
+    )?;
 
-            let current_plus_four_ins =
-                get_instruction_at_index(&creation_code, instruction_position + 4).unwrap();
+    // 2) CODECOPY offset
+    let runtime_offset: usize = init_code.len();
+    patch_constant(
+        init_code,
+        deploy.runtime_offset,
+        runtime_offset,
+        "CODECOPY offset",
+    )?;
 
-            // println!("Current: {} current plus 4: {}",current_instruction,current_plus_four_ins);
-            if (current_instruction == String::from("5f") || current_instruction.starts_with("6"))
-                && current_plus_four_ins == String::from("39")
-            {
-                return Some((index as i32, current_instruction));
-            }
+    // 3) RETURN length
+    if deploy.returned_length.origin.is_none()
+        || deploy.returned_length.origin != deploy.runtime_length.origin
+    {
+        patch_constant(
+            init_code,
+            deploy.returned_length,
+            runtime_length + appended,
+            "RETURN length",
+        )?;
+    }
+    Ok(())
+}
+
+// write `value` into the PUSH a constant came from; a computed constant can only stay as is
+fn patch_constant(
+    init_code: &mut Program,
+    constant: Constant,
+    value: usize,
+    what: &str,
+) -> Result<(), ObfuscatorError> {
+    if constant.value == value {
+        return Ok(());
+    }
+    match constant.origin {
+        Some(index) if index < init_code.instructions.len() => {
+            init_code.instructions[index].set_push_value(value)
         }
+        _ => Err(ObfuscatorError::UnexpectedInitcode(format!(
+            "the {} is computed and cannot be changed from {:#x} to {:#x}",
+            what, constant.value, value
+        ))),
     }
-    return None;
 }
 
-// bytecode: &mut creation_code
-// push_byte_offset: byteoffset_decimal is indec
-// replacement_value: to be calculated
-// instruction: current_instruction
+// move every reference to the data appended behind the runtime (constructor arguments) from
+// `old_offset` to `new_offset`: CODECOPY offsets and the x in CODESIZE - x; returns how
+// many PUSHes were patched
+pub fn patch_appended_data_offset(
+    init_code: &mut Program,
+    trace: &InitcodeTrace,
+    old_offset: usize,
+    new_offset: usize,
+) -> Result<usize, ObfuscatorError> {
+    let mut references: Vec<Constant> = trace
+        .copies
+        .iter()
+        .map(|copy| copy.code_offset)
+        .chain(trace.codesize_offsets.iter().copied())
+        .filter(|constant| constant.value == old_offset)
+        .collect();
+    references.sort_by_key(|constant| constant.origin);
+    references.dedup();
 
-pub fn calculate_runtime_offset(runtime_bytecode: &String) -> i32 {
-    let runtime_byte_length = get_last_instruction_position(runtime_bytecode);
-    return runtime_byte_length;
+    for constant in &references {
+        patch_constant(
+            init_code,
+            *constant,
+            new_offset,
+            "offset of the constructor arguments",
+        )?;
+    }
+    Ok(references.len())
+}
+
+// re-run the deploy search on the patched creation code and make sure it returns exactly
+// `runtime` (placed right after the initcode)
+pub fn check_deploy_site(
+    creation_code: &Program,
+    init_code_length: usize,
+    runtime: &[u8],
+) -> Result<(), ObfuscatorError> {
+    let deploy: DeploySite = find_deploy_site(creation_code).ok_or_else(|| {
+        ObfuscatorError::DeployMismatch(String::from(
+            "the patched initcode no longer returns a copy of its code",
+        ))
+    })?;
+
+    let copied: (usize, usize) = (deploy.runtime_offset.value, deploy.runtime_length.value);
+    if copied != (init_code_length, runtime.len()) {
+        return Err(ObfuscatorError::DeployMismatch(format!(
+            "the initcode copies {:#x} bytes from {:#x} instead of {:#x} bytes from {:#x}",
+            copied.1,
+            copied.0,
+            runtime.len(),
+            init_code_length
+        )));
+    }
+    if deploy.returned_length.value < runtime.len() {
+        return Err(ObfuscatorError::DeployMismatch(format!(
+            "the initcode returns {:#x} bytes of a {:#x} byte runtime",
+            deploy.returned_length.value,
+            runtime.len()
+        )));
+    }
+
+    let code: Vec<u8> = creation_code.encode();
+    if code.get(init_code_length..init_code_length + runtime.len()) != Some(runtime) {
+        return Err(ObfuscatorError::DeployMismatch(String::from(
+            "the copied bytes differ from the obfuscated runtime",
+        )));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::code_type::{CreationSplit, split_creation_code, trace_initcode};
+    use crate::helper::hex::decode_hex;
+
+    // deploys the 3 byte runtime `600100` with constructor arguments reading from 0x1a
+    const CREATION_CODE: &str = "6020601a600039601a38035060038060176000396000f3600100";
+
+    fn split(hex: &str) -> (Program, CreationSplit) {
+        let program: Program = Program::decode(&decode_hex(hex).unwrap());
+        let split: CreationSplit = split_creation_code(&program).unwrap();
+        (program, split)
+    }
+
+    #[test]
+    fn patches_the_deploy_site() {
+        let (_, split) = split(CREATION_CODE);
+        let mut init_code: Program = split.init_code;
+        let runtime: Vec<u8> = vec![0x5b; 0x20];
+        patch_deploy_site(&mut init_code, &split.deploy.unwrap(), runtime.len()).unwrap();
+
+        let mut creation_code: Vec<u8> = init_code.encode();
+        creation_code.extend_from_slice(&runtime);
+        check_deploy_site(&Program::decode(&creation_code), init_code.len(), &runtime).unwrap();
+    }
+
+    #[test]
+    fn refuses_a_length_its_push_cannot_hold() {
+        let (_, split) = split(CREATION_CODE);
+        let mut init_code: Program = split.init_code;
+        assert!(patch_deploy_site(&mut init_code, &split.deploy.unwrap(), 0x100).is_err());
+    }
+
+    #[test]
+    fn detects_a_stale_deploy_site() {
+        let (program, split) = split(CREATION_CODE);
+        let runtime: Vec<u8> = vec![0x00; 4];
+        let mut creation_code: Vec<u8> = split.init_code.encode();
+        creation_code.extend_from_slice(&runtime);
+        assert!(
+            check_deploy_site(
+                &Program::decode(&creation_code),
+                split.init_code.len(),
+                &runtime
+            )
+            .is_err()
+        );
+        // unchanged code checks out
+        check_deploy_site(&program, split.init_code.len(), &split.runtime.encode()).unwrap();
+    }
+
+    #[test]
+    fn moves_the_constructor_arguments() {
+        let (program, split) = split(CREATION_CODE);
+        let mut init_code: Program = split.init_code;
+        let patched: usize =
+            patch_appended_data_offset(&mut init_code, &trace_initcode(&program), 0x1a, 0x40)
+                .unwrap();
+        // the CODECOPY offset and the CODESIZE subtrahend
+        assert_eq!(patched, 2);
+        assert_eq!(init_code.instructions[1].push_value(), Some(0x40));
+        assert_eq!(init_code.instructions[4].push_value(), Some(0x40));
+    }
+
+    #[test]
+    fn updates_the_legacy_runtime_length() {
+        // PUSH2 <length> DUP1 PUSH2 <offset> PUSH0 CODECOPY, as older solc lays it out
+        let mut init_code: Program = Program::decode(&decode_hex("610005806100105f39").unwrap());
+        assert_eq!(get_runtime_length(&init_code), Some(5));
+        let runtime: Program = Program::decode(&[0x60, 0x01, 0x00]);
+        update_runtime_offset(&mut init_code, &runtime).unwrap();
+        assert_eq!(get_runtime_length(&init_code), Some(3));
+
+        let mut unknown: Program = Program::decode(&decode_hex("3660006000395ff3").unwrap());
+        assert_eq!(get_runtime_length(&unknown), None);
+        assert!(update_runtime_offset(&mut unknown, &runtime).is_err());
+    }
 }
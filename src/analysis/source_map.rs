@@ -0,0 +1,190 @@
+use crate::error::ObfuscatorError;
+
+// One entry of a solc source map, describing one instruction: the byte range of the
+// source it was compiled from, the source file index (-1 when the compiler generated it),
+// whether a jump enters (i) or leaves (o) a function or neither (-), and the depth of
+// modifiers it is inlined in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceMapEntry {
+    pub start: i64,
+    pub length: i64,
+    pub file: i64,
+    pub jump: String,
+    pub modifier_depth: i64,
+}
+
+impl SourceMapEntry {
+    // the entry of an instruction no source describes, such as inserted code
+    pub fn synthetic() -> Self {
+        SourceMapEntry {
+            start: -1,
+            length: -1,
+            file: -1,
+            jump: String::from("-"),
+            modifier_depth: 0,
+        }
+    }
+}
+
+// expand a compressed source map (`s:l:f:j:m` entries split by `;`, where an empty or
+// missing field repeats the one of the entry before) into one entry per instruction
+pub fn decode_source_map(map: &str) -> Result<Vec<SourceMapEntry>, ObfuscatorError> {
+    let map: &str = map.trim();
+    if map.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let mut entries: Vec<SourceMapEntry> = Vec::new();
+    let mut previous: SourceMapEntry = SourceMapEntry::synthetic();
+    for (index, text) in map.split(';').enumerate() {
+        let mut entry: SourceMapEntry = previous.clone();
+        for (position, field) in text.split(':').enumerate() {
+            if field.is_empty() {
+                continue;
+            }
+            let number = || -> Result<i64, ObfuscatorError> {
+                field.parse().map_err(|_| {
+                    ObfuscatorError::InvalidSourceMap(format!(
+                        "entry {} has an invalid field {:?}",
+                        index, field
+                    ))
+                })
+            };
+            match position {
+                0 => entry.start = number()?,
+                1 => entry.length = number()?,
+                2 => entry.file = number()?,
+                3 => entry.jump = field.to_string(),
+                4 => entry.modifier_depth = number()?,
+                _ => {
+                    return Err(ObfuscatorError::InvalidSourceMap(format!(
+                        "entry {} has more than five fields",
+                        index
+                    )));
+                }
+            }
+        }
+        entries.push(entry.clone());
+        previous = entry;
+    }
+    Ok(entries)
+}
+
+// compress entries the way solc does: only the fields that changed, trailing ones dropped
+pub fn encode_source_map(entries: &[SourceMapEntry]) -> String {
+    let mut encoded: Vec<String> = Vec::with_capacity(entries.len());
+    let mut previous: SourceMapEntry = SourceMapEntry::synthetic();
+    for entry in entries {
+        let mut fields: Vec<String> = vec![
+            changed(entry.start, previous.start),
+            changed(entry.length, previous.length),
+            changed(entry.file, previous.file),
+            if entry.jump == previous.jump {
+                String::new()
+            } else {
+                entry.jump.clone()
+            },
+            changed(entry.modifier_depth, previous.modifier_depth),
+        ];
+        while fields.last().is_some_and(String::is_empty) {
+            fields.pop();
+        }
+        encoded.push(fields.join(":"));
+        previous = entry.clone();
+    }
+    encoded.join(";")
+}
+
+// source map of transformed code: each instruction takes the entry of the instruction it
+// came from (`origins`, see Program::origins), inserted instructions a synthetic one
+pub fn remap_source_map(map: &str, origins: &[Option<usize>]) -> Result<String, ObfuscatorError> {
+    let entries: Vec<SourceMapEntry> = decode_source_map(map)?;
+    let remapped: Vec<SourceMapEntry> = origins
+        .iter()
+        .map(|origin| {
+            origin
+                .and_then(|index| entries.get(index))
+                .cloned()
+                .unwrap_or_else(SourceMapEntry::synthetic)
+        })
+        .collect();
+    Ok(encode_source_map(&remapped))
+}
+
+fn changed(value: i64, previous: i64) -> String {
+    if value == previous {
+        String::new()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(
+        start: i64,
+        length: i64,
+        file: i64,
+        jump: &str,
+        modifier_depth: i64,
+    ) -> SourceMapEntry {
+        SourceMapEntry {
+            start,
+            length,
+            file,
+            jump: String::from(jump),
+            modifier_depth,
+        }
+    }
+
+    #[test]
+    fn decodes_compressed_entries() {
+        let entries: Vec<SourceMapEntry> =
+            decode_source_map("26:90:0:-:0;;;12:4::i;:::o:1;-1").unwrap();
+        assert_eq!(
+            entries,
+            vec![
+                entry(26, 90, 0, "-", 0),
+                entry(26, 90, 0, "-", 0),
+                entry(26, 90, 0, "-", 0),
+                entry(12, 4, 0, "i", 0),
+                entry(12, 4, 0, "o", 1),
+                entry(-1, 4, 0, "o", 1),
+            ]
+        );
+        assert_eq!(decode_source_map("  ").unwrap(), Vec::new());
+    }
+
+    #[test]
+    fn rejects_malformed_entries() {
+        assert!(decode_source_map("1:2:x").is_err());
+        assert!(decode_source_map("1:2:0:-:0:7").is_err());
+    }
+
+    #[test]
+    fn encode_round_trips() {
+        let entries: Vec<SourceMapEntry> =
+            decode_source_map("26:90:0:-:0;;;12:4::i;:::o:1;-1").unwrap();
+        // the jump and modifier depth of the first entry equal the defaults
+        let encoded: String = encode_source_map(&entries);
+        assert_eq!(encoded, "26:90:0;;;12:4::i;:::o:1;-1");
+        assert_eq!(decode_source_map(&encoded).unwrap(), entries);
+    }
+
+    #[test]
+    fn remaps_by_origin() {
+        let remapped: String =
+            remap_source_map("1:2:0;3:4;5:6", &[Some(2), None, Some(0), Some(7)]).unwrap();
+        assert_eq!(
+            decode_source_map(&remapped).unwrap(),
+            vec![
+                entry(5, 6, 0, "-", 0),
+                SourceMapEntry::synthetic(),
+                entry(1, 2, 0, "-", 0),
+                SourceMapEntry::synthetic(),
+            ]
+        );
+    }
+}
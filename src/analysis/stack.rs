@@ -0,0 +1,361 @@
+use crate::analysis::cfg::{BasicBlock, Cfg, EdgeKind, Terminator};
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use std::collections::{BTreeMap, BTreeSet};
+
+// value sets larger than this are widened to Unknown so the analysis terminates quickly
+const MAX_VALUE_SET: usize = 64;
+// deepest stack slot tracked, anything below is Unknown
+const MAX_TRACKED_DEPTH: usize = 1024;
+// distinct entry stacks kept per block before they are joined into one; keeping them
+// apart keeps internal function returns precise across call sites
+const MAX_CONTEXTS: usize = 32;
+
+// Abstract value of a stack slot
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StackValue {
+    // produced by a computation, calldata, memory, ...
+    Unknown,
+    // the constant of one of these PUSH instructions (indices), depending on the path taken
+    Pushed(BTreeSet<usize>),
+}
+
+impl StackValue {
+    fn join(&self, other: &StackValue) -> StackValue {
+        match (self, other) {
+            (StackValue::Pushed(a), StackValue::Pushed(b)) => {
+                let union: BTreeSet<usize> = a.union(b).copied().collect();
+                if union.len() > MAX_VALUE_SET {
+                    StackValue::Unknown
+                } else {
+                    StackValue::Pushed(union)
+                }
+            }
+            _ => StackValue::Unknown,
+        }
+    }
+}
+
+// Abstract stack, top of stack last; slots below the tracked ones are Unknown
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct AbstractStack {
+    pub values: Vec<StackValue>,
+}
+
+impl AbstractStack {
+    fn push(&mut self, value: StackValue) {
+        self.values.push(value);
+        if self.values.len() > MAX_TRACKED_DEPTH {
+            self.values.remove(0);
+        }
+    }
+
+    fn pop(&mut self) -> StackValue {
+        self.values.pop().unwrap_or(StackValue::Unknown)
+    }
+
+    // value `depth` slots below the top (0 = top)
+    pub fn peek(&self, depth: usize) -> StackValue {
+        match self.values.len().checked_sub(depth + 1) {
+            Some(index) => self.values[index].clone(),
+            None => StackValue::Unknown,
+        }
+    }
+
+    fn swap(&mut self, depth: usize) {
+        let top: usize = self.values.len().wrapping_sub(1);
+        match self.values.len().checked_sub(depth + 1) {
+            Some(index) => self.values.swap(index, top),
+            // the swapped slot is not tracked: the top becomes unknown
+            None => {
+                if let Some(value) = self.values.last_mut() {
+                    *value = StackValue::Unknown;
+                }
+            }
+        }
+    }
+
+    // slot-wise join aligned at the top of the stack
+    fn join(&self, other: &AbstractStack) -> AbstractStack {
+        let depth: usize = self.values.len().min(other.values.len());
+        let mut values: Vec<StackValue> = Vec::with_capacity(depth);
+        for index in (0..depth).rev() {
+            values.push(self.peek(index).join(&other.peek(index)));
+        }
+        AbstractStack { values }
+    }
+}
+
+// A JUMP / JUMPI and what the analysis knows about its destination
+#[derive(Debug, Clone)]
+pub struct JumpSite {
+    // index of the JUMP / JUMPI instruction
+    pub instruction: usize,
+    pub block: usize,
+    // PUSH instructions that may provide the destination, None when unknown
+    pub targets: Option<BTreeSet<usize>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StackAnalysis {
+    // the CFG with every edge resolved through the stack added
+    pub cfg: Cfg,
+    pub jump_sites: Vec<JumpSite>,
+    // PUSH instructions whose constant is only ever used as a jump destination
+    pub code_pointers: BTreeSet<usize>,
+    // stack state on entry of every block reached from the entry block, joined over contexts
+    pub entry_states: Vec<Option<AbstractStack>>,
+}
+
+impl StackAnalysis {
+    // jump sites whose destination could not be determined
+    pub fn unresolved_jumps(&self) -> Vec<&JumpSite> {
+        self.jump_sites
+            .iter()
+            .filter(|site| site.targets.is_none())
+            .collect()
+    }
+}
+
+// run the abstract interpreter over every block reachable from the entry
+pub fn analyze_stack(program: &Program) -> StackAnalysis {
+    let mut cfg: Cfg = Cfg::build(program);
+    let mut entry_states: Vec<Option<AbstractStack>> = vec![None; cfg.blocks.len()];
+    let mut jump_targets: BTreeMap<usize, Option<BTreeSet<usize>>> = BTreeMap::new();
+    // pushes consumed by anything other than a jump destination operand (or DUP/SWAP/POP)
+    let mut data_uses: BTreeSet<usize> = BTreeSet::new();
+
+    if cfg.blocks.is_empty() {
+        return StackAnalysis {
+            cfg,
+            jump_sites: Vec::new(),
+            code_pointers: BTreeSet::new(),
+            entry_states,
+        };
+    }
+
+    // distinct entry stacks per block; a widened block keeps a single joined stack
+    let mut contexts: Vec<Vec<AbstractStack>> = vec![Vec::new(); cfg.blocks.len()];
+    let mut widened: Vec<bool> = vec![false; cfg.blocks.len()];
+    contexts[0].push(AbstractStack::default());
+    let mut worklist: Vec<(usize, AbstractStack)> = vec![(0, AbstractStack::default())];
+
+    while let Some((id, mut stack)) = worklist.pop() {
+        let (start, end, terminator) = {
+            let block: &BasicBlock = &cfg.blocks[id];
+            (block.start, block.end, block.terminator)
+        };
+
+        let mut successors: Vec<(usize, EdgeKind)> = Vec::new();
+        let mut halted: bool = false;
+
+        for index in start..end {
+            let instruction: &Instruction = &program.instructions[index];
+            match instruction.opcode {
+                0x5f..=0x7f => stack.push(StackValue::Pushed(BTreeSet::from([index]))),
+                0x80..=0x8f => {
+                    let value: StackValue = stack.peek((instruction.opcode - 0x80) as usize);
+                    stack.push(value);
+                }
+                0x90..=0x9f => stack.swap((instruction.opcode - 0x8f) as usize),
+                0x50 => {
+                    stack.pop();
+                }
+                0x56 | 0x57 => {
+                    let target: StackValue = stack.pop();
+                    if instruction.opcode == 0x57 {
+                        record_data_use(&stack.pop(), &mut data_uses);
+                    }
+                    let resolved: Option<BTreeSet<usize>> = match target {
+                        StackValue::Pushed(pushes) => Some(pushes),
+                        StackValue::Unknown => None,
+                    };
+
+                    // only this context's destinations are followed
+                    if let Some(pushes) = &resolved {
+                        for push in pushes {
+                            let offset: Option<usize> = program.instructions[*push].push_value();
+                            if let Some(to) =
+                                offset.and_then(|offset| cfg.jumpdest_block(program, offset))
+                            {
+                                successors.push((to, EdgeKind::Jump));
+                            }
+                        }
+                    }
+
+                    // merge with what visits in other contexts saw
+                    let merged: Option<BTreeSet<usize>> = match (jump_targets.get(&index), resolved)
+                    {
+                        (Some(None), _) | (_, None) => None,
+                        (Some(Some(previous)), Some(pushes)) => {
+                            Some(previous.union(&pushes).copied().collect())
+                        }
+                        (None, Some(pushes)) => Some(pushes),
+                    };
+                    jump_targets.insert(index, merged);
+                }
+                _ => match instruction.info() {
+                    Some(info) => {
+                        for _ in 0..info.stack_inputs {
+                            record_data_use(&stack.pop(), &mut data_uses);
+                        }
+                        for _ in 0..info.stack_outputs {
+                            stack.push(StackValue::Unknown);
+                        }
+                        if info.terminating {
+                            halted = true;
+                        }
+                    }
+                    // undefined opcodes abort execution like INVALID
+                    None => halted = true,
+                },
+            }
+            if halted {
+                break;
+            }
+        }
+
+        if !halted
+            && matches!(
+                terminator,
+                Terminator::ConditionalJump | Terminator::Fallthrough
+            )
+            && id + 1 < cfg.blocks.len()
+        {
+            successors.push((id + 1, EdgeKind::Fallthrough));
+        }
+
+        for (to, kind) in successors {
+            cfg.add_edge(id, to, kind);
+            if contexts[to].contains(&stack) {
+                continue;
+            }
+
+            if !widened[to] && contexts[to].len() < MAX_CONTEXTS {
+                contexts[to].push(stack.clone());
+                worklist.push((to, stack.clone()));
+                continue;
+            }
+
+            // too many contexts: fall back to a single joined stack for this block
+            let mut joined: AbstractStack = stack.clone();
+            for existing in &contexts[to] {
+                joined = joined.join(existing);
+            }
+            widened[to] = true;
+            if contexts[to] != [joined.clone()] {
+                contexts[to] = vec![joined.clone()];
+                worklist.push((to, joined));
+            }
+        }
+    }
+
+    for (id, states) in contexts.iter().enumerate() {
+        entry_states[id] = states.iter().cloned().reduce(|a, b| a.join(&b));
+    }
+
+    // a block is only unresolved when some visit could not name its destination
+    for block in &mut cfg.blocks {
+        block.unresolved = false;
+    }
+    let mut jump_sites: Vec<JumpSite> = Vec::new();
+    for (instruction, targets) in jump_targets {
+        let block: usize = cfg
+            .block_of_instruction(instruction)
+            .expect("jump instructions belong to a block");
+        cfg.blocks[block].unresolved = targets.is_none();
+        jump_sites.push(JumpSite {
+            instruction,
+            block,
+            targets,
+        });
+    }
+
+    let code_pointers: BTreeSet<usize> = jump_sites
+        .iter()
+        .filter_map(|site| site.targets.as_ref())
+        .flatten()
+        .copied()
+        .filter(|push| !data_uses.contains(push))
+        .filter(|push| {
+            program.instructions[*push]
+                .push_value()
+                .is_some_and(|offset| cfg.jumpdest_block(program, offset).is_some())
+        })
+        .collect();
+
+    StackAnalysis {
+        cfg,
+        jump_sites,
+        code_pointers,
+        entry_states,
+    }
+}
+
+fn record_data_use(value: &StackValue, data_uses: &mut BTreeSet<usize>) {
+    if let StackValue::Pushed(pushes) = value {
+        data_uses.extend(pushes.iter().copied());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::hex::decode_hex;
+
+    fn analyze(hex: &str) -> StackAnalysis {
+        analyze_stack(&Program::decode(&decode_hex(hex).unwrap()))
+    }
+
+    fn targets(analysis: &StackAnalysis) -> Vec<(usize, Option<Vec<usize>>)> {
+        analysis
+            .jump_sites
+            .iter()
+            .map(|site| {
+                let pushes: Option<Vec<usize>> = site
+                    .targets
+                    .as_ref()
+                    .map(|pushes| pushes.iter().copied().collect());
+                (site.instruction, pushes)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn follows_return_addresses_through_internal_calls() {
+        // two calls of the function at 0x0d, which jumps back to the address left by its caller
+        let analysis: StackAnalysis = analyze("6005600d565b600b600d565b005b56");
+        assert_eq!(
+            targets(&analysis),
+            vec![
+                (2, Some(vec![1])),
+                (6, Some(vec![5])),
+                (10, Some(vec![0, 4])),
+            ]
+        );
+        assert!(analysis.unresolved_jumps().is_empty());
+        assert_eq!(analysis.code_pointers, BTreeSet::from([0, 1, 4, 5]));
+    }
+
+    #[test]
+    fn computed_destinations_are_unresolved() {
+        // JUMP to a word of calldata
+        let analysis: StackAnalysis = analyze("5f35565b00");
+        let unresolved: Vec<usize> = analysis
+            .unresolved_jumps()
+            .iter()
+            .map(|site| site.instruction)
+            .collect();
+        assert_eq!(unresolved, vec![2]);
+        assert!(analysis.cfg.blocks[0].unresolved);
+        assert!(analysis.code_pointers.is_empty());
+    }
+
+    #[test]
+    fn destinations_also_used_as_data_are_not_code_pointers() {
+        // the pushed 7 is both added and jumped to
+        let analysis: StackAnalysis = analyze("600780800150565b00");
+        assert_eq!(targets(&analysis), vec![(5, Some(vec![0]))]);
+        assert!(analysis.code_pointers.is_empty());
+    }
+}
@@ -0,0 +1,255 @@
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+use obfuscator_rs::analysis::immutables::{ImmutableReference, parse_immutable_references};
+use obfuscator_rs::constant::hardfork::Hardfork;
+use obfuscator_rs::helper::link::{Library, LinkReference, decode_unlinked_hex};
+use obfuscator_rs::obfuscation::artifact::{Artifact, parse_artifact};
+use obfuscator_rs::obfuscation::config::{MetadataMode, Pass};
+use obfuscator_rs::obfuscation::dead_code::{DeadCodeLength, DeadCodeMix};
+use std::fs;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser)]
+#[command(name = "obfuscator_rs", version, about = "EVM bytecode obfuscator")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Obfuscate creation bytecode
+    Obfuscate(ObfuscateArgs),
+    /// Print the instructions of a bytecode
+    Disasm(DisasmArgs),
+    /// Report the structure the obfuscator sees in a bytecode
+    Analyze(AnalyzeArgs),
+    /// Check that (obfuscated) creation code is internally consistent
+    Verify(VerifyArgs),
+    /// Fill library addresses into unlinked bytecode
+    Link(LinkArgs),
+}
+
+#[derive(Args)]
+pub struct InputArgs {
+    /// Hex bytecode or a solc standard-JSON / Foundry / Hardhat artifact, a file containing
+    /// it, or `-` for stdin (the default)
+    pub input: Option<String>,
+
+    /// Contract to read from a standard-JSON output holding several (<name> or <source>:<name>)
+    #[arg(long)]
+    pub contract: Option<String>,
+}
+
+#[derive(Args)]
+pub struct OutputArgs {
+    /// Write the result to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+
+    /// Output encoding; `hex` writes an artifact input back as the same artifact
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Hex)]
+    pub format: OutputFormat,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Hex,
+    Bin,
+    Json,
+}
+
+// the passes as listed by Pass::ALL, so `--help` names every one of them
+fn pass_parser() -> impl TypedValueParser<Value = Pass> {
+    PossibleValuesParser::new(Pass::ALL.map(|pass| pass.name())).map(|name: String| {
+        name.parse::<Pass>()
+            .expect("every possible value is a pass")
+    })
+}
+
+#[derive(Args)]
+pub struct ObfuscateArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// Maximum number of jump sequences and code pointers to redirect [default: 10]
+    #[arg(short = 'n', long)]
+    pub max_iterations: Option<usize>,
+
+    /// Comma separated passes to run; all by default
+    #[arg(short, long, value_delimiter = ',', value_parser = pass_parser())]
+    pub passes: Option<Vec<Pass>>,
+
+    /// Oldest hardfork the output has to run on
+    #[arg(long, default_value_t = Hardfork::Prague)]
+    pub fork: Hardfork,
+
+    /// Seed for reproducible output; a random one is chosen and printed otherwise
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+
+    /// What to do with the compiler metadata trailer (keep, strip, rewrite without source hashes)
+    #[arg(long, default_value_t = MetadataMode::Keep)]
+    pub metadata: MetadataMode,
+
+    /// Also obfuscate the initcode (constructor), not only the runtime
+    #[arg(long)]
+    pub initcode: bool,
+
+    /// ABI encoded constructor arguments (hex) to keep behind the obfuscated code; removed
+    /// from the input first if it already ends with them
+    #[arg(long)]
+    pub constructor_args: Option<String>,
+
+    /// JSON file with solc's immutableReferences (or the deployedBytecode object holding
+    /// them); without it immutables are inferred from the constructor
+    #[arg(long)]
+    pub immutables: Option<PathBuf>,
+
+    /// Library to link before obfuscating, as <file.sol:Name>=<address>; may be repeated.
+    /// Placeholders of other libraries are kept, at their new offsets
+    #[arg(short, long = "library")]
+    pub libraries: Vec<Library>,
+
+    /// Source map of the runtime (solc's deployedBytecode.sourceMap), or a file holding it;
+    /// the JSON summary carries it rewritten for the obfuscated runtime
+    #[arg(long)]
+    pub source_map: Option<String>,
+
+    /// Instructions per generated dead code block, as <n> or <min>-<max> [default: 8-24]
+    #[arg(long)]
+    pub dead_code_length: Option<DeadCodeLength>,
+
+    /// Weights of the dead code instruction kinds, as <kind>=<weight>,... with kinds
+    /// constant, arithmetic, bitwise, environment, stack, memory and jump; unlisted kinds keep
+    /// their default weight [default: constant=4,arithmetic=3,bitwise=3,environment=2,stack=2,memory=1,jump=1]
+    #[arg(long)]
+    pub dead_code_mix: Option<DeadCodeMix>,
+}
+
+#[derive(Args)]
+pub struct DisasmArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// Only disassemble the runtime part of creation code
+    #[arg(long)]
+    pub runtime: bool,
+}
+
+#[derive(Args)]
+pub struct AnalyzeArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// Hardfork used to flag unsupported opcodes
+    #[arg(long, default_value_t = Hardfork::Prague)]
+    pub fork: Hardfork,
+}
+
+#[derive(Args)]
+pub struct VerifyArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    /// Hardfork the creation code has to run on
+    #[arg(long, default_value_t = Hardfork::Prague)]
+    pub fork: Hardfork,
+
+    /// Creation code the input was obfuscated from (hex, artifact or file); both are deployed
+    /// in an in-memory EVM and replayed with the same calls to check they behave the same
+    #[arg(long)]
+    pub original: Option<String>,
+
+    /// Calldata (hex) to replay on both contracts, in order; may be repeated. Defaults to
+    /// empty calldata, each function selector of the original and an unknown selector
+    #[arg(long = "calldata", requires = "original")]
+    pub calldata: Vec<String>,
+}
+
+#[derive(Args)]
+pub struct LinkArgs {
+    #[command(flatten)]
+    pub input: InputArgs,
+
+    #[command(flatten)]
+    pub output: OutputArgs,
+
+    /// Library to link, as <file.sol:Name>=<address>; may be repeated
+    #[arg(short, long = "library")]
+    pub libraries: Vec<Library>,
+}
+
+// read hex bytecode from an argument, a file or stdin; library placeholders read as the
+// zero address
+pub fn read_input(input: &InputArgs) -> Result<Vec<u8>, String> {
+    read_unlinked_input(input).map(|(bytes, _)| bytes)
+}
+
+// read hex bytecode that may be unlinked, returning the library placeholders as well
+pub fn read_unlinked_input(input: &InputArgs) -> Result<(Vec<u8>, Vec<LinkReference>), String> {
+    let (hex, _) = read_source(input)?;
+    decode_unlinked_hex(&hex).map_err(|err| err.to_string())
+}
+
+// read hex bytecode or a compiler artifact (JSON) from an argument, a file or stdin; an
+// artifact is returned along with its creation code
+pub fn read_source(input: &InputArgs) -> Result<(String, Option<Artifact>), String> {
+    let text: String = match input.input.as_deref() {
+        None | Some("-") => {
+            let mut buffer: String = String::new();
+            io::stdin()
+                .read_to_string(&mut buffer)
+                .map_err(|err| format!("failed to read stdin: {}", err))?;
+            buffer
+        }
+        Some(value) if Path::new(value).is_file() => {
+            fs::read_to_string(value).map_err(|err| format!("failed to read {}: {}", value, err))?
+        }
+        Some(value) => value.to_string(),
+    };
+
+    if !text.trim_start().starts_with('{') {
+        return Ok((text, None));
+    }
+    let artifact: Artifact =
+        parse_artifact(&text, input.contract.as_deref()).map_err(|err| err.to_string())?;
+    Ok((artifact.bytecode.clone(), Some(artifact)))
+}
+
+// a source map given inline or as a file
+pub fn read_source_map(value: &str) -> Result<String, String> {
+    if Path::new(value).is_file() {
+        return fs::read_to_string(value)
+            .map_err(|err| format!("failed to read {}: {}", value, err));
+    }
+    Ok(value.to_string())
+}
+
+// read a JSON file of immutable references
+pub fn read_immutable_references(path: &Path) -> Result<Vec<ImmutableReference>, String> {
+    let json: String = fs::read_to_string(path)
+        .map_err(|err| format!("failed to read {}: {}", path.display(), err))?;
+    parse_immutable_references(&json).map_err(|err| err.to_string())
+}
+
+// write a result to the output file or stdout
+pub fn write_output(output: &OutputArgs, content: &[u8]) -> Result<(), String> {
+    match &output.output {
+        Some(path) => fs::write(path, content)
+            .map_err(|err| format!("failed to write {}: {}", path.display(), err)),
+        None => io::stdout()
+            .write_all(content)
+            .map_err(|err| format!("failed to write stdout: {}", err)),
+    }
+}
@@ -0,0 +1,69 @@
+use std::fmt;
+use std::str::FromStr;
+
+// Ethereum mainnet hardforks that changed the instruction set, in activation order
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Hardfork {
+    Frontier,
+    Homestead,
+    Byzantium,
+    Constantinople,
+    Istanbul,
+    London,
+    Paris,
+    Shanghai,
+    Cancun,
+    Prague,
+    Osaka,
+}
+
+impl Hardfork {
+    pub const ALL: [Hardfork; 11] = [
+        Hardfork::Frontier,
+        Hardfork::Homestead,
+        Hardfork::Byzantium,
+        Hardfork::Constantinople,
+        Hardfork::Istanbul,
+        Hardfork::London,
+        Hardfork::Paris,
+        Hardfork::Shanghai,
+        Hardfork::Cancun,
+        Hardfork::Prague,
+        Hardfork::Osaka,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Hardfork::Frontier => "frontier",
+            Hardfork::Homestead => "homestead",
+            Hardfork::Byzantium => "byzantium",
+            Hardfork::Constantinople => "constantinople",
+            Hardfork::Istanbul => "istanbul",
+            Hardfork::London => "london",
+            Hardfork::Paris => "paris",
+            Hardfork::Shanghai => "shanghai",
+            Hardfork::Cancun => "cancun",
+            Hardfork::Prague => "prague",
+            Hardfork::Osaka => "osaka",
+        }
+    }
+}
+
+impl fmt::Display for Hardfork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Hardfork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lower: String = s.to_ascii_lowercase();
+        Hardfork::ALL
+            .iter()
+            .find(|fork| fork.name() == lower)
+            .copied()
+            .ok_or_else(|| format!("unknown hardfork: {}", s))
+    }
+}
@@ -1 +1,2 @@
+pub mod hardfork;
 pub mod opcodes;
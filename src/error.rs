@@ -0,0 +1,134 @@
+use crate::constant::hardfork::Hardfork;
+use std::fmt;
+
+// Errors returned by the library instead of panicking on malformed input
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ObfuscatorError {
+    // the input contained no bytecode at all
+    EmptyInput,
+    // hex input with an odd number of digits
+    OddLengthHex {
+        length: usize,
+    },
+    // a character that is not a hex digit, at its position in the (0x stripped) input
+    InvalidHexCharacter {
+        position: usize,
+        character: char,
+    },
+    // PUSH data that runs past the end of the code
+    TruncatedPush {
+        offset: usize,
+        expected: usize,
+        found: usize,
+    },
+    // an opcode that does not exist on the target fork
+    UnsupportedOpcode {
+        offset: usize,
+        mnemonic: String,
+        fork: Hardfork,
+    },
+    // the initcode does not have the shape needed to patch the runtime it deploys
+    UnexpectedInitcode(String),
+    // the patched initcode would not deploy exactly the obfuscated runtime
+    DeployMismatch(String),
+    // a value written into a PUSH that cannot be widened needs more bytes than it has
+    PushValueOverflow {
+        offset: usize,
+        value: usize,
+        width: usize,
+    },
+    // a PUSH refers to a label no instruction carries
+    UndefinedLabel(usize),
+    // a transformation left fewer instructions than the original code it started from had
+    MissingInstructions {
+        expected: usize,
+        found: usize,
+    },
+    // code has to move but some of it depends on its layout, for the reason given
+    FixedLayout(String),
+    // an immutable reference map that is malformed or does not match the runtime
+    InvalidImmutableReferences(String),
+    // a library placeholder that is malformed or not where a PUSH20 keeps its address
+    InvalidLinkReference(String),
+    // a compiler artifact without the fields needed, or naming no single contract
+    InvalidArtifact(String),
+    // a source map entry that is not of the form s:l:f:j:m
+    InvalidSourceMap(String),
+}
+
+impl fmt::Display for ObfuscatorError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObfuscatorError::EmptyInput => write!(f, "input bytecode is empty"),
+            ObfuscatorError::OddLengthHex { length } => {
+                write!(f, "hex input has an odd number of digits ({})", length)
+            }
+            ObfuscatorError::InvalidHexCharacter {
+                position,
+                character,
+            } => write!(
+                f,
+                "invalid hex character {:?} at position {}",
+                character, position
+            ),
+            ObfuscatorError::TruncatedPush {
+                offset,
+                expected,
+                found,
+            } => write!(
+                f,
+                "PUSH at offset {:#x} expects {} data bytes but only {} remain",
+                offset, expected, found
+            ),
+            ObfuscatorError::UnsupportedOpcode {
+                offset,
+                mnemonic,
+                fork,
+            } => write!(
+                f,
+                "{} at offset {:#x} is not available on {}",
+                mnemonic, offset, fork
+            ),
+            ObfuscatorError::UnexpectedInitcode(reason) => {
+                write!(f, "unexpected initcode: {}", reason)
+            }
+            ObfuscatorError::DeployMismatch(reason) => {
+                write!(f, "initcode does not deploy the runtime: {}", reason)
+            }
+            ObfuscatorError::PushValueOverflow {
+                offset,
+                value,
+                width,
+            } => write!(
+                f,
+                "value {:#x} does not fit the PUSH{} at offset {:#x}",
+                value, width, offset
+            ),
+            ObfuscatorError::UndefinedLabel(label) => {
+                write!(f, "PUSH refers to undefined label {}", label)
+            }
+            ObfuscatorError::MissingInstructions { expected, found } => write!(
+                f,
+                "transformed code has {} instructions, fewer than the {} it started from",
+                found, expected
+            ),
+            ObfuscatorError::FixedLayout(reason) => {
+                write!(f, "code has to move but {}", reason)
+            }
+            ObfuscatorError::InvalidImmutableReferences(reason) => {
+                write!(f, "invalid immutable references: {}", reason)
+            }
+            ObfuscatorError::InvalidLinkReference(reason) => {
+                write!(f, "invalid library placeholder: {}", reason)
+            }
+            ObfuscatorError::InvalidArtifact(reason) => {
+                write!(f, "invalid artifact: {}", reason)
+            }
+            ObfuscatorError::InvalidSourceMap(reason) => {
+                write!(f, "invalid source map: {}", reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ObfuscatorError {}
@@ -0,0 +1,368 @@
+use crate::evm::interpreter::{
+    Account, Address, Evm, ExecutionResult, Log, address_from_low_u64, address_to_word,
+};
+use crate::helper::hex::encode_hex;
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use ruint::aliases::U256;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// account both creation codes are deployed from and every call is sent from
+const DEPLOYER: Address = address_from_low_u64(0xdeb107e5);
+// ABI words appended to each selector found, each one the deployer's address: a valid
+// address argument and a non-zero amount, so that transfers and mints get past their checks
+const ARGUMENT_WORDS: usize = 4;
+
+// What differed between the two contracts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DivergenceKind {
+    // one succeeded and the other reverted or failed
+    Status,
+    // returned or revert data
+    Output,
+    Logs,
+    // storage or balance of an account
+    State,
+}
+
+impl fmt::Display for DivergenceKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name: &str = match self {
+            DivergenceKind::Status => "status",
+            DivergenceKind::Output => "output",
+            DivergenceKind::Logs => "logs",
+            DivergenceKind::State => "state",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+// The first difference found: `step` is None for the deployment and the index of the
+// calldata otherwise; `original` and `obfuscated` describe what each side did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    pub step: Option<usize>,
+    pub calldata: Vec<u8>,
+    pub kind: DivergenceKind,
+    pub original: String,
+    pub obfuscated: String,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.step {
+            None => write!(f, "deployment")?,
+            Some(step) => write!(f, "call {} (0x{})", step, encode_hex(&self.calldata))?,
+        }
+        write!(
+            f,
+            ": {} differs\n  original:   {}\n  obfuscated: {}",
+            self.kind, self.original, self.obfuscated
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EquivalenceReport {
+    // calls replayed on both contracts, up to the first divergence
+    pub calls: usize,
+    pub divergence: Option<Divergence>,
+}
+
+impl EquivalenceReport {
+    pub fn is_equivalent(&self) -> bool {
+        self.divergence.is_none()
+    }
+}
+
+/*
+    Differential check of two creation codes:
+    1. Deploy each one from the same account into an empty world of its own, so that both
+       contracts get the same address and see the same environment
+    2. Compare the deployments: success, revert data, logs and the state they left
+    3. Send each calldata to both contracts in turn and compare the same after every call;
+       state carries over between calls, so a call may depend on the ones before it
+    4. Stop at the first divergence
+*/
+pub fn check_equivalence(
+    original: &[u8],
+    obfuscated: &[u8],
+    calls: &[Vec<u8>],
+) -> EquivalenceReport {
+    let mut original_evm: Evm = funded_evm();
+    let mut obfuscated_evm: Evm = funded_evm();
+
+    let original_result: ExecutionResult = original_evm.deploy(DEPLOYER, U256::ZERO, original);
+    let obfuscated_result: ExecutionResult =
+        obfuscated_evm.deploy(DEPLOYER, U256::ZERO, obfuscated);
+    // the outputs of successful deployments are the runtimes, which are meant to differ
+    let compare_output: bool = !original_result.success;
+    if let Some(divergence) = compare(
+        &original_evm,
+        &original_result,
+        &obfuscated_evm,
+        &obfuscated_result,
+        compare_output,
+    ) {
+        return EquivalenceReport {
+            calls: 0,
+            divergence: Some(divergence),
+        };
+    }
+    let Some(address) = original_result.created else {
+        return EquivalenceReport {
+            calls: 0,
+            divergence: None,
+        };
+    };
+
+    for (step, calldata) in calls.iter().enumerate() {
+        let original_result: ExecutionResult =
+            original_evm.call(DEPLOYER, address, U256::ZERO, calldata);
+        let obfuscated_result: ExecutionResult =
+            obfuscated_evm.call(DEPLOYER, address, U256::ZERO, calldata);
+        if let Some(divergence) = compare(
+            &original_evm,
+            &original_result,
+            &obfuscated_evm,
+            &obfuscated_result,
+            true,
+        ) {
+            return EquivalenceReport {
+                calls: step + 1,
+                divergence: Some(Divergence {
+                    step: Some(step),
+                    calldata: calldata.clone(),
+                    ..divergence
+                }),
+            };
+        }
+    }
+    EquivalenceReport {
+        calls: calls.len(),
+        divergence: None,
+    }
+}
+
+// calldata exercising a contract: empty calldata (receive / fallback), each function
+// selector its dispatcher compares against followed by arguments, and a selector it does
+// not know
+pub fn default_calls(code: &Program) -> Vec<Vec<u8>> {
+    let mut calls: Vec<Vec<u8>> = vec![Vec::new()];
+    let instructions: &[Instruction] = &code.instructions;
+    for (index, instruction) in instructions.iter().enumerate() {
+        if instruction.opcode != 0x63 {
+            continue;
+        }
+        // PUSH4 selector, (DUP2,) EQ
+        let compared: bool = instructions[index + 1..]
+            .iter()
+            .take(2)
+            .any(|next| next.opcode == 0x14);
+        if !compared {
+            continue;
+        }
+        let mut calldata: Vec<u8> = instruction.immediate.clone();
+        for _ in 0..ARGUMENT_WORDS {
+            calldata.extend_from_slice(&address_to_word(&DEPLOYER).to_be_bytes::<32>());
+        }
+        if !calls.contains(&calldata) {
+            calls.push(calldata);
+        }
+    }
+    calls.push(vec![0xff; 4]);
+    calls
+}
+
+fn funded_evm() -> Evm {
+    let mut evm: Evm = Evm::new();
+    evm.set_balance(DEPLOYER, U256::from(u128::MAX));
+    evm
+}
+
+// the first difference between two transactions, with `step` and `calldata` left empty
+fn compare(
+    original_evm: &Evm,
+    original: &ExecutionResult,
+    obfuscated_evm: &Evm,
+    obfuscated: &ExecutionResult,
+    compare_output: bool,
+) -> Option<Divergence> {
+    let divergence = |kind: DivergenceKind, original: String, obfuscated: String| Divergence {
+        step: None,
+        calldata: Vec::new(),
+        kind,
+        original,
+        obfuscated,
+    };
+
+    if original.success != obfuscated.success {
+        return Some(divergence(
+            DivergenceKind::Status,
+            describe_status(original),
+            describe_status(obfuscated),
+        ));
+    }
+    if compare_output && original.output != obfuscated.output {
+        return Some(divergence(
+            DivergenceKind::Output,
+            format!("0x{}", encode_hex(&original.output)),
+            format!("0x{}", encode_hex(&obfuscated.output)),
+        ));
+    }
+    if original.logs != obfuscated.logs {
+        return Some(divergence(
+            DivergenceKind::Logs,
+            describe_logs(&original.logs),
+            describe_logs(&obfuscated.logs),
+        ));
+    }
+
+    let original_state: BTreeMap<Address, (U256, &BTreeMap<U256, U256>)> = state(original_evm);
+    let obfuscated_state: BTreeMap<Address, (U256, &BTreeMap<U256, U256>)> = state(obfuscated_evm);
+    if original_state != obfuscated_state {
+        let address: &Address = original_state
+            .keys()
+            .chain(obfuscated_state.keys())
+            .find(|address| original_state.get(*address) != obfuscated_state.get(*address))?;
+        return Some(divergence(
+            DivergenceKind::State,
+            describe_account(address, original_state.get(address)),
+            describe_account(address, obfuscated_state.get(address)),
+        ));
+    }
+    None
+}
+
+// balance and storage of every account; code is left out since it is what was obfuscated
+fn state(evm: &Evm) -> BTreeMap<Address, (U256, &BTreeMap<U256, U256>)> {
+    evm.state
+        .accounts
+        .iter()
+        .map(|(address, account): (&Address, &Account)| {
+            (*address, (account.balance, &account.storage))
+        })
+        .collect()
+}
+
+fn describe_status(result: &ExecutionResult) -> String {
+    match (result.success, &result.error) {
+        (true, _) => String::from("success"),
+        (false, Some(error)) => format!("failed ({})", error),
+        (false, None) => format!("reverted with 0x{}", encode_hex(&result.output)),
+    }
+}
+
+fn describe_logs(logs: &[Log]) -> String {
+    let described: Vec<String> = logs
+        .iter()
+        .map(|log| {
+            let topics: Vec<String> = log
+                .topics
+                .iter()
+                .map(|topic| format!("{:#x}", topic))
+                .collect();
+            format!("[{}] 0x{}", topics.join(", "), encode_hex(&log.data))
+        })
+        .collect();
+    format!("{} log(s) {}", logs.len(), described.join("; "))
+}
+
+fn describe_account(address: &Address, account: Option<&(U256, &BTreeMap<U256, U256>)>) -> String {
+    let Some((balance, storage)) = account else {
+        return format!("0x{} does not exist", encode_hex(address));
+    };
+    let slots: Vec<String> = storage
+        .iter()
+        .map(|(key, value)| format!("{:#x}={:#x}", key, value))
+        .collect();
+    format!(
+        "0x{} balance {} storage {{{}}}",
+        encode_hex(address),
+        balance,
+        slots.join(", ")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::hex::decode_hex;
+
+    // creation code deploying `runtime`, which must be shorter than 0x100 bytes
+    fn creation_code(runtime: &str) -> Vec<u8> {
+        let runtime: Vec<u8> = decode_hex(runtime).unwrap();
+        // PUSH1 len DUP1 PUSH1 0x0b PUSH1 0 CODECOPY PUSH1 0 RETURN
+        let mut code: Vec<u8> = vec![
+            0x60,
+            runtime.len() as u8,
+            0x80,
+            0x60,
+            0x0b,
+            0x60,
+            0x00,
+            0x39,
+            0x60,
+            0x00,
+            0xf3,
+        ];
+        code.extend(runtime);
+        code
+    }
+
+    #[test]
+    fn identical_code_is_equivalent() {
+        // stores the calldata size and returns it
+        let code: Vec<u8> = creation_code("366000553660005260206000f3");
+        let report: EquivalenceReport =
+            check_equivalence(&code, &code, &[Vec::new(), vec![1, 2, 3]]);
+        assert_eq!(report.calls, 2);
+        assert!(report.is_equivalent());
+    }
+
+    #[test]
+    fn finds_the_first_divergence() {
+        let returns_one: Vec<u8> = creation_code("600160005260206000f3");
+        let returns_two: Vec<u8> = creation_code("600260005260206000f3");
+        let report: EquivalenceReport =
+            check_equivalence(&returns_one, &returns_two, &[vec![0xaa], vec![0xbb]]);
+        assert_eq!(report.calls, 1);
+        let divergence: Divergence = report.divergence.unwrap();
+        assert_eq!(divergence.step, Some(0));
+        assert_eq!(divergence.calldata, vec![0xaa]);
+        assert_eq!(divergence.kind, DivergenceKind::Output);
+
+        // one reverts, then both succeed but write different values
+        let stores: Vec<u8> = creation_code("600160005500");
+        let reverts: Vec<u8> = creation_code("60006000fd");
+        let report: EquivalenceReport = check_equivalence(&stores, &reverts, &[Vec::new()]);
+        assert_eq!(report.divergence.unwrap().kind, DivergenceKind::Status);
+        let stores_two: Vec<u8> = creation_code("600260005500");
+        let report: EquivalenceReport = check_equivalence(&stores, &stores_two, &[Vec::new()]);
+        assert_eq!(report.divergence.unwrap().kind, DivergenceKind::State);
+    }
+
+    #[test]
+    fn calls_every_compared_selector() {
+        // PUSH0 CALLDATALOAD PUSH1 0xe0 SHR DUP1 PUSH4 a9059cbb EQ ... PUSH4 70a08231 DUP2 EQ
+        let code: Program = Program::decode(
+            &decode_hex("5f3560e01c8063a9059cbb14600e576370a0823181145b00").unwrap(),
+        );
+        let calls: Vec<Vec<u8>> = default_calls(&code);
+        let selectors: Vec<&[u8]> = calls
+            .iter()
+            .map(|call| &call[..call.len().min(4)])
+            .collect();
+        assert_eq!(
+            selectors,
+            vec![
+                &[][..],
+                &[0xa9, 0x05, 0x9c, 0xbb][..],
+                &[0x70, 0xa0, 0x82, 0x31][..],
+                &[0xff; 4][..],
+            ]
+        );
+        assert_eq!(calls[1].len(), 4 + 32 * ARGUMENT_WORDS);
+    }
+}
@@ -0,0 +1,1225 @@
+use ruint::aliases::U256;
+use std::collections::{BTreeMap, BTreeSet};
+use tiny_keccak::{Hasher, Keccak};
+
+// instructions executed by one transaction, all frames included, before it is aborted;
+// gas is not metered, so this is what stops infinite loops
+const MAX_STEPS: usize = 10_000_000;
+const MAX_STACK: usize = 1024;
+const MAX_DEPTH: usize = 1024;
+// largest memory a frame may use; anything beyond would run out of gas on a real chain
+const MAX_MEMORY: usize = 32 * 1024 * 1024;
+// EIP-170 / EIP-3860
+const MAX_CODE_SIZE: usize = 0x6000;
+const MAX_INITCODE_SIZE: usize = 2 * MAX_CODE_SIZE;
+// the identity precompile, the only one implemented; calls to the others find no code
+const IDENTITY: Address = address_from_low_u64(4);
+
+pub type Address = [u8; 20];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Account {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Vec<u8>,
+    pub storage: BTreeMap<U256, U256>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Log {
+    pub address: Address,
+    pub topics: Vec<U256>,
+    pub data: Vec<u8>,
+}
+
+// Accounts plus everything a reverting frame has to roll back
+#[derive(Debug, Clone, Default)]
+pub struct WorldState {
+    pub accounts: BTreeMap<Address, Account>,
+    transient: BTreeMap<(Address, U256), U256>,
+    logs: Vec<Log>,
+    // contracts created by the current transaction, which SELFDESTRUCT removes (EIP-6780)
+    created: BTreeSet<Address>,
+    destroyed: BTreeSet<Address>,
+}
+
+// Block values seen by the code; fixed so that runs are reproducible
+#[derive(Debug, Clone)]
+pub struct BlockEnv {
+    pub number: u64,
+    pub timestamp: u64,
+    pub coinbase: Address,
+    pub chain_id: u64,
+    pub base_fee: u64,
+    pub blob_base_fee: u64,
+    pub prevrandao: U256,
+    pub gas_limit: u64,
+    pub gas_price: u64,
+}
+
+impl Default for BlockEnv {
+    fn default() -> Self {
+        BlockEnv {
+            number: 20_000_000,
+            timestamp: 1_720_000_000,
+            coinbase: address_from_low_u64(0xc0ffee),
+            chain_id: 1,
+            base_fee: 1_000_000_000,
+            blob_base_fee: 1,
+            prevrandao: U256::from(0x5eed_u64),
+            gas_limit: 30_000_000,
+            gas_price: 1_000_000_000,
+        }
+    }
+}
+
+// What a transaction did: its success, the returned (or revert) data, the logs it emitted
+// and, for a deployment, the address of the new contract. `error` tells why it failed when
+// it did not end in REVERT
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExecutionResult {
+    pub success: bool,
+    pub output: Vec<u8>,
+    pub logs: Vec<Log>,
+    pub created: Option<Address>,
+    pub error: Option<String>,
+}
+
+// A message call or contract creation being executed
+#[derive(Debug, Clone)]
+struct Message {
+    caller: Address,
+    // account whose storage and balance the code uses
+    address: Address,
+    value: U256,
+    data: Vec<u8>,
+    code: Vec<u8>,
+    is_static: bool,
+    depth: usize,
+}
+
+// How a frame ended
+#[derive(Debug, Clone)]
+struct FrameResult {
+    success: bool,
+    output: Vec<u8>,
+    error: Option<String>,
+}
+
+impl FrameResult {
+    fn failure(error: String) -> Self {
+        FrameResult {
+            success: false,
+            output: Vec::new(),
+            error: Some(error),
+        }
+    }
+}
+
+// An in-memory EVM with the semantics of the latest hardfork, without gas accounting:
+// GAS always reports the block gas limit and a step limit bounds every transaction
+#[derive(Debug, Clone, Default)]
+pub struct Evm {
+    pub state: WorldState,
+    pub block: BlockEnv,
+    origin: Address,
+    steps: usize,
+}
+
+impl Evm {
+    pub fn new() -> Self {
+        Evm::default()
+    }
+
+    pub fn set_balance(&mut self, address: Address, balance: U256) {
+        self.state.accounts.entry(address).or_default().balance = balance;
+    }
+
+    pub fn account(&self, address: &Address) -> Option<&Account> {
+        self.state.accounts.get(address)
+    }
+
+    // run a contract creation transaction from `caller`
+    pub fn deploy(&mut self, caller: Address, value: U256, init_code: &[u8]) -> ExecutionResult {
+        self.begin_transaction(caller);
+        let (created, frame) = self.create(caller, value, init_code.to_vec(), None, 0);
+        self.end_transaction(frame, created)
+    }
+
+    // run a message call transaction from `caller`
+    pub fn call(
+        &mut self,
+        caller: Address,
+        to: Address,
+        value: U256,
+        data: &[u8],
+    ) -> ExecutionResult {
+        self.begin_transaction(caller);
+        self.state.accounts.entry(caller).or_default().nonce += 1;
+        let message: Message = Message {
+            caller,
+            address: to,
+            value,
+            data: data.to_vec(),
+            code: self.code_of(&to),
+            is_static: false,
+            depth: 0,
+        };
+        let frame: FrameResult = self.execute(message, true);
+        self.end_transaction(frame, None)
+    }
+
+    fn begin_transaction(&mut self, caller: Address) {
+        self.origin = caller;
+        self.steps = 0;
+        self.state.logs.clear();
+        self.state.transient.clear();
+        self.state.created.clear();
+        self.state.destroyed.clear();
+    }
+
+    fn end_transaction(&mut self, frame: FrameResult, created: Option<Address>) -> ExecutionResult {
+        for address in std::mem::take(&mut self.state.destroyed) {
+            self.state.accounts.remove(&address);
+        }
+        ExecutionResult {
+            success: frame.success,
+            output: frame.output,
+            logs: std::mem::take(&mut self.state.logs),
+            created: created.filter(|_| frame.success),
+            error: frame.error,
+        }
+    }
+
+    fn code_of(&self, address: &Address) -> Vec<u8> {
+        self.state
+            .accounts
+            .get(address)
+            .map(|account| account.code.clone())
+            .unwrap_or_default()
+    }
+
+    fn balance_of(&self, address: &Address) -> U256 {
+        self.state
+            .accounts
+            .get(address)
+            .map_or(U256::ZERO, |account| account.balance)
+    }
+
+    fn transfer(&mut self, from: Address, to: Address, value: U256) -> bool {
+        if value.is_zero() {
+            return true;
+        }
+        if self.balance_of(&from) < value {
+            return false;
+        }
+        self.state.accounts.entry(from).or_default().balance -= value;
+        let recipient: &mut Account = self.state.accounts.entry(to).or_default();
+        recipient.balance = recipient.balance.wrapping_add(value);
+        true
+    }
+
+    // run a message in a frame of its own; its state changes are dropped unless it succeeds
+    fn execute(&mut self, message: Message, transfer: bool) -> FrameResult {
+        let snapshot: WorldState = self.state.clone();
+        if transfer && !self.transfer(message.caller, message.address, message.value) {
+            return FrameResult::failure(String::from("insufficient balance for the value"));
+        }
+
+        let frame: FrameResult = if message.address == IDENTITY {
+            FrameResult {
+                success: true,
+                output: message.data.clone(),
+                error: None,
+            }
+        } else {
+            Frame::new(&message).run(self)
+        };
+        if !frame.success {
+            self.state = snapshot;
+        }
+        frame
+    }
+
+    // CREATE (salt None) or CREATE2 from `caller`; returns the new address on success
+    fn create(
+        &mut self,
+        caller: Address,
+        value: U256,
+        init_code: Vec<u8>,
+        salt: Option<U256>,
+        depth: usize,
+    ) -> (Option<Address>, FrameResult) {
+        let nonce: u64 = self
+            .state
+            .accounts
+            .get(&caller)
+            .map_or(0, |account| account.nonce);
+        let address: Address = match salt {
+            Some(salt) => create2_address(&caller, salt, &init_code),
+            None => create_address(&caller, nonce),
+        };
+        self.state.accounts.entry(caller).or_default().nonce += 1;
+        if init_code.len() > MAX_INITCODE_SIZE {
+            return (
+                None,
+                FrameResult::failure(String::from("initcode too large")),
+            );
+        }
+        if self
+            .state
+            .accounts
+            .get(&address)
+            .is_some_and(|account| account.nonce > 0 || !account.code.is_empty())
+        {
+            return (
+                None,
+                FrameResult::failure(String::from("address collision")),
+            );
+        }
+
+        let snapshot: WorldState = self.state.clone();
+        self.state.accounts.entry(address).or_default().nonce = 1;
+        self.state.created.insert(address);
+        let message: Message = Message {
+            caller,
+            address,
+            value,
+            data: Vec::new(),
+            code: init_code,
+            is_static: false,
+            depth,
+        };
+        let mut frame: FrameResult = self.execute(message, true);
+        if frame.success {
+            let error: Option<&str> = if frame.output.len() > MAX_CODE_SIZE {
+                Some("deployed code too large")
+            } else if frame.output.first() == Some(&0xef) {
+                Some("deployed code starts with 0xef")
+            } else {
+                None
+            };
+            match error {
+                Some(error) => frame = FrameResult::failure(error.to_string()),
+                None => {
+                    self.state.accounts.entry(address).or_default().code = frame.output.clone();
+                    return (Some(address), frame);
+                }
+            }
+        }
+        self.state = snapshot;
+        (None, frame)
+    }
+}
+
+// Machine state of one executing frame
+struct Frame<'a> {
+    message: &'a Message,
+    pc: usize,
+    stack: Vec<U256>,
+    memory: Vec<u8>,
+    return_data: Vec<u8>,
+    jumpdests: Vec<bool>,
+}
+
+impl<'a> Frame<'a> {
+    fn new(message: &'a Message) -> Self {
+        Frame {
+            message,
+            pc: 0,
+            stack: Vec::new(),
+            memory: Vec::new(),
+            return_data: Vec::new(),
+            jumpdests: jumpdest_table(&message.code),
+        }
+    }
+
+    fn pop(&mut self) -> Result<U256, String> {
+        self.stack
+            .pop()
+            .ok_or_else(|| format!("stack underflow at {:#x}", self.pc))
+    }
+
+    fn push(&mut self, value: U256) -> Result<(), String> {
+        if self.stack.len() >= MAX_STACK {
+            return Err(format!("stack overflow at {:#x}", self.pc));
+        }
+        self.stack.push(value);
+        Ok(())
+    }
+
+    // the memory range [offset, offset + size), expanding memory to cover it
+    fn memory_range(&mut self, offset: U256, size: U256) -> Result<std::ops::Range<usize>, String> {
+        if size.is_zero() {
+            return Ok(0..0);
+        }
+        let offset: usize = offset.saturating_to::<usize>();
+        let size: usize = size.saturating_to::<usize>();
+        let end: usize = offset
+            .checked_add(size)
+            .filter(|end| *end <= MAX_MEMORY)
+            .ok_or_else(|| format!("memory limit exceeded at {:#x}", self.pc))?;
+        let words: usize = end.div_ceil(32) * 32;
+        if words > self.memory.len() {
+            self.memory.resize(words, 0);
+        }
+        Ok(offset..end)
+    }
+
+    fn jump(&mut self, destination: U256) -> Result<(), String> {
+        let destination: usize = destination.saturating_to::<usize>();
+        if !self.jumpdests.get(destination).copied().unwrap_or(false) {
+            return Err(format!("invalid jump destination {:#x}", destination));
+        }
+        self.pc = destination;
+        Ok(())
+    }
+
+    fn check_static(&self) -> Result<(), String> {
+        if self.message.is_static {
+            return Err(format!("state change in a static call at {:#x}", self.pc));
+        }
+        Ok(())
+    }
+
+    fn run(mut self, evm: &mut Evm) -> FrameResult {
+        loop {
+            match self.step(evm) {
+                Ok(None) => {}
+                Ok(Some(result)) => return result,
+                Err(error) => return FrameResult::failure(error),
+            }
+        }
+    }
+
+    // execute the instruction at pc; Some when the frame ended
+    fn step(&mut self, evm: &mut Evm) -> Result<Option<FrameResult>, String> {
+        evm.steps += 1;
+        if evm.steps > MAX_STEPS {
+            return Err(String::from("step limit exceeded"));
+        }
+        let message: &'a Message = self.message;
+        let code: &'a [u8] = &message.code;
+        let Some(&opcode) = code.get(self.pc) else {
+            return Ok(Some(FrameResult {
+                success: true,
+                output: Vec::new(),
+                error: None,
+            }));
+        };
+        let pc: usize = self.pc;
+        self.pc += 1;
+
+        match opcode {
+            0x00 => {
+                return Ok(Some(FrameResult {
+                    success: true,
+                    output: Vec::new(),
+                    error: None,
+                }));
+            }
+            0x15 => {
+                let value: U256 = self.pop()?;
+                self.push(U256::from(value.is_zero()))?;
+            }
+            0x19 => {
+                let value: U256 = self.pop()?;
+                self.push(!value)?;
+            }
+            0x01..=0x0b | 0x10..=0x1d => {
+                let a: U256 = self.pop()?;
+                let b: U256 = self.pop()?;
+                let value: U256 = match opcode {
+                    0x01 => a.wrapping_add(b),
+                    0x02 => a.wrapping_mul(b),
+                    0x03 => a.wrapping_sub(b),
+                    0x04 => a.checked_div(b).unwrap_or_default(),
+                    0x05 => signed_div(a, b),
+                    0x06 => a.checked_rem(b).unwrap_or_default(),
+                    0x07 => signed_rem(a, b),
+                    0x08 => a.add_mod(b, self.pop()?),
+                    0x09 => a.mul_mod(b, self.pop()?),
+                    0x0a => a.wrapping_pow(b),
+                    0x0b => sign_extend(a, b),
+                    0x10 => U256::from(a < b),
+                    0x11 => U256::from(a > b),
+                    0x12 => U256::from(signed_lt(a, b)),
+                    0x13 => U256::from(signed_lt(b, a)),
+                    0x14 => U256::from(a == b),
+                    0x16 => a & b,
+                    0x17 => a | b,
+                    0x18 => a ^ b,
+                    0x1a => match a.saturating_to::<usize>() {
+                        index if index < 32 => U256::from(b.byte(31 - index)),
+                        _ => U256::ZERO,
+                    },
+                    0x1b => shift_left(b, a),
+                    0x1c => shift_right(b, a),
+                    _ => shift_right_signed(b, a),
+                };
+                self.push(value)?;
+            }
+            0x20 => {
+                let (offset, size) = (self.pop()?, self.pop()?);
+                let range: std::ops::Range<usize> = self.memory_range(offset, size)?;
+                let hash: [u8; 32] = keccak256(&self.memory[range]);
+                self.push(U256::from_be_bytes(hash))?;
+            }
+            0x30 => self.push(address_to_word(&self.message.address))?,
+            0x31 => {
+                let address: Address = word_to_address(self.pop()?);
+                self.push(evm.balance_of(&address))?;
+            }
+            0x32 => self.push(address_to_word(&evm.origin))?,
+            0x33 => self.push(address_to_word(&self.message.caller))?,
+            0x34 => self.push(self.message.value)?,
+            0x35 => {
+                let offset: usize = self.pop()?.saturating_to::<usize>();
+                let word: [u8; 32] = padded_slice(&self.message.data, offset);
+                self.push(U256::from_be_bytes(word))?;
+            }
+            0x36 => self.push(U256::from(self.message.data.len()))?,
+            0x37 | 0x39 | 0x3e => {
+                let (destination, offset, size) = (self.pop()?, self.pop()?, self.pop()?);
+                let source: Vec<u8> = match opcode {
+                    0x37 => self.message.data.clone(),
+                    0x39 => self.message.code.clone(),
+                    _ => {
+                        let end: Option<U256> = offset.checked_add(size);
+                        if end.is_none_or(|end| end > U256::from(self.return_data.len())) {
+                            return Err(format!("return data out of bounds at {:#x}", pc));
+                        }
+                        self.return_data.clone()
+                    }
+                };
+                self.copy_to_memory(destination, offset, size, &source)?;
+            }
+            0x38 => self.push(U256::from(self.message.code.len()))?,
+            0x3a => self.push(U256::from(evm.block.gas_price))?,
+            0x3b => {
+                let address: Address = word_to_address(self.pop()?);
+                self.push(U256::from(evm.code_of(&address).len()))?;
+            }
+            0x3c => {
+                let address: Address = word_to_address(self.pop()?);
+                let (destination, offset, size) = (self.pop()?, self.pop()?, self.pop()?);
+                let code: Vec<u8> = evm.code_of(&address);
+                self.copy_to_memory(destination, offset, size, &code)?;
+            }
+            0x3d => self.push(U256::from(self.return_data.len()))?,
+            0x3f => {
+                let address: Address = word_to_address(self.pop()?);
+                let hash: U256 = match evm.state.accounts.get(&address) {
+                    Some(account)
+                        if account.nonce > 0
+                            || !account.balance.is_zero()
+                            || !account.code.is_empty() =>
+                    {
+                        U256::from_be_bytes(keccak256(&account.code))
+                    }
+                    _ => U256::ZERO,
+                };
+                self.push(hash)?;
+            }
+            0x40 => {
+                let number: U256 = self.pop()?;
+                let current: U256 = U256::from(evm.block.number);
+                let hash: U256 = if number < current && current - number <= U256::from(256) {
+                    U256::from_be_bytes(keccak256(&number.to_be_bytes::<32>()))
+                } else {
+                    U256::ZERO
+                };
+                self.push(hash)?;
+            }
+            0x41 => self.push(address_to_word(&evm.block.coinbase))?,
+            0x42 => self.push(U256::from(evm.block.timestamp))?,
+            0x43 => self.push(U256::from(evm.block.number))?,
+            0x44 => self.push(evm.block.prevrandao)?,
+            0x45 | 0x5a => self.push(U256::from(evm.block.gas_limit))?,
+            0x46 => self.push(U256::from(evm.block.chain_id))?,
+            0x47 => self.push(evm.balance_of(&self.message.address))?,
+            0x48 => self.push(U256::from(evm.block.base_fee))?,
+            // no blob transactions: every BLOBHASH index is out of range
+            0x49 => {
+                self.pop()?;
+                self.push(U256::ZERO)?;
+            }
+            0x4a => self.push(U256::from(evm.block.blob_base_fee))?,
+            0x50 => {
+                self.pop()?;
+            }
+            0x51 => {
+                let offset: U256 = self.pop()?;
+                let range: std::ops::Range<usize> = self.memory_range(offset, U256::from(32))?;
+                let word: U256 = U256::from_be_slice(&self.memory[range]);
+                self.push(word)?;
+            }
+            0x52 => {
+                let (offset, value) = (self.pop()?, self.pop()?);
+                let range: std::ops::Range<usize> = self.memory_range(offset, U256::from(32))?;
+                self.memory[range].copy_from_slice(&value.to_be_bytes::<32>());
+            }
+            0x53 => {
+                let (offset, value) = (self.pop()?, self.pop()?);
+                let range: std::ops::Range<usize> = self.memory_range(offset, U256::from(1))?;
+                self.memory[range.start] = value.byte(0);
+            }
+            0x54 => {
+                let key: U256 = self.pop()?;
+                let value: U256 = evm
+                    .state
+                    .accounts
+                    .get(&self.message.address)
+                    .and_then(|account| account.storage.get(&key))
+                    .copied()
+                    .unwrap_or_default();
+                self.push(value)?;
+            }
+            0x55 => {
+                self.check_static()?;
+                let (key, value) = (self.pop()?, self.pop()?);
+                let storage: &mut BTreeMap<U256, U256> = &mut evm
+                    .state
+                    .accounts
+                    .entry(self.message.address)
+                    .or_default()
+                    .storage;
+                if value.is_zero() {
+                    storage.remove(&key);
+                } else {
+                    storage.insert(key, value);
+                }
+            }
+            0x56 => {
+                let destination: U256 = self.pop()?;
+                self.jump(destination)?;
+            }
+            0x57 => {
+                let (destination, condition) = (self.pop()?, self.pop()?);
+                if !condition.is_zero() {
+                    self.jump(destination)?;
+                }
+            }
+            0x58 => self.push(U256::from(pc))?,
+            0x59 => self.push(U256::from(self.memory.len()))?,
+            0x5b => {}
+            0x5c => {
+                let key: U256 = self.pop()?;
+                let value: U256 = evm
+                    .state
+                    .transient
+                    .get(&(self.message.address, key))
+                    .copied()
+                    .unwrap_or_default();
+                self.push(value)?;
+            }
+            0x5d => {
+                self.check_static()?;
+                let (key, value) = (self.pop()?, self.pop()?);
+                evm.state
+                    .transient
+                    .insert((self.message.address, key), value);
+            }
+            0x5e => {
+                let (destination, offset, size) = (self.pop()?, self.pop()?, self.pop()?);
+                let source: std::ops::Range<usize> = self.memory_range(offset, size)?;
+                let target: std::ops::Range<usize> = self.memory_range(destination, size)?;
+                self.memory.copy_within(source, target.start);
+            }
+            0x5f..=0x7f => {
+                let width: usize = (opcode - 0x5f) as usize;
+                let end: usize = (self.pc + width).min(code.len());
+                let mut word: [u8; 32] = [0; 32];
+                // PUSH data cut off by the end of the code reads as zeros
+                word[32 - width..32 - width + (end - self.pc)].copy_from_slice(&code[self.pc..end]);
+                self.pc += width;
+                self.push(U256::from_be_bytes(word))?;
+            }
+            0x80..=0x8f => {
+                let depth: usize = (opcode - 0x80) as usize;
+                let value: U256 = *self
+                    .stack
+                    .len()
+                    .checked_sub(depth + 1)
+                    .and_then(|index| self.stack.get(index))
+                    .ok_or_else(|| format!("stack underflow at {:#x}", pc))?;
+                self.push(value)?;
+            }
+            0x90..=0x9f => {
+                let depth: usize = (opcode - 0x8f) as usize;
+                let top: usize = self.stack.len().wrapping_sub(1);
+                let index: usize = self
+                    .stack
+                    .len()
+                    .checked_sub(depth + 1)
+                    .ok_or_else(|| format!("stack underflow at {:#x}", pc))?;
+                self.stack.swap(index, top);
+            }
+            0xa0..=0xa4 => {
+                self.check_static()?;
+                let (offset, size) = (self.pop()?, self.pop()?);
+                let mut topics: Vec<U256> = Vec::new();
+                for _ in 0..opcode - 0xa0 {
+                    topics.push(self.pop()?);
+                }
+                let range: std::ops::Range<usize> = self.memory_range(offset, size)?;
+                evm.state.logs.push(Log {
+                    address: self.message.address,
+                    topics,
+                    data: self.memory[range].to_vec(),
+                });
+            }
+            0xf0 | 0xf5 => {
+                self.check_static()?;
+                let (value, offset, size) = (self.pop()?, self.pop()?, self.pop()?);
+                let salt: Option<U256> = match opcode {
+                    0xf5 => Some(self.pop()?),
+                    _ => None,
+                };
+                let range: std::ops::Range<usize> = self.memory_range(offset, size)?;
+                let init_code: Vec<u8> = self.memory[range].to_vec();
+                self.return_data.clear();
+                let creator: Address = self.message.address;
+                if self.message.depth + 1 > MAX_DEPTH || evm.balance_of(&creator) < value {
+                    self.push(U256::ZERO)?;
+                    return Ok(None);
+                }
+                let (created, frame) =
+                    evm.create(creator, value, init_code, salt, self.message.depth + 1);
+                if !frame.success {
+                    self.return_data = frame.output;
+                }
+                self.push(created.as_ref().map_or(U256::ZERO, address_to_word))?;
+            }
+            0xf1 | 0xf2 | 0xf4 | 0xfa => {
+                let _gas: U256 = self.pop()?;
+                let target: Address = word_to_address(self.pop()?);
+                let value: U256 = match opcode {
+                    0xf1 | 0xf2 => self.pop()?,
+                    _ => U256::ZERO,
+                };
+                if opcode == 0xf1 && !value.is_zero() {
+                    self.check_static()?;
+                }
+                let (in_offset, in_size) = (self.pop()?, self.pop()?);
+                let (out_offset, out_size) = (self.pop()?, self.pop()?);
+                let input: std::ops::Range<usize> = self.memory_range(in_offset, in_size)?;
+                let output: std::ops::Range<usize> = self.memory_range(out_offset, out_size)?;
+
+                let message: Message = Message {
+                    // DELEGATECALL keeps the caller and value of the current frame
+                    caller: match opcode {
+                        0xf4 => self.message.caller,
+                        _ => self.message.address,
+                    },
+                    // CALLCODE and DELEGATECALL run the target's code on this account
+                    address: match opcode {
+                        0xf1 | 0xfa => target,
+                        _ => self.message.address,
+                    },
+                    value: match opcode {
+                        0xf4 => self.message.value,
+                        _ => value,
+                    },
+                    data: self.memory[input].to_vec(),
+                    code: evm.code_of(&target),
+                    is_static: self.message.is_static || opcode == 0xfa,
+                    depth: self.message.depth + 1,
+                };
+                let frame: FrameResult = if message.depth > MAX_DEPTH {
+                    FrameResult::failure(String::from("call depth exceeded"))
+                } else if target == IDENTITY && opcode != 0xf1 {
+                    FrameResult {
+                        success: true,
+                        output: message.data.clone(),
+                        error: None,
+                    }
+                } else {
+                    evm.execute(message, opcode == 0xf1 || opcode == 0xf2)
+                };
+
+                let copied: usize = output.len().min(frame.output.len());
+                self.memory[output.start..output.start + copied]
+                    .copy_from_slice(&frame.output[..copied]);
+                self.return_data = frame.output;
+                self.push(U256::from(frame.success))?;
+            }
+            0xf3 | 0xfd => {
+                let (offset, size) = (self.pop()?, self.pop()?);
+                let range: std::ops::Range<usize> = self.memory_range(offset, size)?;
+                return Ok(Some(FrameResult {
+                    success: opcode == 0xf3,
+                    output: self.memory[range].to_vec(),
+                    error: None,
+                }));
+            }
+            0xff => {
+                self.check_static()?;
+                let beneficiary: Address = word_to_address(self.pop()?);
+                let address: Address = self.message.address;
+                let balance: U256 = evm.balance_of(&address);
+                evm.state.accounts.entry(address).or_default().balance = U256::ZERO;
+                if beneficiary != address || !evm.state.created.contains(&address) {
+                    let recipient: &mut Account =
+                        evm.state.accounts.entry(beneficiary).or_default();
+                    recipient.balance = recipient.balance.wrapping_add(balance);
+                }
+                if evm.state.created.contains(&address) {
+                    evm.state.destroyed.insert(address);
+                }
+                return Ok(Some(FrameResult {
+                    success: true,
+                    output: Vec::new(),
+                    error: None,
+                }));
+            }
+            _ => return Err(format!("invalid opcode {:#04x} at {:#x}", opcode, pc)),
+        }
+        Ok(None)
+    }
+
+    // copy `size` bytes of `source` from `offset` (zero padded) to memory at `destination`
+    fn copy_to_memory(
+        &mut self,
+        destination: U256,
+        offset: U256,
+        size: U256,
+        source: &[u8],
+    ) -> Result<(), String> {
+        let range: std::ops::Range<usize> = self.memory_range(destination, size)?;
+        let offset: usize = offset.saturating_to::<usize>();
+        for (index, byte) in self.memory[range].iter_mut().enumerate() {
+            *byte = offset
+                .checked_add(index)
+                .and_then(|position| source.get(position))
+                .copied()
+                .unwrap_or(0);
+        }
+        Ok(())
+    }
+}
+
+// valid jump destinations: JUMPDEST bytes that are not PUSH data
+fn jumpdest_table(code: &[u8]) -> Vec<bool> {
+    let mut table: Vec<bool> = vec![false; code.len()];
+    let mut pc: usize = 0;
+    while pc < code.len() {
+        let opcode: u8 = code[pc];
+        if opcode == 0x5b {
+            table[pc] = true;
+        }
+        pc += 1;
+        if (0x60..=0x7f).contains(&opcode) {
+            pc += (opcode - 0x5f) as usize;
+        }
+    }
+    table
+}
+
+// 32 bytes of `data` from `offset`, zero padded past its end
+fn padded_slice(data: &[u8], offset: usize) -> [u8; 32] {
+    let mut word: [u8; 32] = [0; 32];
+    for (index, byte) in word.iter_mut().enumerate() {
+        *byte = offset
+            .checked_add(index)
+            .and_then(|position| data.get(position))
+            .copied()
+            .unwrap_or(0);
+    }
+    word
+}
+
+fn is_negative(value: U256) -> bool {
+    value.bit(255)
+}
+
+fn absolute(value: U256) -> U256 {
+    if is_negative(value) {
+        value.wrapping_neg()
+    } else {
+        value
+    }
+}
+
+fn signed_div(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::ZERO;
+    }
+    let quotient: U256 = absolute(a) / absolute(b);
+    if is_negative(a) != is_negative(b) {
+        quotient.wrapping_neg()
+    } else {
+        quotient
+    }
+}
+
+fn signed_rem(a: U256, b: U256) -> U256 {
+    if b.is_zero() {
+        return U256::ZERO;
+    }
+    let remainder: U256 = absolute(a) % absolute(b);
+    if is_negative(a) {
+        remainder.wrapping_neg()
+    } else {
+        remainder
+    }
+}
+
+fn signed_lt(a: U256, b: U256) -> bool {
+    match (is_negative(a), is_negative(b)) {
+        (true, false) => true,
+        (false, true) => false,
+        _ => a < b,
+    }
+}
+
+// SIGNEXTEND: extend the sign bit of byte `size` (counted from the right) of `value`
+fn sign_extend(size: U256, value: U256) -> U256 {
+    if size >= U256::from(31) {
+        return value;
+    }
+    let bit: usize = size.saturating_to::<usize>() * 8 + 7;
+    let mask: U256 = (U256::from(1) << (bit + 1)) - U256::from(1);
+    if value.bit(bit) {
+        value | !mask
+    } else {
+        value & mask
+    }
+}
+
+fn shift_left(value: U256, shift: U256) -> U256 {
+    match shift.saturating_to::<usize>() {
+        shift if shift < 256 => value << shift,
+        _ => U256::ZERO,
+    }
+}
+
+fn shift_right(value: U256, shift: U256) -> U256 {
+    match shift.saturating_to::<usize>() {
+        shift if shift < 256 => value >> shift,
+        _ => U256::ZERO,
+    }
+}
+
+fn shift_right_signed(value: U256, shift: U256) -> U256 {
+    let shift: usize = shift.saturating_to::<usize>().min(255);
+    if is_negative(value) {
+        !((!value) >> shift)
+    } else {
+        value >> shift
+    }
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    let mut hash: [u8; 32] = [0; 32];
+    let mut keccak: Keccak = Keccak::v256();
+    keccak.update(data);
+    keccak.finalize(&mut hash);
+    hash
+}
+
+pub const fn address_from_low_u64(value: u64) -> Address {
+    let bytes: [u8; 8] = value.to_be_bytes();
+    let mut address: Address = [0; 20];
+    let mut index: usize = 0;
+    while index < 8 {
+        address[12 + index] = bytes[index];
+        index += 1;
+    }
+    address
+}
+
+pub fn address_to_word(address: &Address) -> U256 {
+    U256::from_be_slice(address)
+}
+
+pub fn word_to_address(word: U256) -> Address {
+    let bytes: [u8; 32] = word.to_be_bytes::<32>();
+    let mut address: Address = [0; 20];
+    address.copy_from_slice(&bytes[12..]);
+    address
+}
+
+// keccak256(rlp([sender, nonce]))[12..]
+fn create_address(sender: &Address, nonce: u64) -> Address {
+    let mut nonce_rlp: Vec<u8> = Vec::new();
+    match nonce {
+        0 => nonce_rlp.push(0x80),
+        1..=0x7f => nonce_rlp.push(nonce as u8),
+        _ => {
+            let bytes: Vec<u8> = nonce
+                .to_be_bytes()
+                .into_iter()
+                .skip_while(|byte| *byte == 0)
+                .collect();
+            nonce_rlp.push(0x80 + bytes.len() as u8);
+            nonce_rlp.extend_from_slice(&bytes);
+        }
+    }
+    let mut rlp: Vec<u8> = vec![0xc0 + (21 + nonce_rlp.len()) as u8, 0x94];
+    rlp.extend_from_slice(sender);
+    rlp.extend_from_slice(&nonce_rlp);
+    word_to_address(U256::from_be_bytes(keccak256(&rlp)))
+}
+
+// keccak256(0xff ++ sender ++ salt ++ keccak256(init_code))[12..]
+fn create2_address(sender: &Address, salt: U256, init_code: &[u8]) -> Address {
+    let mut preimage: Vec<u8> = vec![0xff];
+    preimage.extend_from_slice(sender);
+    preimage.extend_from_slice(&salt.to_be_bytes::<32>());
+    preimage.extend_from_slice(&keccak256(init_code));
+    word_to_address(U256::from_be_bytes(keccak256(&preimage)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::hex::{decode_hex, encode_hex};
+
+    const CALLER: Address = address_from_low_u64(0xca11);
+    const CONTRACT: Address = address_from_low_u64(0xc0de);
+
+    fn install(evm: &mut Evm, address: Address, code: &str) {
+        evm.state.accounts.insert(
+            address,
+            Account {
+                code: decode_hex(code).unwrap(),
+                ..Account::default()
+            },
+        );
+    }
+
+    // call `code` installed at CONTRACT with empty calldata
+    fn run(code: &str) -> (Evm, ExecutionResult) {
+        let mut evm: Evm = Evm::new();
+        install(&mut evm, CONTRACT, code);
+        let result: ExecutionResult = evm.call(CALLER, CONTRACT, U256::ZERO, &[]);
+        (evm, result)
+    }
+
+    fn error(code: &str) -> String {
+        let (_, result) = run(code);
+        assert!(!result.success);
+        result.error.unwrap()
+    }
+
+    // the word `code` leaves on top of the stack, returned through memory
+    fn word(code: &str) -> U256 {
+        let (_, result) = run(&format!("{}60005260206000f3", code));
+        assert!(result.success, "{}: {:?}", code, result.error);
+        U256::from_be_slice(&result.output)
+    }
+
+    // PUSH32 of a negative number
+    fn push_negative(value: u64) -> String {
+        format!(
+            "7f{}",
+            encode_hex(&U256::from(value).wrapping_neg().to_be_bytes::<32>())
+        )
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(word("6003600201"), U256::from(5));
+        assert_eq!(word("6001600003"), U256::MAX);
+        assert_eq!(word("6000600104"), U256::ZERO);
+        assert_eq!(word("6008600a600a08"), U256::from(4));
+        assert_eq!(word("6008600a600a09"), U256::from(4));
+        assert_eq!(word("600a60020a"), U256::from(1024));
+        // -8 / 3 and -8 % 3 round towards zero
+        assert_eq!(
+            word(&format!("6003{}05", push_negative(8))),
+            U256::from(2).wrapping_neg()
+        );
+        assert_eq!(
+            word(&format!("6003{}07", push_negative(8))),
+            U256::from(2).wrapping_neg()
+        );
+        assert_eq!(word(&format!("6000{}05", push_negative(8))), U256::ZERO);
+    }
+
+    #[test]
+    fn comparisons_and_bits() {
+        assert_eq!(word(&format!("6001{}12", push_negative(1))), U256::from(1));
+        assert_eq!(word(&format!("6001{}10", push_negative(1))), U256::ZERO);
+        assert_eq!(word("60ff60000b"), U256::MAX);
+        assert_eq!(word("607f60000b"), U256::from(0x7f));
+        assert_eq!(word("611234601f1a"), U256::from(0x34));
+        assert_eq!(word("61123460201a"), U256::ZERO);
+        assert_eq!(word("600160041b"), U256::from(16));
+        assert_eq!(word("60016101001c"), U256::ZERO);
+        assert_eq!(word(&format!("{}60041d", push_negative(16))), U256::MAX);
+        assert_eq!(word(&format!("{}6101001d", push_negative(16))), U256::MAX);
+    }
+
+    #[test]
+    fn environment() {
+        let mut evm: Evm = Evm::new();
+        evm.set_balance(CALLER, U256::from(100));
+        // CALLER CALLVALUE CALLDATASIZE, PUSH1 1 CALLDATALOAD, CODESIZE GAS, stored in memory
+        install(
+            &mut evm,
+            CONTRACT,
+            "336000523460205236604052600135606052386080525a60a05260c06000f3",
+        );
+        let result: ExecutionResult = evm.call(CALLER, CONTRACT, U256::from(7), &[0xaa, 0xbb]);
+        let words: Vec<U256> = result.output.chunks(32).map(U256::from_be_slice).collect();
+        assert_eq!(
+            words,
+            vec![
+                address_to_word(&CALLER),
+                U256::from(7),
+                U256::from(2),
+                // the byte past the calldata reads as zero
+                U256::from(0xbb) << 248,
+                U256::from(31),
+                U256::from(30_000_000),
+            ]
+        );
+        assert_eq!(evm.account(&CONTRACT).unwrap().balance, U256::from(7));
+        assert_eq!(evm.account(&CALLER).unwrap().balance, U256::from(93));
+    }
+
+    #[test]
+    fn memory_and_transient_storage() {
+        // MSTORE8 expands memory by a whole word
+        assert_eq!(word("60ff601f5359"), U256::from(32));
+        assert_eq!(word("60ff601f535f51"), U256::from(0xff));
+        assert_eq!(word("602a60015d60015c"), U256::from(42));
+        // MCOPY of the stored word one word up
+        assert_eq!(word("602a5f5260205f60205e602051"), U256::from(42));
+    }
+
+    #[test]
+    fn limits() {
+        assert_eq!(error("01"), "stack underflow at 0x1");
+        assert_eq!(error("80"), "stack underflow at 0x0");
+        assert_eq!(error(&"5f".repeat(1025)), "stack overflow at 0x401");
+        assert_eq!(error("630200000051"), "memory limit exceeded at 0x6");
+        assert_eq!(error("fe"), "invalid opcode 0xfe at 0x0");
+        assert_eq!(error("6001600060003e"), "return data out of bounds at 0x6");
+
+        let mut evm: Evm = Evm::new();
+        let result: ExecutionResult = evm.deploy(CALLER, U256::ZERO, &vec![0; 0xc001]);
+        assert_eq!(result.error.as_deref(), Some("initcode too large"));
+        // the nonce is used up even so
+        assert_eq!(evm.account(&CALLER).unwrap().nonce, 1);
+    }
+
+    #[test]
+    fn static_calls_cannot_change_state() {
+        const TARGET: Address = address_from_low_u64(0x0b);
+        // returns the success flag of a STATICCALL, then of a CALL, to TARGET which stores
+        let caller_code: &str = "5f5f5f5f600b5afa5f525f5f5f5f5f600b5af160205260405ff3";
+        let mut evm: Evm = Evm::new();
+        install(&mut evm, CONTRACT, caller_code);
+        install(&mut evm, TARGET, "600160005500");
+        let result: ExecutionResult = evm.call(CALLER, CONTRACT, U256::ZERO, &[]);
+        assert!(result.success);
+        assert_eq!(U256::from_be_slice(&result.output[..32]), U256::ZERO);
+        assert_eq!(U256::from_be_slice(&result.output[32..]), U256::from(1));
+        assert_eq!(evm.account(&TARGET).unwrap().storage.len(), 1);
+    }
+
+    #[test]
+    fn hashes() {
+        assert_eq!(
+            encode_hex(&word("6000600020").to_be_bytes::<32>()),
+            "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+        );
+    }
+
+    #[test]
+    fn jumps_only_into_jumpdests() {
+        assert!(run("6003565b00").1.success);
+        // the 0x5b at offset 4 is PUSH data
+        let (_, result) = run("600456605b00");
+        assert!(!result.success);
+        assert_eq!(
+            result.error.as_deref(),
+            Some("invalid jump destination 0x4")
+        );
+    }
+
+    #[test]
+    fn revert_rolls_back_storage() {
+        let (evm, result) = run("600160005560aa60005260206000fd");
+        assert!(!result.success);
+        assert_eq!(U256::from_be_slice(&result.output), U256::from(0xaa));
+        assert!(evm.account(&CONTRACT).unwrap().storage.is_empty());
+
+        let (evm, result) = run("600160005500");
+        assert!(result.success);
+        assert_eq!(
+            evm.account(&CONTRACT).unwrap().storage.get(&U256::ZERO),
+            Some(&U256::from(1))
+        );
+    }
+
+    #[test]
+    fn logs() {
+        let (_, result) = run("60aa600052600160206000a1");
+        assert_eq!(result.logs.len(), 1);
+        assert_eq!(result.logs[0].address, CONTRACT);
+        assert_eq!(result.logs[0].topics, vec![U256::from(1)]);
+        assert_eq!(U256::from_be_slice(&result.logs[0].data), U256::from(0xaa));
+    }
+
+    #[test]
+    fn deploys_and_calls() {
+        let runtime: Vec<u8> = decode_hex("600160005260206000f3").unwrap();
+        let mut init_code: Vec<u8> = decode_hex("600a80600b6000396000f3").unwrap();
+        init_code.extend_from_slice(&runtime);
+
+        let mut evm: Evm = Evm::new();
+        let deployed: ExecutionResult = evm.deploy(CALLER, U256::ZERO, &init_code);
+        let address: Address = deployed.created.unwrap();
+        assert_eq!(address, create_address(&CALLER, 0));
+        assert_eq!(evm.account(&address).unwrap().code, runtime);
+
+        let result: ExecutionResult = evm.call(CALLER, address, U256::ZERO, &[]);
+        assert_eq!(U256::from_be_slice(&result.output), U256::from(1));
+    }
+
+    #[test]
+    fn rejects_oversized_code() {
+        let mut evm: Evm = Evm::new();
+        let result: ExecutionResult =
+            evm.deploy(CALLER, U256::ZERO, &decode_hex("6160016000f3").unwrap());
+        assert!(result.created.is_none());
+        assert_eq!(result.error.as_deref(), Some("deployed code too large"));
+    }
+
+    #[test]
+    fn stops_infinite_loops() {
+        let (_, result) = run("5b600056");
+        assert_eq!(result.error.as_deref(), Some("step limit exceeded"));
+    }
+
+    #[test]
+    fn contract_addresses() {
+        let sender: Address = word_to_address(U256::from_be_slice(
+            &decode_hex("6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0").unwrap(),
+        ));
+        assert_eq!(
+            encode_hex(&create_address(&sender, 0)),
+            "cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d"
+        );
+        assert_eq!(
+            encode_hex(&create_address(&sender, 1)),
+            "343c43a37d37dff08ae8c4a11544c718abb4fcf8"
+        );
+        // EIP-1014 examples
+        assert_eq!(
+            encode_hex(&create2_address(&[0; 20], U256::ZERO, &[0x00])),
+            "4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38"
+        );
+        let deployer: Address = word_to_address(U256::from_be_slice(
+            &decode_hex("deadbeef00000000000000000000000000000000").unwrap(),
+        ));
+        assert_eq!(
+            encode_hex(&create2_address(&deployer, U256::ZERO, &[0x00])),
+            "b928f69bb1d91cd65274e3c79d8986362984fda3"
+        );
+    }
+}
@@ -0,0 +1,2 @@
+pub mod equivalence;
+pub mod interpreter;
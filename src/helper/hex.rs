@@ -0,0 +1,42 @@
+use crate::error::ObfuscatorError;
+
+// function to convert a hex string (with or without 0x) into raw bytes
+pub fn decode_hex(hex: &str) -> Result<Vec<u8>, ObfuscatorError> {
+    let hex: &str = hex.trim();
+    let hex: &str = hex.strip_prefix("0x").unwrap_or(hex);
+
+    if !hex.len().is_multiple_of(2) {
+        return Err(ObfuscatorError::OddLengthHex { length: hex.len() });
+    }
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(hex.len() / 2);
+    for position in (0..hex.len()).step_by(2) {
+        let high: u8 = hex_digit(hex, position)?;
+        let low: u8 = hex_digit(hex, position + 1)?;
+        bytes.push((high << 4) | low);
+    }
+    Ok(bytes)
+}
+
+// function to convert raw bytes into a lowercase hex string without 0x
+pub fn encode_hex(bytes: &[u8]) -> String {
+    let mut hex: String = String::with_capacity(bytes.len() * 2);
+    for byte in bytes {
+        hex.push_str(&format!("{:02x}", byte));
+    }
+    hex
+}
+
+// every digit before `position` is ASCII, so `position` is always a char boundary
+pub(crate) fn hex_digit(hex: &str, position: usize) -> Result<u8, ObfuscatorError> {
+    let c: u8 = hex.as_bytes()[position];
+    match c {
+        b'0'..=b'9' => Ok(c - b'0'),
+        b'a'..=b'f' => Ok(c - b'a' + 10),
+        b'A'..=b'F' => Ok(c - b'A' + 10),
+        _ => Err(ObfuscatorError::InvalidHexCharacter {
+            position,
+            character: hex[position..].chars().next().unwrap_or('?'),
+        }),
+    }
+}
@@ -0,0 +1,376 @@
+use crate::error::ObfuscatorError;
+use crate::helper::hex::{decode_hex, encode_hex, hex_digit};
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use std::fmt;
+use std::str::FromStr;
+use tiny_keccak::{Hasher, Keccak};
+
+// length of a library address, and of its placeholder in hex characters
+const ADDRESS_LENGTH: usize = 20;
+const PLACEHOLDER_LENGTH: usize = 2 * ADDRESS_LENGTH;
+
+// A library address left out of unlinked bytecode: `placeholder` is the text standing in
+// for it (`__$<34 hex>$__`, or `__<name>___...` before solc 0.5) and `start` the byte
+// offset of the 20 bytes it replaces
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LinkReference {
+    pub placeholder: String,
+    pub start: usize,
+    pub length: usize,
+}
+
+// A library to link, by fully qualified name (`file.sol:Name`) or by the placeholder's hash
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Library {
+    pub name: String,
+    pub address: [u8; ADDRESS_LENGTH],
+}
+
+impl Library {
+    // whether the placeholder stands for this library
+    pub fn matches(&self, placeholder: &str) -> bool {
+        placeholder == library_placeholder(&self.name)
+            || placeholder == legacy_placeholder(&self.name)
+            || placeholder == format!("__${}$__", self.name)
+            || placeholder == self.name
+    }
+}
+
+impl fmt::Display for Library {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}=0x{}", self.name, encode_hex(&self.address))
+    }
+}
+
+// `<name>=<address>`, the format of solc's --libraries
+impl FromStr for Library {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, address) = s
+            .rsplit_once('=')
+            .ok_or_else(|| format!("expected <name>=<address>: {}", s))?;
+        let address: Vec<u8> = decode_hex(address).map_err(|err| err.to_string())?;
+        let address: [u8; ADDRESS_LENGTH] = address
+            .try_into()
+            .map_err(|_| format!("library address is not 20 bytes: {}", s))?;
+        Ok(Library {
+            name: name.trim().to_string(),
+            address,
+        })
+    }
+}
+
+// placeholder solc >= 0.5 emits: the first 17 bytes of keccak256 of the qualified name
+pub fn library_placeholder(name: &str) -> String {
+    let mut hash: [u8; 32] = [0; 32];
+    let mut keccak: Keccak = Keccak::v256();
+    keccak.update(name.as_bytes());
+    keccak.finalize(&mut hash);
+    format!("__${}$__", encode_hex(&hash[..17]))
+}
+
+// placeholder of older compilers: the name cut to 36 characters and padded with '_'
+pub fn legacy_placeholder(name: &str) -> String {
+    let name: String = name.chars().take(PLACEHOLDER_LENGTH - 4).collect();
+    format!("__{:_<width$}", name, width = PLACEHOLDER_LENGTH - 2)
+}
+
+// decode hex that may contain library placeholders; each one becomes 20 zero bytes and a
+// LinkReference, so the code decodes as if linked against the zero address
+pub fn decode_unlinked_hex(hex: &str) -> Result<(Vec<u8>, Vec<LinkReference>), ObfuscatorError> {
+    let hex: &str = hex.trim();
+    let hex: &str = hex.strip_prefix("0x").unwrap_or(hex);
+
+    let mut bytes: Vec<u8> = Vec::with_capacity(hex.len() / 2);
+    let mut references: Vec<LinkReference> = Vec::new();
+    let mut position: usize = 0;
+    while position < hex.len() {
+        if hex[position..].starts_with("__") {
+            let placeholder: &str = hex
+                .get(position..position + PLACEHOLDER_LENGTH)
+                .filter(|placeholder| placeholder.ends_with("__"))
+                .ok_or_else(|| {
+                    ObfuscatorError::InvalidLinkReference(format!(
+                        "library placeholder at position {} is cut off",
+                        position
+                    ))
+                })?;
+            references.push(LinkReference {
+                placeholder: placeholder.to_string(),
+                start: bytes.len(),
+                length: ADDRESS_LENGTH,
+            });
+            bytes.extend_from_slice(&[0; ADDRESS_LENGTH]);
+            position += PLACEHOLDER_LENGTH;
+            continue;
+        }
+        if position + 1 == hex.len() {
+            return Err(ObfuscatorError::OddLengthHex { length: hex.len() });
+        }
+        let high: u8 = hex_digit(hex, position)?;
+        let low: u8 = hex_digit(hex, position + 1)?;
+        bytes.push((high << 4) | low);
+        position += 2;
+    }
+    Ok((bytes, references))
+}
+
+// hex without 0x, with the placeholder of every unlinked reference put back
+pub fn encode_unlinked_hex(bytes: &[u8], references: &[LinkReference]) -> String {
+    let mut hex: String = encode_hex(bytes);
+    for reference in references {
+        let range: std::ops::Range<usize> =
+            2 * reference.start..2 * (reference.start + reference.length);
+        if range.end <= hex.len() && reference.placeholder.len() == range.len() {
+            hex.replace_range(range, &reference.placeholder);
+        }
+    }
+    hex
+}
+
+// write library addresses over their placeholders; returns the references left unlinked
+pub fn link(
+    bytes: &mut [u8],
+    references: &[LinkReference],
+    libraries: &[Library],
+) -> Result<Vec<LinkReference>, ObfuscatorError> {
+    let mut unlinked: Vec<LinkReference> = Vec::new();
+    for reference in references {
+        let Some(library) = libraries
+            .iter()
+            .find(|library| library.matches(&reference.placeholder))
+        else {
+            unlinked.push(reference.clone());
+            continue;
+        };
+        let slot: &mut [u8] = bytes
+            .get_mut(reference.start..reference.start + reference.length)
+            .filter(|slot| slot.len() == library.address.len())
+            .ok_or_else(|| {
+                ObfuscatorError::InvalidLinkReference(format!(
+                    "{} at {:#x} is not a 20 byte slot of the code",
+                    reference.placeholder, reference.start
+                ))
+            })?;
+        slot.copy_from_slice(&library.address);
+    }
+    Ok(unlinked)
+}
+
+// A link reference whose PUSH20 carries a label, in the initcode or in the runtime
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LabelledLink {
+    pub reference: LinkReference,
+    pub in_runtime: bool,
+    pub label: usize,
+}
+
+// label the PUSH holding each placeholder; offsets are those of the creation code, which
+// is the initcode followed by the runtime
+pub fn label_link_references(
+    init_code: &mut Program,
+    runtime: &mut Program,
+    references: &[LinkReference],
+) -> Result<Vec<LabelledLink>, ObfuscatorError> {
+    let init_length: usize = init_code.len();
+    let mut labelled: Vec<LabelledLink> = Vec::new();
+    for reference in references {
+        let in_runtime: bool = reference.start >= init_length;
+        let (program, start): (&mut Program, usize) = if in_runtime {
+            (&mut *runtime, reference.start - init_length)
+        } else {
+            (&mut *init_code, reference.start)
+        };
+        let index: Option<usize> = start
+            .checked_sub(1)
+            .and_then(|offset| program.index_at_offset(offset))
+            .filter(|index| {
+                let instruction: &Instruction = &program.instructions[*index];
+                instruction.is_push() && instruction.push_width() == reference.length
+            });
+        let Some(index) = index else {
+            return Err(ObfuscatorError::InvalidLinkReference(format!(
+                "{} at {:#x} is not the data of a PUSH{}",
+                reference.placeholder, reference.start, reference.length
+            )));
+        };
+        labelled.push(LabelledLink {
+            reference: reference.clone(),
+            in_runtime,
+            label: program.label_at(index),
+        });
+    }
+    Ok(labelled)
+}
+
+// the references at their offsets in the assembled creation code
+pub fn relocate_link_references(
+    init_code: &Program,
+    runtime: &Program,
+    references: &[LabelledLink],
+) -> Result<Vec<LinkReference>, ObfuscatorError> {
+    let mut relocated: Vec<LinkReference> = Vec::new();
+    for labelled in references {
+        let (program, base): (&Program, usize) = if labelled.in_runtime {
+            (runtime, init_code.len())
+        } else {
+            (init_code, 0)
+        };
+        let offset: usize = program
+            .label_offset(labelled.label)
+            .ok_or(ObfuscatorError::UndefinedLabel(labelled.label))?;
+        relocated.push(LinkReference {
+            start: base + offset + 1,
+            ..labelled.reference.clone()
+        });
+    }
+    Ok(relocated)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NAME: &str = "src/Math.sol:Math";
+
+    fn address(byte: u8) -> [u8; ADDRESS_LENGTH] {
+        [byte; ADDRESS_LENGTH]
+    }
+
+    #[test]
+    fn placeholders_have_the_width_of_an_address() {
+        let placeholder: String = library_placeholder(NAME);
+        assert_eq!(placeholder.len(), PLACEHOLDER_LENGTH);
+        assert!(placeholder.starts_with("__$") && placeholder.ends_with("$__"));
+        assert_eq!(
+            legacy_placeholder(NAME),
+            format!("__{}{}", NAME, "_".repeat(21))
+        );
+        let long: String = "L".repeat(50);
+        assert_eq!(legacy_placeholder(&long), format!("__{}__", "L".repeat(36)));
+    }
+
+    #[test]
+    fn libraries_match_every_form_of_their_placeholder() {
+        let library: Library = format!("{}=0x{}", NAME, "11".repeat(20)).parse().unwrap();
+        assert_eq!(library.address, address(0x11));
+        assert!(library.matches(&library_placeholder(NAME)));
+        assert!(library.matches(&legacy_placeholder(NAME)));
+        assert!(!library.matches(&library_placeholder("src/Math.sol:Other")));
+
+        // a library named by the hash in its placeholder
+        let placeholder: String = library_placeholder(NAME);
+        let hash: &str = &placeholder[3..placeholder.len() - 3];
+        let library: Library = format!("{}={}", hash, "22".repeat(20)).parse().unwrap();
+        assert!(library.matches(&placeholder));
+
+        assert!("Math".parse::<Library>().is_err());
+        assert!("Math=0x1234".parse::<Library>().is_err());
+    }
+
+    #[test]
+    fn unlinked_hex_round_trips() {
+        // PUSH20 <placeholder> STOP
+        let placeholder: String = library_placeholder(NAME);
+        let hex: String = format!("0x73{}00", placeholder);
+        let (bytes, references) = decode_unlinked_hex(&hex).unwrap();
+        let mut expected: Vec<u8> = vec![0x73];
+        expected.extend_from_slice(&[0; ADDRESS_LENGTH]);
+        expected.push(0x00);
+        assert_eq!(bytes, expected);
+        assert_eq!(
+            references,
+            vec![LinkReference {
+                placeholder,
+                start: 1,
+                length: ADDRESS_LENGTH,
+            }]
+        );
+        assert_eq!(
+            format!("0x{}", encode_unlinked_hex(&bytes, &references)),
+            hex
+        );
+    }
+
+    #[test]
+    fn rejects_cut_off_placeholders() {
+        let placeholder: String = library_placeholder(NAME);
+        let cut: String = format!("73{}", &placeholder[..30]);
+        assert!(matches!(
+            decode_unlinked_hex(&cut),
+            Err(ObfuscatorError::InvalidLinkReference(_))
+        ));
+        assert!(matches!(
+            decode_unlinked_hex("731"),
+            Err(ObfuscatorError::OddLengthHex { length: 3 })
+        ));
+    }
+
+    #[test]
+    fn links_known_libraries_and_keeps_the_rest() {
+        let other: String = library_placeholder("src/Other.sol:Other");
+        let hex: String = format!("73{}73{}00", library_placeholder(NAME), other);
+        let (mut bytes, references) = decode_unlinked_hex(&hex).unwrap();
+        let libraries: Vec<Library> = vec![Library {
+            name: String::from(NAME),
+            address: address(0x33),
+        }];
+        let unlinked: Vec<LinkReference> = link(&mut bytes, &references, &libraries).unwrap();
+        assert_eq!(bytes[1..21], address(0x33));
+        assert_eq!(bytes[22..42], [0; ADDRESS_LENGTH]);
+        assert_eq!(unlinked, vec![references[1].clone()]);
+
+        // a reference past the end of the code
+        let outside: LinkReference = LinkReference {
+            start: 40,
+            ..references[0].clone()
+        };
+        assert!(matches!(
+            link(&mut bytes, &[outside], &libraries),
+            Err(ObfuscatorError::InvalidLinkReference(_))
+        ));
+    }
+
+    #[test]
+    fn placeholders_move_with_their_push() {
+        // initcode `PUSH20 <placeholder> POP`, runtime `PUSH20 <placeholder> STOP`
+        let placeholder: String = library_placeholder(NAME);
+        let hex: String = format!("73{}5073{}00", placeholder, placeholder);
+        let (bytes, references) = decode_unlinked_hex(&hex).unwrap();
+        let mut init_code: Program = Program::decode(&bytes);
+        let mut runtime: Program = init_code.split_off(2);
+        let labelled: Vec<LabelledLink> =
+            label_link_references(&mut init_code, &mut runtime, &references).unwrap();
+        assert_eq!(
+            labelled
+                .iter()
+                .map(|link| link.in_runtime)
+                .collect::<Vec<bool>>(),
+            vec![false, true]
+        );
+
+        // one byte more in each part
+        init_code.instructions.insert(0, Instruction::new(0x5b));
+        runtime.instructions.insert(0, Instruction::new(0x5b));
+        init_code.assemble().unwrap();
+        runtime.assemble().unwrap();
+        let starts: Vec<usize> = relocate_link_references(&init_code, &runtime, &labelled)
+            .unwrap()
+            .iter()
+            .map(|reference| reference.start)
+            .collect();
+        assert_eq!(starts, vec![2, 25]);
+
+        // a placeholder that is not PUSH data
+        let misplaced: LinkReference = LinkReference {
+            start: 3,
+            ..references[0].clone()
+        };
+        assert!(matches!(
+            label_link_references(&mut init_code, &mut runtime, &[misplaced]),
+            Err(ObfuscatorError::InvalidLinkReference(_))
+        ));
+    }
+}
@@ -1 +1,3 @@
 pub mod bytecode;
+pub mod hex;
+pub mod link;
@@ -0,0 +1,178 @@
+use crate::constant::opcodes::{self, OpcodeInfo};
+use crate::error::ObfuscatorError;
+use std::fmt;
+
+// A single decoded EVM instruction. `offset` is the byte offset of the opcode
+// inside its program and `immediate` holds the PUSH data (empty otherwise).
+// `label` names an instruction (usually a JUMPDEST) and a PUSH whose `target` is set
+// pushes the offset of the instruction with that label once the program is assembled.
+// `origin` is the index the instruction had in the input, None for inserted code.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Instruction {
+    pub offset: usize,
+    pub opcode: u8,
+    pub immediate: Vec<u8>,
+    pub label: Option<usize>,
+    pub target: Option<usize>,
+    pub origin: Option<usize>,
+}
+
+impl Instruction {
+    pub fn new(opcode: u8) -> Self {
+        Instruction {
+            offset: 0,
+            opcode,
+            immediate: Vec::new(),
+            label: None,
+            target: None,
+            origin: None,
+        }
+    }
+
+    // build a PUSH holding `value`, at least `width` bytes wide
+    pub fn push(value: usize, width: usize) -> Self {
+        let mut instruction: Instruction = Instruction::new(0x5f + width as u8);
+        instruction.immediate = vec![0; width];
+        instruction.resize_push(value);
+        instruction
+    }
+
+    // build a PUSH<width> of the offset of the instruction labelled `target`
+    pub fn push_label(target: usize, width: usize) -> Self {
+        let mut instruction: Instruction = Instruction::push(0, width);
+        instruction.target = Some(target);
+        instruction
+    }
+
+    // size of the instruction in bytes, opcode included
+    pub fn size(&self) -> usize {
+        1 + self.immediate.len()
+    }
+
+    pub fn info(&self) -> Option<&'static OpcodeInfo> {
+        opcodes::opcode_info(self.opcode)
+    }
+
+    pub fn is_push(&self) -> bool {
+        (0x5f..=0x7f).contains(&self.opcode)
+    }
+
+    pub fn push_width(&self) -> usize {
+        opcodes::immediate_size(self.opcode)
+    }
+
+    // value pushed by this instruction, if it is a PUSH and the value fits in usize
+    pub fn push_value(&self) -> Option<usize> {
+        if !self.is_push() {
+            return None;
+        }
+        let mut value: usize = 0;
+        for byte in &self.immediate {
+            if value > usize::MAX >> 8 {
+                return None;
+            }
+            value = (value << 8) | *byte as usize;
+        }
+        Some(value)
+    }
+
+    // overwrite the pushed value in place; the size of the instruction never changes, so a
+    // value that needs a wider PUSH is an error rather than a shift of every later byte
+    pub fn set_push_value(&mut self, value: usize) -> Result<(), ObfuscatorError> {
+        let width: usize = self.push_width();
+        if value_width(value) > width {
+            return Err(ObfuscatorError::PushValueOverflow {
+                offset: self.offset,
+                value,
+                width,
+            });
+        }
+        self.write_push_value(value, width);
+        Ok(())
+    }
+
+    // overwrite the pushed value, widening the PUSH when the value does not fit; returns
+    // whether the instruction grew (and every later offset moved)
+    pub fn resize_push(&mut self, value: usize) -> bool {
+        let width: usize = self.push_width();
+        let needed: usize = value_width(value).max(width);
+        self.write_push_value(value, needed);
+        needed != width
+    }
+
+    fn write_push_value(&mut self, value: usize, width: usize) {
+        let bytes: [u8; 8] = (value as u64).to_be_bytes();
+        self.immediate = vec![0; width];
+        for (index, byte) in bytes.iter().rev().take(width).enumerate() {
+            self.immediate[width - 1 - index] = *byte;
+        }
+        self.opcode = 0x5f + width as u8;
+    }
+
+    pub fn name(&self) -> String {
+        match opcodes::get_opcode_name(self.opcode) {
+            Some(name) => name.to_string(),
+            None => format!("UNKNOWN_0x{:02x}", self.opcode),
+        }
+    }
+}
+
+// minimum number of PUSH data bytes needed to hold `value`
+fn value_width(value: usize) -> usize {
+    (usize::BITS - value.leading_zeros()).div_ceil(8) as usize
+}
+
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04x}: {}", self.offset, self.name())?;
+        if !self.immediate.is_empty() {
+            write!(f, " 0x")?;
+            for byte in &self.immediate {
+                write!(f, "{:02x}", byte)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn push_takes_the_narrowest_width_at_least_asked_for() {
+        assert_eq!(Instruction::push(0x12, 1).immediate, vec![0x12]);
+        assert_eq!(Instruction::push(0x12, 2).immediate, vec![0x00, 0x12]);
+        let wide: Instruction = Instruction::push(0x12345, 1);
+        assert_eq!(wide.opcode, 0x62);
+        assert_eq!(wide.push_value(), Some(0x12345));
+        assert_eq!(Instruction::push(0, 0).opcode, 0x5f);
+    }
+
+    #[test]
+    fn resize_reports_growth() {
+        let mut push: Instruction = Instruction::push(0xff, 1);
+        assert!(!push.resize_push(0x01));
+        assert_eq!(push.immediate, vec![0x01]);
+        assert!(push.resize_push(0x100));
+        assert_eq!(push.opcode, 0x61);
+        assert_eq!(push.size(), 3);
+    }
+
+    #[test]
+    fn set_push_value_keeps_the_width() {
+        let mut push: Instruction = Instruction::push(0, 1);
+        push.offset = 0x20;
+        push.set_push_value(0xff).unwrap();
+        assert_eq!(push.immediate, vec![0xff]);
+        assert_eq!(
+            push.set_push_value(0x100),
+            Err(ObfuscatorError::PushValueOverflow {
+                offset: 0x20,
+                value: 0x100,
+                width: 1,
+            })
+        );
+        assert_eq!(push.immediate, vec![0xff]);
+    }
+}
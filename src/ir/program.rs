@@ -0,0 +1,376 @@
+use crate::constant::opcodes::immediate_size;
+use crate::error::ObfuscatorError;
+use crate::ir::instruction::Instruction;
+use std::collections::BTreeMap;
+use std::fmt;
+
+// A linear sequence of decoded instructions. Offsets are kept in sync with the
+// encoded layout so passes can map between byte offsets and instruction indices.
+// Labels are handed out by a counter and looked up in a map from label to instruction
+// index; `push` and `label_at` keep the map current, any other edit of `instructions`
+// has to be followed by `reindex` before labels are looked up again.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    pub instructions: Vec<Instruction>,
+    // every label and target in use is below it
+    next_label: usize,
+    labels: BTreeMap<usize, usize>,
+}
+
+impl Program {
+    // decode raw bytecode in a single pass; truncated PUSH data at the end is
+    // kept as-is so that encoding gives back the exact input bytes
+    pub fn decode(bytes: &[u8]) -> Self {
+        let mut instructions: Vec<Instruction> = Vec::new();
+        let mut offset: usize = 0;
+
+        while offset < bytes.len() {
+            let opcode: u8 = bytes[offset];
+            let data_end: usize = (offset + 1 + immediate_size(opcode)).min(bytes.len());
+            instructions.push(Instruction {
+                offset,
+                opcode,
+                immediate: bytes[offset + 1..data_end].to_vec(),
+                label: None,
+                target: None,
+                origin: None,
+            });
+            offset = data_end;
+        }
+
+        Program {
+            instructions,
+            next_label: 0,
+            labels: BTreeMap::new(),
+        }
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        let mut bytes: Vec<u8> = Vec::with_capacity(self.len());
+        for instruction in &self.instructions {
+            bytes.push(instruction.opcode);
+            bytes.extend_from_slice(&instruction.immediate);
+        }
+        bytes
+    }
+
+    // length of the encoded program in bytes
+    pub fn len(&self) -> usize {
+        match self.instructions.last() {
+            Some(last) => last.offset + last.size(),
+            None => 0,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
+
+    // append an instruction at the end and return its byte offset
+    pub fn push(&mut self, mut instruction: Instruction) -> usize {
+        let offset: usize = self.len();
+        instruction.offset = offset;
+        self.reserve_labels(&instruction);
+        if let Some(label) = instruction.label {
+            self.labels.insert(label, self.instructions.len());
+        }
+        self.instructions.push(instruction);
+        offset
+    }
+
+    // keep the counter above the labels an instruction carries or targets
+    fn reserve_labels(&mut self, instruction: &Instruction) {
+        for label in [instruction.label, instruction.target]
+            .into_iter()
+            .flatten()
+        {
+            self.next_label = self.next_label.max(label + 1);
+        }
+    }
+
+    // append every instruction of another program at the end; its labels are renumbered
+    // so they cannot collide with the labels already used here
+    pub fn extend(&mut self, other: Program) {
+        let base: usize = self.next_label;
+        self.next_label += other.next_label;
+        for mut instruction in other.instructions {
+            instruction.label = instruction.label.map(|label| label + base);
+            instruction.target = instruction.target.map(|target| target + base);
+            self.push(instruction);
+        }
+    }
+
+    // remember the current index of every instruction as its origin
+    pub fn mark_origins(&mut self) {
+        for (index, instruction) in self.instructions.iter_mut().enumerate() {
+            instruction.origin = Some(index);
+        }
+    }
+
+    // origin of every instruction, in order
+    pub fn origins(&self) -> Vec<Option<usize>> {
+        self.instructions
+            .iter()
+            .map(|instruction| instruction.origin)
+            .collect()
+    }
+
+    // a label id not handed out before
+    pub fn fresh_label(&mut self) -> usize {
+        let label: usize = self.next_label;
+        self.next_label += 1;
+        label
+    }
+
+    // label of the instruction at `index`, labelling it first if needed
+    pub fn label_at(&mut self, index: usize) -> usize {
+        if let Some(label) = self.instructions[index].label {
+            return label;
+        }
+        let label: usize = self.fresh_label();
+        self.instructions[index].label = Some(label);
+        self.labels.insert(label, index);
+        label
+    }
+
+    // index of the instruction carrying `label`
+    pub fn label_index(&self, label: usize) -> Option<usize> {
+        self.labels.get(&label).copied()
+    }
+
+    // offset of the instruction carrying `label`
+    pub fn label_offset(&self, label: usize) -> Option<usize> {
+        self.label_index(label)
+            .map(|index| self.instructions[index].offset)
+    }
+
+    /*  ASSEMBLING
+    1) recompute every offset
+    2) write the offset of its target label into every labelled PUSH
+    3) a PUSH too narrow for its value is widened, which moves all later code: start over
+    widths only ever grow, so this reaches a fixpoint
+    */
+    pub fn assemble(&mut self) -> Result<(), ObfuscatorError> {
+        loop {
+            // 1) recompute offsets
+            self.reindex();
+            let offsets: Vec<usize> = self
+                .instructions
+                .iter()
+                .map(|instruction| instruction.offset)
+                .collect();
+
+            // 2) patch the labelled pushes
+            let mut grew: bool = false;
+            for instruction in &mut self.instructions {
+                let Some(target) = instruction.target else {
+                    continue;
+                };
+                let offset: usize = self
+                    .labels
+                    .get(&target)
+                    .map(|index| offsets[*index])
+                    .ok_or(ObfuscatorError::UndefinedLabel(target))?;
+                // 3) widened: later offsets are stale
+                grew |= instruction.resize_push(offset);
+            }
+
+            if !grew {
+                return Ok(());
+            }
+        }
+    }
+
+    // split the program before the instruction at `index`, rebasing the tail to offset 0;
+    // both halves keep drawing labels from the same range
+    pub fn split_off(&mut self, index: usize) -> Program {
+        let mut tail: Program = Program {
+            instructions: self.instructions.split_off(index),
+            next_label: self.next_label,
+            labels: BTreeMap::new(),
+        };
+        self.reindex();
+        tail.reindex();
+        tail
+    }
+
+    // recompute every offset from the instruction sizes, and the index of every label
+    pub fn reindex(&mut self) {
+        let mut offset: usize = 0;
+        let mut labels: BTreeMap<usize, usize> = BTreeMap::new();
+        let mut next_label: usize = self.next_label;
+        for (index, instruction) in self.instructions.iter_mut().enumerate() {
+            instruction.offset = offset;
+            offset += instruction.size();
+            if let Some(label) = instruction.label {
+                labels.insert(label, index);
+            }
+            for label in [instruction.label, instruction.target]
+                .into_iter()
+                .flatten()
+            {
+                next_label = next_label.max(label + 1);
+            }
+        }
+        self.labels = labels;
+        self.next_label = next_label;
+    }
+
+    // the final instruction when its PUSH data runs past the end of the code
+    pub fn truncated_push(&self) -> Option<&Instruction> {
+        self.instructions
+            .last()
+            .filter(|last| last.immediate.len() < last.push_width())
+    }
+
+    // index of the instruction starting exactly at a byte offset
+    pub fn index_at_offset(&self, offset: usize) -> Option<usize> {
+        self.instructions
+            .binary_search_by_key(&offset, |instruction| instruction.offset)
+            .ok()
+    }
+}
+
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for instruction in &self.instructions {
+            writeln!(f, "{}", instruction)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn jumpdest(label: usize) -> Instruction {
+        let mut jumpdest: Instruction = Instruction::new(0x5b);
+        jumpdest.label = Some(label);
+        jumpdest
+    }
+
+    #[test]
+    fn decode_round_trips() {
+        // PUSH2 0x1234 JUMPDEST STOP, then a PUSH2 cut off by the end of the code
+        let bytes: Vec<u8> = vec![0x61, 0x12, 0x34, 0x5b, 0x00, 0x61, 0xff];
+        let program: Program = Program::decode(&bytes);
+        let offsets: Vec<usize> = program
+            .instructions
+            .iter()
+            .map(|instruction| instruction.offset)
+            .collect();
+        assert_eq!(offsets, vec![0, 3, 4, 5]);
+        assert_eq!(program.instructions[0].push_value(), Some(0x1234));
+        assert_eq!(program.truncated_push().unwrap().immediate, vec![0xff]);
+        assert_eq!(program.len(), bytes.len());
+        assert_eq!(program.encode(), bytes);
+        assert_eq!(program.index_at_offset(4), Some(2));
+        assert_eq!(program.index_at_offset(1), None);
+    }
+
+    #[test]
+    fn labels_follow_their_instruction() {
+        // PUSH1 4 JUMP STOP JUMPDEST, with a JUMPDEST inserted after the JUMP
+        let mut program: Program = Program::decode(&[0x60, 0x04, 0x56, 0x00, 0x5b]);
+        let label: usize = program.label_at(3);
+        assert_eq!(program.label_at(3), label);
+        assert_ne!(program.fresh_label(), label);
+        program.instructions[0].target = Some(label);
+        assert_eq!(program.label_offset(label), Some(4));
+
+        program.instructions.insert(2, Instruction::new(0x5b));
+        program.reindex();
+        assert_eq!(program.label_index(label), Some(4));
+        program.assemble().unwrap();
+        assert_eq!(program.encode(), vec![0x60, 0x05, 0x56, 0x5b, 0x00, 0x5b]);
+    }
+
+    #[test]
+    fn extend_renumbers_labels() {
+        // each program jumps to its own JUMPDEST, both labelled 0
+        let part = || {
+            let mut part: Program = Program::default();
+            part.push(Instruction::push_label(0, 1));
+            part.push(Instruction::new(0x56));
+            part.push(jumpdest(0));
+            part
+        };
+        let mut program: Program = part();
+        program.extend(part());
+        program.assemble().unwrap();
+        assert_eq!(
+            program.encode(),
+            vec![0x60, 0x03, 0x56, 0x5b, 0x60, 0x07, 0x56, 0x5b]
+        );
+        assert_eq!(program.fresh_label(), 2);
+    }
+
+    #[test]
+    fn split_off_shares_the_label_range() {
+        let mut program: Program = Program::decode(&[0x00, 0x5b, 0x5b]);
+        let head: usize = program.label_at(1);
+        let mut tail: Program = program.split_off(2);
+        assert_eq!(tail.instructions[0].offset, 0);
+        assert_eq!(program.len(), 2);
+        assert_eq!(program.label_index(head), Some(1));
+        assert_ne!(tail.label_at(0), head);
+    }
+
+    #[test]
+    fn widens_a_push_whose_target_moves_out_of_reach() {
+        let mut program: Program = Program::default();
+        let label: usize = program.fresh_label();
+        program.push(Instruction::push_label(label, 1));
+        program.push(Instruction::new(0x56));
+        for _ in 0..300 {
+            program.push(Instruction::new(0x00));
+        }
+        program.push(jumpdest(label));
+        program.assemble().unwrap();
+
+        assert_eq!(program.instructions[0].opcode, 0x61);
+        assert_eq!(program.label_offset(label), Some(304));
+        assert_eq!(program.instructions[0].push_value(), Some(304));
+        assert_eq!(program.instructions[1].offset, 3);
+    }
+
+    #[test]
+    fn widening_repeats_until_every_push_fits() {
+        // `near` is at 0xff until the PUSH1 of `far`, at 0x100, widens and moves it
+        let mut program: Program = Program::default();
+        let far: usize = program.fresh_label();
+        let near: usize = program.fresh_label();
+        program.push(Instruction::push_label(far, 1));
+        program.push(Instruction::push_label(near, 1));
+        for _ in 0..0xfb {
+            program.push(Instruction::new(0x00));
+        }
+        program.push(jumpdest(near));
+        program.push(jumpdest(far));
+        assert_eq!(program.label_offset(near), Some(0xff));
+        program.assemble().unwrap();
+
+        assert_eq!(program.instructions[0].push_value(), Some(0x102));
+        assert_eq!(program.instructions[1].push_value(), Some(0x101));
+        assert_eq!(program.instructions[1].push_width(), 2);
+        assert_eq!(program.len(), 0x103);
+    }
+
+    #[test]
+    fn pushes_never_shrink() {
+        let mut program: Program = Program::default();
+        let label: usize = program.fresh_label();
+        program.push(Instruction::push_label(label, 2));
+        program.push(jumpdest(label));
+        program.assemble().unwrap();
+        assert_eq!(program.encode(), vec![0x61, 0x00, 0x03, 0x5b]);
+    }
+
+    #[test]
+    fn rejects_undefined_labels() {
+        let mut program: Program = Program::default();
+        program.push(Instruction::push_label(7, 1));
+        assert_eq!(program.assemble(), Err(ObfuscatorError::UndefinedLabel(7)));
+    }
+}
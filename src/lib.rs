@@ -1,7 +1,13 @@
 pub mod constant;
 
+pub mod error;
+
 pub mod helper;
 
+pub mod ir;
+
 pub mod analysis;
 
 pub mod obfuscation;
+
+pub mod evm;
@@ -1,17 +1,419 @@
+mod cli;
+
+use clap::Parser;
+use cli::{
+    AnalyzeArgs, Cli, Command, DisasmArgs, InputArgs, LinkArgs, ObfuscateArgs, OutputFormat,
+    VerifyArgs,
+};
+use obfuscator_rs::analysis::cfg::Cfg;
+use obfuscator_rs::analysis::code_type::{CreationSplit, split_creation_code};
+use obfuscator_rs::analysis::fork_support::find_unsupported_opcodes;
+use obfuscator_rs::analysis::integrity::{IntegrityIssue, check_creation_code};
+use obfuscator_rs::analysis::jump_seq::{find_jump_seq, find_jumpi_seq};
+use obfuscator_rs::analysis::metadata::{Metadata, decode_metadata, metadata_length};
+use obfuscator_rs::analysis::push_codecopy_seq::get_runtime_length;
+use obfuscator_rs::analysis::source_map::remap_source_map;
+use obfuscator_rs::analysis::stack::{StackAnalysis, analyze_stack};
+use obfuscator_rs::evm::equivalence::{EquivalenceReport, check_equivalence, default_calls};
+use obfuscator_rs::helper::hex::{decode_hex, encode_hex};
+use obfuscator_rs::helper::link::{LinkReference, decode_unlinked_hex, encode_unlinked_hex, link};
+use obfuscator_rs::ir::program::Program;
+use obfuscator_rs::obfuscation::config::ObfuscationConfig;
 use obfuscator_rs::obfuscation::obfuscate::obfuscate;
-use std::fs::File;
-use std::io::Write;
+use obfuscator_rs::obfuscation::report::{ObfuscationReport, SkippedPass};
+use serde_json::{Value, json};
+use std::process::ExitCode;
+
+// largest runtime a contract creation may return (EIP-170)
+const MAX_RUNTIME_LENGTH: usize = 0x6000;
+
+fn main() -> ExitCode {
+    let cli: Cli = Cli::parse();
+
+    let result: Result<(), String> = match &cli.command {
+        Command::Obfuscate(args) => run_obfuscate(args),
+        Command::Disasm(args) => run_disasm(args),
+        Command::Analyze(args) => run_analyze(args),
+        Command::Verify(args) => run_verify(args),
+        Command::Link(args) => run_link(args),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(err) => {
+            eprintln!("error: {}", err);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run_obfuscate(args: &ObfuscateArgs) -> Result<(), String> {
+    let (source, artifact) = cli::read_source(&args.input)?;
+    let (input, _) = decode_unlinked_hex(&source).map_err(|err| err.to_string())?;
 
-fn main() {
-    let mut creation_code: String = String::from(
-        "0x608060405234801561000f575f5ffd5b506040518060400160405280600781526020016626bcaa37b5b2b760c91b815250604051806040016040528060038152602001624d544b60e81b815250816003908161005b9190610108565b5060046100688282610108565b5050506101c2565b634e487b7160e01b5f52604160045260245ffd5b600181811c9082168061009857607f821691505b6020821081036100b657634e487b7160e01b5f52602260045260245ffd5b50919050565b601f82111561010357805f5260205f20601f840160051c810160208510156100e15750805b601f840160051c820191505b81811015610100575f81556001016100ed565b50505b505050565b81516001600160401b0381111561012157610121610070565b6101358161012f8454610084565b846100bc565b6020601f821160018114610167575f83156101505750848201515b5f19600385901b1c1916600184901b178455610100565b5f84815260208120601f198516915b828110156101965787850151825560209485019460019092019101610176565b50848210156101b357868401515f19600387901b60f8161c191681555b50505050600190811b01905550565b610746806101cf5f395ff3fe608060405234801561000f575f5ffd5b506004361061009b575f3560e01c806340c10f191161006357806340c10f191461011457806370a082311461012957806395d89b4114610151578063a9059cbb14610159578063dd62ed3e1461016c575f5ffd5b806306fdde031461009f578063095ea7b3146100bd57806318160ddd146100e057806323b872dd146100f2578063313ce56714610105575b5f5ffd5b6100a76101a4565b6040516100b491906105b6565b60405180910390f35b6100d06100cb366004610606565b610234565b60405190151581526020016100b4565b6002545b6040519081526020016100b4565b6100d061010036600461062e565b61024d565b604051601281526020016100b4565b610127610122366004610606565b610270565b005b6100e4610137366004610668565b6001600160a01b03165f9081526020819052604090205490565b6100a761027e565b6100d0610167366004610606565b61028d565b6100e461017a366004610688565b6001600160a01b039182165f90815260016020908152604080832093909416825291909152205490565b6060600380546101b3906106b9565b80601f01602080910402602001604051908101604052809291908181526020018280546101df906106b9565b801561022a5780601f106102015761010080835404028352916020019161022a565b820191905f5260205f20905b81548152906001019060200180831161020d57829003601f168201915b5050505050905090565b5f3361024181858561029a565b60019150505b92915050565b5f3361025a8582856102ac565b61026585858561032d565b506001949350505050565b61027a828261038a565b5050565b6060600480546101b3906106b9565b5f3361024181858561032d565b6102a783838360016103be565b505050565b6001600160a01b038381165f908152600160209081526040808320938616835292905220545f19811015610327578181101561031957604051637dc7a0d960e11b81526001600160a01b038416600482015260248101829052604481018390526064015b60405180910390fd5b61032784848484035f6103be565b50505050565b6001600160a01b03831661035657604051634b637e8f60e11b81525f6004820152602401610310565b6001600160a01b03821661037f5760405163ec442f0560e01b81525f6004820152602401610310565b6102a7838383610490565b6001600160a01b0382166103b35760405163ec442f0560e01b81525f6004820152602401610310565b61027a5f8383610490565b6001600160a01b0384166103e75760405163e602df0560e01b81525f6004820152602401610310565b6001600160a01b03831661041057604051634a1406b160e11b81525f6004820152602401610310565b6001600160a01b038085165f908152600160209081526040808320938716835292905220829055801561032757826001600160a01b0316846001600160a01b03167f8c5be1e5ebec7d5bd14f71427d1e84f3dd0314c0f7b2291e5b200ac8c7c3b9258460405161048291815260200190565b60405180910390a350505050565b6001600160a01b0383166104ba578060025f8282546104af91906106f1565b9091555061052a9050565b6001600160a01b0383165f908152602081905260409020548181101561050c5760405163391434e360e21b81526001600160a01b03851660048201526024810182905260448101839052606401610310565b6001600160a01b0384165f9081526020819052604090209082900390555b6001600160a01b03821661054657600280548290039055610564565b6001600160a01b0382165f9081526020819052604090208054820190555b816001600160a01b0316836001600160a01b03167fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef836040516105a991815260200190565b60405180910390a3505050565b602081525f82518060208401528060208501604085015e5f604082850101526040601f19601f83011684010191505092915050565b80356001600160a01b0381168114610601575f5ffd5b919050565b5f5f60408385031215610617575f5ffd5b610620836105eb565b946020939093013593505050565b5f5f5f60608486031215610640575f5ffd5b610649846105eb565b9250610657602085016105eb565b929592945050506040919091013590565b5f60208284031215610678575f5ffd5b610681826105eb565b9392505050565b5f5f60408385031215610699575f5ffd5b6106a2836105eb565b91506106b0602084016105eb565b90509250929050565b600181811c908216806106cd57607f821691505b6020821081036106eb57634e487b7160e01b5f52602260045260245ffd5b50919050565b8082018082111561024757634e487b7160e01b5f52601160045260245ffdfea2646970667358221220f8bef253fb06780b7b0a4bd8c7f18a07ca1216c01aa67c1c073660c31499829564736f6c634300081b0033",
-    );
+    let mut config: ObfuscationConfig = ObfuscationConfig {
+        target_fork: args.fork,
+        seed: args.seed,
+        metadata: args.metadata,
+        obfuscate_initcode: args.initcode,
+        libraries: args.libraries.clone(),
+        ..ObfuscationConfig::default()
+    };
+    if let Some(constructor_args) = &args.constructor_args {
+        config.constructor_args =
+            Some(decode_hex(constructor_args).map_err(|err| err.to_string())?);
+    }
+    if let Some(path) = &args.immutables {
+        config.immutable_references = Some(cli::read_immutable_references(path)?);
+    } else if let Some(artifact) = &artifact {
+        config.immutable_references = artifact.immutable_references.clone();
+    }
+    if let Some(max_iterations) = args.max_iterations {
+        config.max_iterations = max_iterations;
+    }
+    if let Some(passes) = &args.passes {
+        config.passes = passes.clone();
+    }
+    if let Some(length) = args.dead_code_length {
+        config.dead_code.length = length;
+    }
+    if let Some(mix) = args.dead_code_mix {
+        config.dead_code.mix = mix;
+    }
+
+    let report: ObfuscationReport = obfuscate(&source, &config).map_err(|err| err.to_string())?;
+    if let Some(seed) = report.seed {
+        eprintln!("Seed: {}", seed);
+    }
+    let source_map: Option<String> = match &args.source_map {
+        Some(map) => Some(
+            remap_source_map(&cli::read_source_map(map)?, &report.runtime_origins)
+                .map_err(|err| err.to_string())?,
+        ),
+        None => None,
+    };
+    for skipped in &report.skipped_passes {
+        eprintln!(
+            "note: {} skipped on the {}: {}",
+            skipped.pass.name(),
+            if skipped.initcode {
+                "initcode"
+            } else {
+                "runtime"
+            },
+            skipped.reason
+        );
+    }
+    // EIP-170: longer runtimes cannot be deployed on mainnet
+    if report.runtime_length > MAX_RUNTIME_LENGTH {
+        eprintln!(
+            "warning: the runtime is {} bytes, above the {} byte limit; lower -n or --dead-code-length",
+            report.runtime_length, MAX_RUNTIME_LENGTH
+        );
+    }
+    if !report.link_references.is_empty() {
+        eprintln!(
+            "{} library placeholder(s) left unlinked",
+            report.link_references.len()
+        );
+    }
+
+    let content: Vec<u8> = match (args.output.format, &artifact) {
+        (OutputFormat::Hex, Some(artifact)) => {
+            let mut json: String = artifact.write(&report).map_err(|err| err.to_string())?;
+            json.push('\n');
+            json.into_bytes()
+        }
+        (OutputFormat::Hex, None) => format!("0x{}\n", report.bytecode_hex()).into_bytes(),
+        (OutputFormat::Bin, _) => report.bytecode.clone(),
+        (OutputFormat::Json, _) => {
+            let summary: Value = json!({
+                "bytecode": format!("0x{}", report.bytecode_hex()),
+                "input_length": input.len(),
+                "output_length": report.bytecode.len(),
+                "original_initcode_length": report.original_initcode_length,
+                "initcode_length": report.initcode_length,
+                "original_runtime_length": report.original_runtime_length,
+                "runtime_length": report.runtime_length,
+                "split": report.split.map(|split| split.name()),
+                "constructor_args_length": report.constructor_args_length,
+                "metadata_length": report.metadata_length,
+                "redirected_jumps": report.redirected_jumps,
+                "redirected_conditional_jumps": report.redirected_conditional_jumps,
+                "redirected_pointers": report.redirected_pointers,
+                "initcode_redirects": report.initcode_redirects,
+                "dispatched_blocks": report.dispatched_blocks,
+                "flattened_edges": report.flattened_edges,
+                "reordered_blocks": report.reordered_blocks,
+                "masked_selectors": report.masked_selectors,
+                "fake_selectors": report.fake_selectors,
+                "skipped_passes": skipped_passes_json(&report.skipped_passes),
+                "immutable_references": report.immutable_references.len(),
+                "moved_immutables": report.moved_immutables,
+                "link_references": link_references_json(&report.link_references),
+                "source_map": source_map,
+                "target_fork": report.target_fork.name(),
+                "seed": report.seed,
+            });
+            format!("{:#}\n", summary).into_bytes()
+        }
+    };
+    cli::write_output(&args.output, &content)
+}
+
+fn run_disasm(args: &DisasmArgs) -> Result<(), String> {
+    let input: Vec<u8> = cli::read_input(&args.input)?;
+    let mut program: Program = Program::decode(&input);
+
+    if args.runtime {
+        let split: CreationSplit = split_creation_code(&program)
+            .ok_or_else(|| String::from("runtime could not be separated from initcode"))?;
+        program = split.runtime;
+    }
+
+    let content: Vec<u8> = match args.output.format {
+        OutputFormat::Hex => program.to_string().into_bytes(),
+        OutputFormat::Bin => program.encode(),
+        OutputFormat::Json => {
+            let instructions: Vec<Value> = program
+                .instructions
+                .iter()
+                .map(|instruction| {
+                    json!({
+                        "offset": instruction.offset,
+                        "opcode": instruction.opcode,
+                        "mnemonic": instruction.name(),
+                        "immediate": encode_hex(&instruction.immediate),
+                    })
+                })
+                .collect();
+            format!("{:#}\n", Value::Array(instructions)).into_bytes()
+        }
+    };
+    cli::write_output(&args.output, &content)
+}
 
-    obfuscate(&mut creation_code, 10);
+fn run_analyze(args: &AnalyzeArgs) -> Result<(), String> {
+    let input: Vec<u8> = cli::read_input(&args.input)?;
+    let program: Program = Program::decode(&input);
+    let split: Option<CreationSplit> = split_creation_code(&program);
+    let (init_code, runtime_bytecode, trailing) = match &split {
+        Some(split) => (
+            split.init_code.clone(),
+            split.runtime.clone(),
+            split.trailing.len(),
+        ),
+        None => (Program::default(), program.clone(), 0),
+    };
+    let split_heuristic: Option<&str> = split.as_ref().map(|split| split.heuristic.name());
+    let declared_runtime_length: Option<usize> =
+        match split.as_ref().and_then(|split| split.deploy.as_ref()) {
+            Some(deploy) => Some(deploy.runtime_length.value),
+            None => get_runtime_length(&init_code),
+        };
+    let runtime_bytes: Vec<u8> = runtime_bytecode.encode();
+
+    let stack_analysis: StackAnalysis = analyze_stack(&runtime_bytecode);
+    let jumps: Vec<Value> = find_jump_seq(&runtime_bytecode, &stack_analysis)
+        .iter()
+        .map(|push_jump| json!({ "offset": push_jump.byte_offset, "target": push_jump.value }))
+        .collect();
+    let conditional_jumps: Vec<Value> = find_jumpi_seq(&runtime_bytecode, &stack_analysis)
+        .iter()
+        .map(|push_jump| json!({ "offset": push_jump.byte_offset, "target": push_jump.value }))
+        .collect();
+    let cfg: &Cfg = &stack_analysis.cfg;
+    let reachable_blocks: usize = cfg
+        .reachable(&runtime_bytecode)
+        .iter()
+        .filter(|reachable| **reachable)
+        .count();
+    let metadata: Option<Metadata> = decode_metadata(&runtime_bytes);
+    let metadata_summary: Value = match &metadata {
+        Some(metadata) => json!({
+            "solc": metadata.solc_version(),
+            "ipfs": metadata.ipfs(),
+            "swarm": metadata.swarm().map(encode_hex),
+            "experimental": metadata.is_experimental(),
+        }),
+        None => Value::Null,
+    };
+    let unsupported: Vec<Value> = find_unsupported_opcodes(&program, args.fork)
+        .iter()
+        .map(|instruction| json!({ "offset": instruction.offset, "mnemonic": instruction.name() }))
+        .collect();
+
+    let report: Value = json!({
+        "length": input.len(),
+        "initcode_length": init_code.len(),
+        "runtime_length": runtime_bytecode.len(),
+        "trailing_length": trailing,
+        "split": split_heuristic,
+        "declared_runtime_length": declared_runtime_length,
+        "returned_length": split
+            .as_ref()
+            .and_then(|split| split.deploy.as_ref())
+            .map(|deploy| deploy.returned_length.value),
+        "metadata_length": metadata_length(&runtime_bytes),
+        "metadata": metadata_summary,
+        "push_jump_sequences": jumps,
+        "push_jumpi_sequences": conditional_jumps,
+        "basic_blocks": cfg.blocks.len(),
+        "reachable_blocks": reachable_blocks,
+        "edges": cfg.edges.len(),
+        "unresolved_jumps": cfg.unresolved_blocks(),
+        "code_pointers": stack_analysis
+            .code_pointers
+            .iter()
+            .map(|push| runtime_bytecode.instructions[*push].offset)
+            .collect::<Vec<usize>>(),
+        "unsupported_opcodes": unsupported,
+        "fork": args.fork.name(),
+    });
+
+    let content: Vec<u8> = match args.output.format {
+        OutputFormat::Json => format!("{:#}\n", report).into_bytes(),
+        _ => {
+            let rows: Vec<(&str, String)> = vec![
+                ("length", input.len().to_string()),
+                ("initcode length", init_code.len().to_string()),
+                ("runtime length", runtime_bytecode.len().to_string()),
+                ("trailing data length", trailing.to_string()),
+                ("split", split_heuristic.unwrap_or("none").to_string()),
+                (
+                    "declared runtime length",
+                    report["declared_runtime_length"].to_string(),
+                ),
+                ("returned length", report["returned_length"].to_string()),
+                ("metadata length", report["metadata_length"].to_string()),
+                (
+                    "compiler",
+                    metadata
+                        .as_ref()
+                        .and_then(|metadata| metadata.solc_version())
+                        .unwrap_or_else(|| String::from("unknown")),
+                ),
+                (
+                    "source hash",
+                    metadata
+                        .as_ref()
+                        .and_then(|metadata| {
+                            metadata.ipfs().or_else(|| metadata.swarm().map(encode_hex))
+                        })
+                        .unwrap_or_else(|| String::from("none")),
+                ),
+                ("push-jump sequences", jumps.len().to_string()),
+                ("push-jumpi sequences", conditional_jumps.len().to_string()),
+                ("basic blocks", cfg.blocks.len().to_string()),
+                ("reachable blocks", reachable_blocks.to_string()),
+                ("edges", cfg.edges.len().to_string()),
+                (
+                    "unresolved jumps",
+                    cfg.unresolved_blocks().len().to_string(),
+                ),
+                (
+                    "code pointers",
+                    stack_analysis.code_pointers.len().to_string(),
+                ),
+                ("unsupported opcodes", unsupported.len().to_string()),
+            ];
+            let mut text: String = String::new();
+            for (label, value) in rows {
+                text.push_str(&format!("{:<24} {}\n", format!("{}:", label), value));
+            }
+            text.into_bytes()
+        }
+    };
+    cli::write_output(&args.output, &content)
+}
+
+fn run_verify(args: &VerifyArgs) -> Result<(), String> {
+    let input: Vec<u8> = cli::read_input(&args.input)?;
+    let issues: Vec<IntegrityIssue> = check_creation_code(&Program::decode(&input), args.fork);
+
+    if !issues.is_empty() {
+        for issue in &issues {
+            println!("{}", issue);
+        }
+        return Err(format!("{} issue(s) found", issues.len()));
+    }
+    let Some(original) = &args.original else {
+        println!("OK");
+        return Ok(());
+    };
+
+    let original: Vec<u8> = cli::read_input(&InputArgs {
+        input: Some(original.clone()),
+        contract: args.input.contract.clone(),
+    })?;
+    let calls: Vec<Vec<u8>> = if args.calldata.is_empty() {
+        default_calls(&Program::decode(&original))
+    } else {
+        args.calldata
+            .iter()
+            .map(|calldata| decode_hex(calldata).map_err(|err| err.to_string()))
+            .collect::<Result<Vec<Vec<u8>>, String>>()?
+    };
+    let report: EquivalenceReport = check_equivalence(&original, &input, &calls);
+    match report.divergence {
+        None => {
+            println!("OK: equivalent over {} call(s)", report.calls);
+            Ok(())
+        }
+        Some(divergence) => {
+            println!("{}", divergence);
+            Err(String::from("obfuscated code diverges from the original"))
+        }
+    }
+}
+
+fn run_link(args: &LinkArgs) -> Result<(), String> {
+    let (mut bytecode, link_references) = cli::read_unlinked_input(&args.input)?;
+    let unlinked: Vec<LinkReference> =
+        link(&mut bytecode, &link_references, &args.libraries).map_err(|err| err.to_string())?;
+    if !unlinked.is_empty() {
+        eprintln!("{} library placeholder(s) left unlinked", unlinked.len());
+    }
+
+    let content: Vec<u8> = match args.output.format {
+        OutputFormat::Hex => {
+            format!("0x{}\n", encode_unlinked_hex(&bytecode, &unlinked)).into_bytes()
+        }
+        OutputFormat::Bin => bytecode,
+        OutputFormat::Json => {
+            let summary: Value = json!({
+                "bytecode": format!("0x{}", encode_unlinked_hex(&bytecode, &unlinked)),
+                "linked": link_references.len() - unlinked.len(),
+                "link_references": link_references_json(&unlinked),
+            });
+            format!("{:#}\n", summary).into_bytes()
+        }
+    };
+    cli::write_output(&args.output, &content)
+}
+
+fn skipped_passes_json(skipped_passes: &[SkippedPass]) -> Vec<Value> {
+    skipped_passes
+        .iter()
+        .map(|skipped| {
+            json!({
+                "pass": skipped.pass.name(),
+                "initcode": skipped.initcode,
+                "reason": skipped.reason,
+            })
+        })
+        .collect()
+}
 
-    // write creation_code into a txt file
-    let mut file = File::create("obfuscated_bytecode.txt").expect("Failed to create file");
-    file.write_all(creation_code.as_bytes())
-        .expect("Failed to write to file");
-    println!("Obfuscated Bytecode written to obfuscated_bytecode.txt");
+fn link_references_json(references: &[LinkReference]) -> Vec<Value> {
+    references
+        .iter()
+        .map(|reference| {
+            json!({
+                "placeholder": reference.placeholder,
+                "start": reference.start,
+                "length": reference.length,
+            })
+        })
+        .collect()
 }
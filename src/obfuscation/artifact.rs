@@ -0,0 +1,538 @@
+use crate::analysis::immutables::{ImmutableReference, parse_immutable_references};
+use crate::analysis::source_map::remap_source_map;
+use crate::error::ObfuscatorError;
+use crate::helper::link::{LinkReference, encode_unlinked_hex};
+use crate::obfuscation::report::ObfuscationReport;
+use serde_json::{Map, Value, json};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Compiler output a contract can be read from and written back to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArtifactFormat {
+    // output of `solc --standard-json`, every contract under contracts.<source>.<name>
+    StandardJson,
+    // a Foundry `out/<source>/<name>.json`
+    Foundry,
+    // a Hardhat `artifacts/<source>/<name>.json`
+    Hardhat,
+}
+
+// JSON pointers (relative to the contract object) of the fields each format has
+struct ArtifactLayout {
+    bytecode: &'static str,
+    deployed_bytecode: &'static str,
+    link_references: &'static str,
+    deployed_link_references: &'static str,
+    immutable_references: Option<&'static str>,
+    source_map: Option<&'static str>,
+    deployed_source_map: Option<&'static str>,
+}
+
+impl ArtifactFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ArtifactFormat::StandardJson => "standard-json",
+            ArtifactFormat::Foundry => "foundry",
+            ArtifactFormat::Hardhat => "hardhat",
+        }
+    }
+
+    fn layout(&self) -> ArtifactLayout {
+        match self {
+            ArtifactFormat::StandardJson => ArtifactLayout {
+                bytecode: "/evm/bytecode/object",
+                deployed_bytecode: "/evm/deployedBytecode/object",
+                link_references: "/evm/bytecode/linkReferences",
+                deployed_link_references: "/evm/deployedBytecode/linkReferences",
+                immutable_references: Some("/evm/deployedBytecode/immutableReferences"),
+                source_map: Some("/evm/bytecode/sourceMap"),
+                deployed_source_map: Some("/evm/deployedBytecode/sourceMap"),
+            },
+            ArtifactFormat::Foundry => ArtifactLayout {
+                bytecode: "/bytecode/object",
+                deployed_bytecode: "/deployedBytecode/object",
+                link_references: "/bytecode/linkReferences",
+                deployed_link_references: "/deployedBytecode/linkReferences",
+                immutable_references: Some("/deployedBytecode/immutableReferences"),
+                source_map: Some("/bytecode/sourceMap"),
+                deployed_source_map: Some("/deployedBytecode/sourceMap"),
+            },
+            // immutables and source maps only are in Hardhat's build-info files
+            ArtifactFormat::Hardhat => ArtifactLayout {
+                bytecode: "/bytecode",
+                deployed_bytecode: "/deployedBytecode",
+                link_references: "/linkReferences",
+                deployed_link_references: "/deployedLinkReferences",
+                immutable_references: None,
+                source_map: None,
+                deployed_source_map: None,
+            },
+        }
+    }
+}
+
+impl fmt::Display for ArtifactFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+// One contract of a compiler artifact, with the whole document kept to be written back
+#[derive(Debug, Clone)]
+pub struct Artifact {
+    pub format: ArtifactFormat,
+    // `<source>:<name>` when the artifact says which contract it holds
+    pub contract: Option<String>,
+    // creation and runtime code as hex, library placeholders included
+    pub bytecode: String,
+    pub deployed_bytecode: String,
+    // source and name of the library behind each placeholder
+    pub libraries: BTreeMap<String, (String, String)>,
+    // None when the format does not carry them
+    pub immutable_references: Option<Vec<ImmutableReference>>,
+    pub source_map: Option<String>,
+    pub deployed_source_map: Option<String>,
+    document: Value,
+    // JSON pointer to the contract object inside `document`
+    pointer: String,
+}
+
+/*  READING AN ARTIFACT
+1) recognise the format: a `contracts` map is standard JSON, a bytecode object with an
+    `object` field is Foundry, a bytecode string is Hardhat
+2) for standard JSON pick the contract by name or `<source>:<name>`, the only one if unnamed
+3) read the fields of the format's layout; placeholders are named after the libraries of
+    the link references that point at them
+*/
+pub fn parse_artifact(json: &str, contract: Option<&str>) -> Result<Artifact, ObfuscatorError> {
+    let invalid = |reason: String| ObfuscatorError::InvalidArtifact(reason);
+    let document: Value = serde_json::from_str(json).map_err(|error| invalid(error.to_string()))?;
+
+    // 1) + 2) format and contract object
+    let (format, pointer, name): (ArtifactFormat, String, Option<String>) =
+        if let Some(contracts) = document.get("contracts").and_then(Value::as_object) {
+            let (source, contract_name) = select_contract(contracts, contract)?;
+            let pointer: String = format!(
+                "/contracts/{}/{}",
+                escape_pointer(&source),
+                escape_pointer(&contract_name)
+            );
+            let qualified: String = format!("{}:{}", source, contract_name);
+            (ArtifactFormat::StandardJson, pointer, Some(qualified))
+        } else {
+            let format: ArtifactFormat = match document.get("bytecode") {
+                Some(Value::String(_)) => ArtifactFormat::Hardhat,
+                Some(Value::Object(bytecode)) if bytecode.contains_key("object") => {
+                    ArtifactFormat::Foundry
+                }
+                _ => return Err(invalid(String::from("no bytecode found"))),
+            };
+            let name: Option<String> = match (
+                document.get("sourceName").and_then(Value::as_str),
+                document.get("contractName").and_then(Value::as_str),
+            ) {
+                (Some(source), Some(name)) => Some(format!("{}:{}", source, name)),
+                _ => None,
+            };
+            (format, String::new(), name)
+        };
+    let object: &Value = document
+        .pointer(&pointer)
+        .ok_or_else(|| invalid(String::from("contract not found")))?;
+
+    // 3) fields
+    let layout: ArtifactLayout = format.layout();
+    let text = |field: Option<&str>| -> Option<String> {
+        field
+            .and_then(|field| object.pointer(field))
+            .and_then(Value::as_str)
+            .map(str::to_string)
+    };
+    let bytecode: String = text(Some(layout.bytecode))
+        .filter(|bytecode| !bytecode.trim_start_matches("0x").is_empty())
+        .ok_or_else(|| invalid(String::from("the contract has no creation code")))?;
+    let deployed_bytecode: String = text(Some(layout.deployed_bytecode)).unwrap_or_default();
+
+    let mut libraries: BTreeMap<String, (String, String)> = BTreeMap::new();
+    for (field, code) in [
+        (layout.link_references, &bytecode),
+        (layout.deployed_link_references, &deployed_bytecode),
+    ] {
+        let code: &str = code.strip_prefix("0x").unwrap_or(code);
+        for (source, library, start) in read_link_references(object.pointer(field)) {
+            if let Some(placeholder) = code.get(2 * start..2 * start + 40) {
+                libraries.insert(placeholder.to_string(), (source, library));
+            }
+        }
+    }
+
+    let immutable_references: Option<Vec<ImmutableReference>> = match layout
+        .immutable_references
+        .and_then(|field| object.pointer(field))
+    {
+        Some(references) => Some(parse_immutable_references(&references.to_string())?),
+        None => None,
+    };
+
+    Ok(Artifact {
+        format,
+        contract: name,
+        bytecode,
+        deployed_bytecode,
+        libraries,
+        immutable_references,
+        source_map: text(layout.source_map),
+        deployed_source_map: text(layout.deployed_source_map),
+        pointer,
+        document,
+    })
+}
+
+impl Artifact {
+    /*  WRITING AN ARTIFACT
+    the document is written back as it was read, with the contract's fields replaced:
+    1) creation and runtime code, hex prefixed with 0x only where the input was
+    2) link references of both, regrouped by source and library, at their new offsets
+    3) immutable references at their new offsets in the runtime
+    4) source maps get an entry per instruction of the new code, taken from the instruction
+        it came from (a synthetic one for inserted code)
+    */
+    pub fn write(&self, report: &ObfuscationReport) -> Result<String, ObfuscatorError> {
+        let layout: ArtifactLayout = self.format.layout();
+        let mut document: Value = self.document.clone();
+        let object: &mut Value = document
+            .pointer_mut(&self.pointer)
+            .ok_or_else(|| ObfuscatorError::InvalidArtifact(String::from("contract not found")))?;
+
+        // the runtime is whatever follows the initcode, up to its length
+        let runtime_start: usize = report.initcode_length;
+        let runtime_end: usize = runtime_start + report.runtime_length;
+        let runtime_references: Vec<LinkReference> = report
+            .link_references
+            .iter()
+            .filter(|reference| (runtime_start..runtime_end).contains(&reference.start))
+            .map(|reference| LinkReference {
+                start: reference.start - runtime_start,
+                ..reference.clone()
+            })
+            .collect();
+
+        // 1) code
+        let prefix = |original: &str| if original.starts_with("0x") { "0x" } else { "" };
+        let bytecode: String = format!("{}{}", prefix(&self.bytecode), report.bytecode_hex());
+        let deployed_bytecode: String = format!(
+            "{}{}",
+            prefix(&self.deployed_bytecode),
+            encode_unlinked_hex(
+                &report.bytecode[runtime_start..runtime_end],
+                &runtime_references
+            )
+        );
+        set_field(object, layout.bytecode, Value::String(bytecode));
+        set_field(
+            object,
+            layout.deployed_bytecode,
+            Value::String(deployed_bytecode),
+        );
+
+        // 2) link references
+        set_field(
+            object,
+            layout.link_references,
+            self.link_references_json(&report.link_references),
+        );
+        set_field(
+            object,
+            layout.deployed_link_references,
+            self.link_references_json(&runtime_references),
+        );
+
+        // 3) immutables
+        if let Some(field) = layout.immutable_references {
+            let mut immutables: Map<String, Value> = Map::new();
+            for reference in &report.immutable_references {
+                let entries: &mut Value = immutables
+                    .entry(reference.id.clone())
+                    .or_insert_with(|| json!([]));
+                if let Some(entries) = entries.as_array_mut() {
+                    entries.push(json!({"start": reference.start, "length": reference.length}));
+                }
+            }
+            set_field(object, field, Value::Object(immutables));
+        }
+
+        // 4) source maps; input that was all runtime has no initcode of its own
+        let initcode_origins: &[Option<usize>] = match report.split {
+            Some(_) => &report.initcode_origins,
+            None => &report.runtime_origins,
+        };
+        for (field, map, origins) in [
+            (layout.source_map, &self.source_map, initcode_origins),
+            (
+                layout.deployed_source_map,
+                &self.deployed_source_map,
+                &report.runtime_origins,
+            ),
+        ] {
+            if let (Some(field), Some(map)) = (field, map) {
+                let remapped: String = remap_source_map(map, origins)?;
+                set_field(object, field, Value::String(remapped));
+            }
+        }
+
+        serde_json::to_string_pretty(&document)
+            .map_err(|error| ObfuscatorError::InvalidArtifact(error.to_string()))
+    }
+
+    // `{ <source>: { <library>: [{start, length}] } }`; placeholders of unknown libraries
+    // are listed under an empty source with the placeholder as name
+    fn link_references_json(&self, references: &[LinkReference]) -> Value {
+        let mut sources: Map<String, Value> = Map::new();
+        for reference in references {
+            let (source, library) = self
+                .libraries
+                .get(&reference.placeholder)
+                .cloned()
+                .unwrap_or_else(|| (String::new(), reference.placeholder.clone()));
+            let libraries: &mut Value = sources.entry(source).or_insert_with(|| json!({}));
+            if let Some(entries) = libraries
+                .as_object_mut()
+                .map(|libraries| libraries.entry(library).or_insert_with(|| json!([])))
+                .and_then(Value::as_array_mut)
+            {
+                entries.push(json!({"start": reference.start, "length": reference.length}));
+            }
+        }
+        Value::Object(sources)
+    }
+}
+
+// the contract `<name>` or `<source>:<name>` of a standard JSON output; without a name the
+// output has to hold exactly one contract with code
+fn select_contract(
+    contracts: &Map<String, Value>,
+    wanted: Option<&str>,
+) -> Result<(String, String), ObfuscatorError> {
+    let mut candidates: Vec<(String, String)> = Vec::new();
+    for (source, names) in contracts {
+        for (name, contract) in names.as_object().into_iter().flatten() {
+            let matches: bool = match wanted {
+                Some(wanted) => wanted == name || wanted == format!("{}:{}", source, name),
+                None => contract
+                    .pointer("/evm/bytecode/object")
+                    .and_then(Value::as_str)
+                    .is_some_and(|bytecode| !bytecode.is_empty()),
+            };
+            if matches {
+                candidates.push((source.clone(), name.clone()));
+            }
+        }
+    }
+    match candidates.len() {
+        1 => Ok(candidates.remove(0)),
+        0 => Err(ObfuscatorError::InvalidArtifact(match wanted {
+            Some(wanted) => format!("no contract {} in the output", wanted),
+            None => String::from("no contract with bytecode in the output"),
+        })),
+        _ => Err(ObfuscatorError::InvalidArtifact(format!(
+            "several contracts match, choose one of {}",
+            candidates
+                .iter()
+                .map(|(source, name)| format!("{}:{}", source, name))
+                .collect::<Vec<String>>()
+                .join(", ")
+        ))),
+    }
+}
+
+// (source, library, start) of every entry of a linkReferences map
+fn read_link_references(references: Option<&Value>) -> Vec<(String, String, usize)> {
+    let mut entries: Vec<(String, String, usize)> = Vec::new();
+    for (source, libraries) in references.and_then(Value::as_object).into_iter().flatten() {
+        for (library, positions) in libraries.as_object().into_iter().flatten() {
+            for position in positions.as_array().into_iter().flatten() {
+                if let Some(start) = position.get("start").and_then(Value::as_u64) {
+                    entries.push((source.clone(), library.clone(), start as usize));
+                }
+            }
+        }
+    }
+    entries
+}
+
+// set a field of a JSON object, creating the objects on the way
+fn set_field(object: &mut Value, pointer: &str, value: Value) {
+    let mut current: &mut Value = object;
+    let keys: Vec<&str> = pointer.split('/').skip(1).collect();
+    for (position, key) in keys.iter().enumerate() {
+        if !current.is_object() {
+            *current = json!({});
+        }
+        let Some(map) = current.as_object_mut() else {
+            return;
+        };
+        if position + 1 == keys.len() {
+            map.insert(key.to_string(), value);
+            return;
+        }
+        current = map.entry(key.to_string()).or_insert_with(|| json!({}));
+    }
+}
+
+// escape a key for use in a JSON pointer (RFC 6901)
+fn escape_pointer(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::helper::link::library_placeholder;
+    use crate::obfuscation::config::ObfuscationConfig;
+    use crate::obfuscation::obfuscate::obfuscate;
+
+    // deploys the runtime `PUSH1 3 JUMP JUMPDEST PUSH20 <Math> POP STOP` behind an 11 byte
+    // initcode
+    fn creation_code() -> String {
+        format!(
+            "0x601b80600b6000396000f36003565b73{}5000",
+            library_placeholder("src/Math.sol:Math")
+        )
+    }
+
+    fn runtime_code() -> String {
+        format!(
+            "0x6003565b73{}5000",
+            library_placeholder("src/Math.sol:Math")
+        )
+    }
+
+    fn link_references(start: usize) -> Value {
+        json!({"src/Math.sol": {"Math": [{"start": start, "length": 20}]}})
+    }
+
+    fn standard_json() -> Value {
+        let contract = |bytecode: &str, deployed: &str| {
+            json!({"abi": [], "evm": {
+                "bytecode": {"object": bytecode, "linkReferences": {}, "sourceMap": ""},
+                "deployedBytecode": {"object": deployed, "linkReferences": {},
+                    "immutableReferences": {}, "sourceMap": ""}
+            }})
+        };
+        json!({"contracts": {
+            "src/Token.sol": {"Token": contract("6000", "00")},
+            "src/IToken.sol": {"IToken": contract("", "")}
+        }})
+    }
+
+    fn foundry() -> Value {
+        json!({
+            "abi": [],
+            "bytecode": {"object": creation_code(), "linkReferences": link_references(16),
+                "sourceMap": "0:1:0:-:0;;;;;;;"},
+            "deployedBytecode": {"object": runtime_code(), "linkReferences": link_references(5),
+                "immutableReferences": {}, "sourceMap": "0:1:0:-:0;;"}
+        })
+    }
+
+    fn hardhat() -> Value {
+        json!({
+            "_format": "hh-sol-artifact-1",
+            "contractName": "Calc",
+            "sourceName": "src/Calc.sol",
+            "abi": [],
+            "bytecode": creation_code(),
+            "deployedBytecode": runtime_code(),
+            "linkReferences": link_references(16),
+            "deployedLinkReferences": link_references(5)
+        })
+    }
+
+    fn parse(document: &Value, contract: Option<&str>) -> Result<Artifact, ObfuscatorError> {
+        parse_artifact(&document.to_string(), contract)
+    }
+
+    #[test]
+    fn recognises_each_layout() {
+        let artifact: Artifact = parse(&standard_json(), None).unwrap();
+        assert_eq!(artifact.format, ArtifactFormat::StandardJson);
+        assert_eq!(artifact.contract.as_deref(), Some("src/Token.sol:Token"));
+        assert_eq!(artifact.bytecode, "6000");
+        assert_eq!(artifact.immutable_references, Some(Vec::new()));
+
+        let artifact: Artifact = parse(&foundry(), None).unwrap();
+        assert_eq!(artifact.format, ArtifactFormat::Foundry);
+        assert_eq!(artifact.contract, None);
+        assert_eq!(artifact.bytecode, creation_code());
+        assert_eq!(artifact.deployed_bytecode, runtime_code());
+        assert_eq!(artifact.immutable_references, Some(Vec::new()));
+        assert_eq!(artifact.deployed_source_map.as_deref(), Some("0:1:0:-:0;;"));
+
+        let artifact: Artifact = parse(&hardhat(), None).unwrap();
+        assert_eq!(artifact.format, ArtifactFormat::Hardhat);
+        assert_eq!(artifact.contract.as_deref(), Some("src/Calc.sol:Calc"));
+        assert_eq!(artifact.immutable_references, None);
+        assert_eq!(artifact.source_map, None);
+        let library: (String, String) = (String::from("src/Math.sol"), String::from("Math"));
+        assert_eq!(
+            artifact.libraries,
+            BTreeMap::from([(library_placeholder("src/Math.sol:Math"), library)])
+        );
+    }
+
+    #[test]
+    fn selects_contracts_of_standard_json() {
+        let document: Value = standard_json();
+        for name in ["Token", "src/Token.sol:Token"] {
+            assert_eq!(parse(&document, Some(name)).unwrap().bytecode, "6000");
+        }
+        // named, a contract without code is still found, and then refused
+        assert!(matches!(
+            parse(&document, Some("IToken")),
+            Err(ObfuscatorError::InvalidArtifact(_))
+        ));
+        assert!(matches!(
+            parse(&document, Some("Missing")),
+            Err(ObfuscatorError::InvalidArtifact(_))
+        ));
+
+        let mut ambiguous: Value = document.clone();
+        ambiguous["contracts"]["src/IToken.sol"]["IToken"]["evm"]["bytecode"]["object"] =
+            json!("6001");
+        assert!(matches!(
+            parse(&ambiguous, None),
+            Err(ObfuscatorError::InvalidArtifact(_))
+        ));
+        assert!(parse(&json!({"abi": []}), None).is_err());
+    }
+
+    #[test]
+    fn writes_back_into_the_fields_it_read() {
+        let config: ObfuscationConfig = ObfuscationConfig {
+            seed: Some(3),
+            ..ObfuscationConfig::default()
+        };
+        for document in [foundry(), hardhat()] {
+            let artifact: Artifact = parse(&document, None).unwrap();
+            let report: ObfuscationReport = obfuscate(&artifact.bytecode, &config).unwrap();
+            let written: Value = serde_json::from_str(&artifact.write(&report).unwrap()).unwrap();
+            let reread: Artifact = parse(&written, None).unwrap();
+
+            assert_eq!(reread.format, artifact.format);
+            assert_eq!(written["abi"], json!([]));
+            assert_eq!(reread.bytecode, format!("0x{}", report.bytecode_hex()));
+            assert_eq!(
+                reread.deployed_bytecode,
+                format!(
+                    "0x{}",
+                    &report.bytecode_hex()[2 * report.initcode_length
+                        ..2 * (report.initcode_length + report.runtime_length)]
+                )
+            );
+            // the placeholder moved, and is found where the link references say
+            assert_ne!(report.link_references[0].start, 16);
+            assert_eq!(reread.libraries, artifact.libraries);
+        }
+    }
+}
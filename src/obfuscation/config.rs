@@ -0,0 +1,172 @@
+use crate::analysis::immutables::ImmutableReference;
+use crate::constant::hardfork::Hardfork;
+use crate::helper::link::Library;
+use crate::obfuscation::dead_code::DeadCodeConfig;
+use std::fmt;
+use std::str::FromStr;
+
+// Individual transformations that can be switched on or off
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Pass {
+    // redirect PUSH-JUMP sequences through an appended JUMPDEST and trampoline
+    JumpRedirect,
+    // redirect the taken edge of PUSH-JUMPI sequences the same way
+    JumpiRedirect,
+    // redirect code pointers pushed away from their jump (e.g. internal function return
+    // addresses), located by abstract stack interpretation
+    PointerRedirect,
+    // inject dead code between the redirected JUMPDEST and its trampoline
+    DeadCode,
+    // guard trampolines with always true opaque predicates and turn the constant guards of
+    // dead code into always false ones, both with bogus edges into real JUMPDESTs
+    OpaquePredicates,
+    // route every edge between the blocks, trampolines included, through a state machine
+    // dispatcher instead of a jump to the block
+    Flatten,
+    // shuffle the basic blocks of the code, appended ones included, so the layout no longer
+    // follows the compiler's order
+    ReorderBlocks,
+    // hide the selectors of the function dispatcher behind masked comparisons, among fake
+    // ones
+    MaskSelectors,
+}
+
+impl Pass {
+    pub const ALL: [Pass; 8] = [
+        Pass::JumpRedirect,
+        Pass::JumpiRedirect,
+        Pass::PointerRedirect,
+        Pass::DeadCode,
+        Pass::OpaquePredicates,
+        Pass::Flatten,
+        Pass::ReorderBlocks,
+        Pass::MaskSelectors,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pass::JumpRedirect => "jump-redirect",
+            Pass::JumpiRedirect => "jumpi-redirect",
+            Pass::PointerRedirect => "pointer-redirect",
+            Pass::DeadCode => "dead-code",
+            Pass::OpaquePredicates => "opaque-predicates",
+            Pass::Flatten => "flatten",
+            Pass::ReorderBlocks => "reorder-blocks",
+            Pass::MaskSelectors => "mask-selectors",
+        }
+    }
+}
+
+impl fmt::Display for Pass {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Pass {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Pass::ALL
+            .iter()
+            .find(|pass| pass.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown pass: {}", s))
+    }
+}
+
+// What happens to the compiler metadata trailer at the end of the runtime code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MetadataMode {
+    // leave it untouched at the tail of the obfuscated runtime
+    Keep,
+    // drop it
+    Strip,
+    // keep the compiler version but drop the ipfs / swarm hashes that lead to the sources
+    Rewrite,
+}
+
+impl MetadataMode {
+    pub const ALL: [MetadataMode; 3] = [
+        MetadataMode::Keep,
+        MetadataMode::Strip,
+        MetadataMode::Rewrite,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            MetadataMode::Keep => "keep",
+            MetadataMode::Strip => "strip",
+            MetadataMode::Rewrite => "rewrite",
+        }
+    }
+}
+
+impl fmt::Display for MetadataMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for MetadataMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MetadataMode::ALL
+            .iter()
+            .find(|mode| mode.name() == s)
+            .copied()
+            .ok_or_else(|| format!("unknown metadata mode: {}", s))
+    }
+}
+
+// Options controlling a single obfuscation run
+#[derive(Debug, Clone)]
+pub struct ObfuscationConfig {
+    // maximum number of PUSH-JUMP / PUSH-JUMPI sequences and code pointers to redirect; each
+    // one adds dead code and a trampoline, so an unbounded run can outgrow EIP-170
+    pub max_iterations: usize,
+    // oldest hardfork the output must run on; emitted code only uses opcodes available there
+    pub target_fork: Hardfork,
+    // transformations to apply
+    pub passes: Vec<Pass>,
+    // seed for every randomized choice; a random seed is drawn (and reported) when unset
+    pub seed: Option<u64>,
+    // handling of the compiler metadata trailer
+    pub metadata: MetadataMode,
+    // run the passes over the initcode (constructor) as well as the runtime
+    pub obfuscate_initcode: bool,
+    // ABI encoded constructor arguments placed behind the output; stripped from the input
+    // first when it already ends with them
+    pub constructor_args: Option<Vec<u8>>,
+    // placeholders the constructor writes immutables into (solc's `immutableReferences`);
+    // inferred from the constructor's writes into the deployed copy when unset
+    pub immutable_references: Option<Vec<ImmutableReference>>,
+    // library addresses linked into the input before it is obfuscated
+    pub libraries: Vec<Library>,
+    // length and opcode mix of the generated dead code
+    pub dead_code: DeadCodeConfig,
+}
+
+impl ObfuscationConfig {
+    pub fn is_enabled(&self, pass: Pass) -> bool {
+        self.passes.contains(&pass)
+    }
+}
+
+impl Default for ObfuscationConfig {
+    fn default() -> Self {
+        ObfuscationConfig {
+            max_iterations: 10,
+            target_fork: Hardfork::Prague,
+            passes: Pass::ALL.to_vec(),
+            seed: None,
+            metadata: MetadataMode::Keep,
+            obfuscate_initcode: false,
+            constructor_args: None,
+            immutable_references: None,
+            libraries: Vec::new(),
+            dead_code: DeadCodeConfig::default(),
+        }
+    }
+}
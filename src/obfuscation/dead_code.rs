@@ -0,0 +1,604 @@
+use crate::constant::hardfork::Hardfork;
+use crate::constant::opcodes::{OpcodeInfo, opcode_info};
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use rand::{Rng, RngCore};
+use std::fmt;
+use std::str::FromStr;
+
+// values dead code may hold on the stack at once, on top of the contract's own
+const MAX_STACK_DEPTH: usize = 12;
+// conditional and unconditional jumps nest at most this deep
+const MAX_NESTING: usize = 2;
+// scratch memory: the first two words, saved before dead code writes them and restored after
+const SCRATCH_LENGTH: usize = 0x40;
+
+const ARITHMETIC: [u8; 11] = [
+    0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07, 0x08, 0x09, 0x0a, 0x0b,
+];
+// comparisons, bitwise logic and shifts
+const BITWISE: [u8; 14] = [
+    0x10, 0x11, 0x12, 0x13, 0x14, 0x15, 0x16, 0x17, 0x18, 0x19, 0x1a, 0x1b, 0x1c, 0x1d,
+];
+// reads of the call, the block and the machine state; nothing touching storage or accounts
+// nor PC, whose value would depend on where the block ends up
+const ENVIRONMENT: [u8; 19] = [
+    0x30, 0x32, 0x33, 0x34, 0x35, 0x36, 0x38, 0x3a, 0x3d, 0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47,
+    0x48, 0x59, 0x5a,
+];
+// comparisons a constant guard can use
+const COMPARISONS: [u8; 5] = [0x10, 0x11, 0x12, 0x13, 0x14];
+
+// Relative weights of the kinds of instructions dead code is made of
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadCodeMix {
+    // PUSH of a random constant
+    pub constant: u32,
+    // ADD, MUL, SUB, DIV, MOD, EXP, ...
+    pub arithmetic: u32,
+    // comparisons, AND, OR, XOR, NOT, BYTE and shifts
+    pub bitwise: u32,
+    // CALLER, CALLDATALOAD, TIMESTAMP, GAS, ...
+    pub environment: u32,
+    // DUP, SWAP and POP of values the dead code pushed
+    pub stack: u32,
+    // MLOAD, MSTORE, MSTORE8 and KECCAK256 on scratch memory
+    pub memory: u32,
+    // forward jumps over unreachable code and branches that join again
+    pub jump: u32,
+}
+
+impl DeadCodeMix {
+    pub const CATEGORIES: [&'static str; 7] = [
+        "constant",
+        "arithmetic",
+        "bitwise",
+        "environment",
+        "stack",
+        "memory",
+        "jump",
+    ];
+
+    fn weights(&self) -> [u32; 7] {
+        [
+            self.constant,
+            self.arithmetic,
+            self.bitwise,
+            self.environment,
+            self.stack,
+            self.memory,
+            self.jump,
+        ]
+    }
+
+    fn weight_mut(&mut self, category: &str) -> Option<&mut u32> {
+        match category {
+            "constant" => Some(&mut self.constant),
+            "arithmetic" => Some(&mut self.arithmetic),
+            "bitwise" => Some(&mut self.bitwise),
+            "environment" => Some(&mut self.environment),
+            "stack" => Some(&mut self.stack),
+            "memory" => Some(&mut self.memory),
+            "jump" => Some(&mut self.jump),
+            _ => None,
+        }
+    }
+}
+
+impl Default for DeadCodeMix {
+    fn default() -> Self {
+        DeadCodeMix {
+            constant: 4,
+            arithmetic: 3,
+            bitwise: 3,
+            environment: 2,
+            stack: 2,
+            memory: 1,
+            jump: 1,
+        }
+    }
+}
+
+impl fmt::Display for DeadCodeMix {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let weights: Vec<String> = DeadCodeMix::CATEGORIES
+            .iter()
+            .zip(self.weights())
+            .map(|(category, weight)| format!("{}={}", category, weight))
+            .collect();
+        write!(f, "{}", weights.join(","))
+    }
+}
+
+// `<category>=<weight>,...`; categories left out keep their default weight
+impl FromStr for DeadCodeMix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix: DeadCodeMix = DeadCodeMix::default();
+        for entry in s.split(',').filter(|entry| !entry.trim().is_empty()) {
+            let (category, weight) = entry
+                .split_once('=')
+                .ok_or_else(|| format!("expected <category>=<weight>: {}", entry))?;
+            let weight: u32 = weight
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid weight: {}", entry))?;
+            *mix.weight_mut(category.trim()).ok_or_else(|| {
+                format!(
+                    "unknown dead code category {} (expected one of {})",
+                    category,
+                    DeadCodeMix::CATEGORIES.join(", ")
+                )
+            })? = weight;
+        }
+        if mix.weights().iter().all(|weight| *weight == 0) {
+            return Err(String::from("every dead code weight is zero"));
+        }
+        Ok(mix)
+    }
+}
+
+// Number of instructions generated per dead code block, drawn from `min..=max`; jumps
+// add their unreachable and branch code on top
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeadCodeLength {
+    pub min: usize,
+    pub max: usize,
+}
+
+impl Default for DeadCodeLength {
+    fn default() -> Self {
+        DeadCodeLength { min: 8, max: 24 }
+    }
+}
+
+impl fmt::Display for DeadCodeLength {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.min, self.max)
+    }
+}
+
+// `<n>` or `<min>-<max>`
+impl FromStr for DeadCodeLength {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (min, max) = s.split_once('-').unwrap_or((s, s));
+        let parse = |value: &str| -> Result<usize, String> {
+            value
+                .trim()
+                .parse()
+                .map_err(|_| format!("invalid dead code length: {}", s))
+        };
+        let length: DeadCodeLength = DeadCodeLength {
+            min: parse(min)?,
+            max: parse(max)?,
+        };
+        if length.min > length.max {
+            return Err(format!("dead code length {} has min above max", s));
+        }
+        Ok(length)
+    }
+}
+
+// Shape of the generated dead code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct DeadCodeConfig {
+    pub length: DeadCodeLength,
+    pub mix: DeadCodeMix,
+}
+
+/*
+    Generate a block of dead code that would run without any effect the contract can
+    observe, so that any path an analysis takes through it looks plausible:
+    1. when memory is in the mix, save the two scratch words on the stack
+    2. draw instructions by weight from the mix, only from opcodes the fork has and only
+       when the values they take were pushed by the dead code itself; the contract's own
+       stack is never read, reordered or consumed, and no instruction writes storage,
+       emits logs, calls, creates or halts
+    3. jumps are forward to labels of the block: over random unreachable code, or a JUMPI
+       whose fall through is a nested block leaving the stack as it found it, so both
+       paths reach the JUMPDEST with the same stack
+    4. POP what is left, then write the scratch words back
+    memory outside the scratch words is never touched and the scratch words end up with
+    their old values; only MSIZE can grow, to at most 0x40. The caller jumps over the block
+    behind an opaque predicate, so none of it actually runs
+*/
+pub fn generate_dead_code(
+    config: &DeadCodeConfig,
+    fork: Hardfork,
+    rng: &mut dyn RngCore,
+) -> Program {
+    let mut generator: Generator = Generator {
+        mix: config.mix,
+        fork,
+        rng,
+        program: Program::default(),
+        depth: 0,
+        scratch: config.mix.memory > 0,
+    };
+
+    // 1) the saved words sit below everything the block pushes
+    if generator.scratch {
+        generator.emit_push(0);
+        generator.emit(0x51);
+        generator.emit_push(0x20);
+        generator.emit(0x51);
+    }
+    let floor: usize = generator.depth;
+
+    // 2) - 3)
+    let length: usize = generator
+        .rng
+        .random_range(config.length.min..=config.length.max);
+    generator.block(floor, length, 0);
+
+    // 4) MSTORE takes the offset from the top, the saved word right below it
+    if generator.scratch {
+        generator.emit_push(0x20);
+        generator.emit(0x52);
+        generator.emit_push(0);
+        generator.emit(0x52);
+    }
+    generator.program
+}
+
+struct Generator<'a> {
+    mix: DeadCodeMix,
+    fork: Hardfork,
+    rng: &'a mut dyn RngCore,
+    program: Program,
+    // values pushed by the dead code that are still on the stack
+    depth: usize,
+    // scratch memory is saved and may be written
+    scratch: bool,
+}
+
+impl Generator<'_> {
+    fn emit(&mut self, opcode: u8) {
+        let info: &OpcodeInfo = opcode_info(opcode).expect("dead code only emits defined opcodes");
+        self.depth = self.depth + info.stack_outputs - info.stack_inputs;
+        self.program.push(Instruction::new(opcode));
+    }
+
+    fn emit_push(&mut self, value: usize) {
+        self.program.push(Instruction::push(value, 1));
+        self.depth += 1;
+    }
+
+    fn available(&self, opcode: u8) -> Option<&'static OpcodeInfo> {
+        opcode_info(opcode).filter(|info| info.is_available(self.fork))
+    }
+
+    // `length` instructions that leave `floor` values on the stack
+    fn block(&mut self, floor: usize, length: usize, nesting: usize) {
+        for _ in 0..length {
+            self.instruction(floor, nesting);
+        }
+        while self.depth > floor {
+            self.emit(0x50);
+        }
+    }
+
+    // one instruction of a category drawn by weight, a constant when it cannot be used here
+    fn instruction(&mut self, floor: usize, nesting: usize) {
+        let weights: [u32; 7] = self.mix.weights();
+        let total: u32 = weights.iter().sum();
+        let mut draw: u32 = self.rng.random_range(0..total.max(1));
+        let mut category: usize = 0;
+        while category < weights.len() - 1 && draw >= weights[category] {
+            draw -= weights[category];
+            category += 1;
+        }
+
+        let emitted: bool = match category {
+            1 => self.operation(&ARITHMETIC, floor),
+            2 => self.operation(&BITWISE, floor),
+            3 => self.operation(&ENVIRONMENT, floor),
+            4 => self.stack_operation(floor),
+            5 => self.memory_operation(floor),
+            6 if nesting < MAX_NESTING => self.jump(floor, nesting),
+            _ => false,
+        };
+        if !emitted && self.depth < MAX_STACK_DEPTH {
+            self.constant();
+        }
+    }
+
+    // mostly narrow like the constants of compiled code, now and then a full word
+    fn constant(&mut self) {
+        let width: usize = if self.rng.random_bool(0.85) {
+            self.rng.random_range(1..=4)
+        } else {
+            self.rng.random_range(5..=32)
+        };
+        let mut push: Instruction = Instruction::new(0x5f + width as u8);
+        push.immediate = (0..width).map(|_| self.rng.random()).collect();
+        self.program.push(push);
+        self.depth += 1;
+    }
+
+    // an opcode of `opcodes` whose inputs the dead code has pushed
+    fn operation(&mut self, opcodes: &[u8], floor: usize) -> bool {
+        let usable: Vec<u8> = opcodes
+            .iter()
+            .copied()
+            .filter(|opcode| {
+                self.available(*opcode).is_some_and(|info| {
+                    info.stack_inputs <= self.depth - floor
+                        && self.depth + info.stack_outputs - info.stack_inputs <= MAX_STACK_DEPTH
+                })
+            })
+            .collect();
+        if usable.is_empty() {
+            return false;
+        }
+        let opcode: u8 = usable[self.rng.random_range(0..usable.len())];
+        self.emit(opcode);
+        true
+    }
+
+    // DUP, SWAP or POP within the values above `floor`
+    fn stack_operation(&mut self, floor: usize) -> bool {
+        let owned: usize = self.depth - floor;
+        let mut choices: Vec<u8> = Vec::new();
+        if owned >= 1 {
+            choices.push(0x50);
+        }
+        if owned >= 1 && self.depth < MAX_STACK_DEPTH {
+            choices.extend((0..owned.min(16) as u8).map(|n| 0x80 + n));
+        }
+        if owned >= 2 {
+            choices.extend((0..(owned - 1).min(16) as u8).map(|n| 0x90 + n));
+        }
+        if choices.is_empty() {
+            return false;
+        }
+        let opcode: u8 = choices[self.rng.random_range(0..choices.len())];
+        self.emit(opcode);
+        true
+    }
+
+    // a read or, with the scratch words saved, a write of scratch memory
+    fn memory_operation(&mut self, floor: usize) -> bool {
+        if !self.scratch || self.depth + 2 > MAX_STACK_DEPTH {
+            return false;
+        }
+        let owned: usize = self.depth - floor;
+        match self.rng.random_range(0..4) {
+            // MSTORE / MSTORE8 a value the dead code pushed
+            0 | 1 if owned >= 1 => {
+                let (opcode, size): (u8, usize) = if self.rng.random_bool(0.5) {
+                    (0x52, 32)
+                } else {
+                    (0x53, 1)
+                };
+                let offset: usize = self.rng.random_range(0..=SCRATCH_LENGTH - size);
+                self.emit_push(offset);
+                self.emit(opcode);
+            }
+            2 => {
+                let offset: usize = self.rng.random_range(0..SCRATCH_LENGTH);
+                let size: usize = self.rng.random_range(0..=SCRATCH_LENGTH - offset);
+                self.emit_push(size);
+                self.emit_push(offset);
+                self.emit(0x20);
+            }
+            _ => {
+                let offset: usize = self.rng.random_range(0..=SCRATCH_LENGTH - 32);
+                self.emit_push(offset);
+                self.emit(0x51);
+            }
+        }
+        true
+    }
+
+    // a forward jump to a JUMPDEST of the block, over unreachable code or a branch
+    fn jump(&mut self, floor: usize, nesting: usize) -> bool {
+        if self.depth + 3 > MAX_STACK_DEPTH {
+            return false;
+        }
+        let label: usize = self.program.fresh_label();
+        if self.rng.random_bool(0.5) {
+            self.program.push(Instruction::push_label(label, 2));
+            self.program.push(Instruction::new(0x56));
+            self.unreachable_code();
+        } else {
+            // the condition: a value of the dead code, or a comparison of two constants that
+            // is always false and that the opaque predicates pass disguises
+            if self.depth == floor || self.rng.random_bool(0.5) {
+                let first: usize = self.rng.random_range(0..=0xff);
+                let second: usize = self.rng.random_range(0..=0xff);
+                let comparison: u8 = COMPARISONS[self.rng.random_range(0..COMPARISONS.len())];
+                self.emit_push(first);
+                // pick the second constant, the left operand, so that the comparison is false
+                let second: usize = match comparison {
+                    0x10 | 0x12 => first.max(second),
+                    0x11 | 0x13 => first.min(second),
+                    _ => first ^ (second | 1),
+                };
+                self.emit_push(second);
+                self.emit(comparison);
+            }
+            self.program.push(Instruction::push_label(label, 2));
+            self.emit_jumpi();
+            let length: usize = self.rng.random_range(1..=4);
+            self.block(self.depth, length, nesting + 1);
+        }
+        let mut jumpdest: Instruction = Instruction::new(0x5b);
+        jumpdest.label = Some(label);
+        self.program.push(jumpdest);
+        true
+    }
+
+    // JUMPI after its destination was pushed without counting it; consumes the condition
+    fn emit_jumpi(&mut self) {
+        self.program.push(Instruction::new(0x57));
+        self.depth -= 1;
+    }
+
+    // instructions jumped over: random operations and constants, never executed
+    fn unreachable_code(&mut self) {
+        let length: usize = self.rng.random_range(1..=6);
+        for _ in 0..length {
+            let opcodes: &[u8] = match self.rng.random_range(0..4) {
+                0 => &ARITHMETIC,
+                1 => &BITWISE,
+                2 => &ENVIRONMENT,
+                _ => &[0x50, 0x80, 0x81, 0x90, 0x51, 0x52],
+            };
+            let opcode: u8 = opcodes[self.rng.random_range(0..opcodes.len())];
+            if self.available(opcode).is_some() && self.rng.random_bool(0.7) {
+                self.program.push(Instruction::new(opcode));
+            } else {
+                let width: usize = self.rng.random_range(1..=4);
+                let mut push: Instruction = Instruction::new(0x5f + width as u8);
+                push.immediate = (0..width).map(|_| self.rng.random()).collect();
+                self.program.push(push);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::interpreter::{Account, Address, Evm, ExecutionResult, address_from_low_u64};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use ruint::aliases::U256;
+
+    const CONTRACT: Address = address_from_low_u64(0xc0de);
+
+    fn generate(config: &DeadCodeConfig, fork: Hardfork, seed: u64) -> Program {
+        generate_dead_code(config, fork, &mut StdRng::seed_from_u64(seed))
+    }
+
+    // PUSH1 `value` as the contract would push it
+    fn push(program: &mut Program, value: usize) {
+        program.push(Instruction::push(value, 1));
+    }
+
+    #[test]
+    fn parses_mixes_and_lengths() {
+        let mix: DeadCodeMix = "jump=0, memory=5".parse().unwrap();
+        assert_eq!(
+            mix,
+            DeadCodeMix {
+                jump: 0,
+                memory: 5,
+                ..DeadCodeMix::default()
+            }
+        );
+        assert_eq!(mix.to_string().parse::<DeadCodeMix>(), Ok(mix));
+        assert!("calls=1".parse::<DeadCodeMix>().is_err());
+        assert!("jump".parse::<DeadCodeMix>().is_err());
+        let zero: String = DeadCodeMix::CATEGORIES
+            .iter()
+            .map(|category| format!("{}=0", category))
+            .collect::<Vec<String>>()
+            .join(",");
+        assert!(zero.parse::<DeadCodeMix>().is_err());
+
+        assert_eq!("4-9".parse(), Ok(DeadCodeLength { min: 4, max: 9 }));
+        assert_eq!("7".parse(), Ok(DeadCodeLength { min: 7, max: 7 }));
+        assert!("9-4".parse::<DeadCodeLength>().is_err());
+        assert!("few".parse::<DeadCodeLength>().is_err());
+    }
+
+    #[test]
+    fn leaves_stack_and_memory_as_it_found_them() {
+        let config: DeadCodeConfig = DeadCodeConfig {
+            length: DeadCodeLength { min: 20, max: 60 },
+            mix: "memory=4,jump=3".parse().unwrap(),
+        };
+        for seed in 0..64 {
+            // scratch words 0xaa and 0xbb and the contract's stack 0x11 0x22
+            let mut program: Program = Program::default();
+            for (value, offset) in [(0xaa, 0x00), (0xbb, 0x20)] {
+                push(&mut program, value);
+                push(&mut program, offset);
+                program.push(Instruction::new(0x52));
+            }
+            push(&mut program, 0x11);
+            push(&mut program, 0x22);
+
+            program.extend(generate(&config, Hardfork::Osaka, seed));
+
+            // return the scratch words followed by the stack, top first
+            for offset in [0x40, 0x60] {
+                push(&mut program, offset);
+                program.push(Instruction::new(0x52));
+            }
+            push(&mut program, 0x80);
+            push(&mut program, 0x00);
+            program.push(Instruction::new(0xf3));
+            program.assemble().unwrap();
+
+            let mut evm: Evm = Evm::new();
+            evm.state.accounts.insert(
+                CONTRACT,
+                Account {
+                    code: program.encode(),
+                    ..Account::default()
+                },
+            );
+            let result: ExecutionResult =
+                evm.call(address_from_low_u64(1), CONTRACT, U256::ZERO, &[]);
+            assert!(result.success, "seed {}: {:?}", seed, result.error);
+            let words: Vec<U256> = result.output.chunks(32).map(U256::from_be_slice).collect();
+            assert_eq!(
+                words,
+                [0xaa, 0xbb, 0x22, 0x11].map(U256::from).to_vec(),
+                "seed {}",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn keeps_to_the_fork_and_the_mix() {
+        // neither halting nor touching state
+        let forbidden = |opcode: u8| {
+            matches!(
+                opcode,
+                0x00 | 0x31 | 0x3b | 0x3c | 0x3f | 0x54 | 0x55 | 0x5c | 0x5d | 0xa0..=0xa4 | 0xf0..
+            )
+        };
+        let config: DeadCodeConfig = DeadCodeConfig {
+            length: DeadCodeLength { min: 30, max: 30 },
+            mix: DeadCodeMix::default(),
+        };
+        for seed in 0..32 {
+            let program: Program = generate(&config, Hardfork::Frontier, seed);
+            for instruction in &program.instructions {
+                let info: &OpcodeInfo = instruction.info().unwrap();
+                assert!(info.is_available(Hardfork::Frontier), "{}", instruction);
+                assert!(!forbidden(instruction.opcode), "{}", instruction);
+            }
+        }
+
+        // constants and arithmetic only: no jumps, memory or environment reads
+        let config: DeadCodeConfig = DeadCodeConfig {
+            length: DeadCodeLength { min: 5, max: 10 },
+            mix: "memory=0,jump=0,environment=0,bitwise=0,stack=0"
+                .parse()
+                .unwrap(),
+        };
+        for seed in 0..32 {
+            let program: Program = generate(&config, Hardfork::Osaka, seed);
+            let generated: usize = program
+                .instructions
+                .iter()
+                .filter(|instruction| instruction.opcode != 0x50)
+                .count();
+            assert!((5..=10).contains(&generated), "seed {}", seed);
+            assert!(program.instructions.iter().all(|instruction| {
+                instruction.is_push()
+                    || instruction.opcode == 0x50
+                    || ARITHMETIC.contains(&instruction.opcode)
+            }));
+        }
+    }
+}
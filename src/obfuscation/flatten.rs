@@ -0,0 +1,354 @@
+use crate::analysis::cfg::{BasicBlock, Cfg, Terminator};
+use crate::analysis::stack::StackAnalysis;
+use crate::helper::bytecode::seal_end;
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use rand::{Rng, RngCore};
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/*
+    A state machine dispatcher for the edges of one program. Instead of jumping to a block,
+    code pushes the block's encoded state and jumps to the dispatcher, which
+    1. decodes the state: XOR with a key drawn per program
+    2. finds the block id by binary search, each node comparing the state against the
+       middle id (`DUP1 PUSH4 <id> GT PUSH <left> JUMPI`) and falling through to the
+       right half
+    3. at a leaf POPs the state and jumps to the block, whose stack is then exactly that
+       of the jump
+    every flattened edge ends at the same JUMPDEST, so the order and nesting of the blocks
+    is gone from the CFG; the state only lives on the stack, at most three items above
+    the code's own, between the jump and the block
+*/
+#[derive(Debug, Clone)]
+pub struct Dispatcher {
+    // label of the dispatcher's JUMPDEST, allocated on first use
+    label: Option<usize>,
+    key: u32,
+    // block id of each target label
+    ids: BTreeMap<usize, u32>,
+}
+
+impl Dispatcher {
+    pub fn new(rng: &mut dyn RngCore) -> Self {
+        Dispatcher {
+            label: None,
+            key: rng.random(),
+            ids: BTreeMap::new(),
+        }
+    }
+
+    // number of blocks reached through the dispatcher
+    pub fn blocks(&self) -> usize {
+        self.ids.len()
+    }
+
+    // append `PUSH4 <state>` selecting the instruction labelled `target`
+    pub fn append_state(&mut self, program: &mut Program, target: usize, rng: &mut dyn RngCore) {
+        let state: u32 = self.state(target, rng);
+        program.push(push4(state));
+    }
+
+    // encoded state selecting the instruction labelled `target`
+    fn state(&mut self, target: usize, rng: &mut dyn RngCore) -> u32 {
+        let id: u32 = match self.ids.get(&target) {
+            Some(id) => *id,
+            None => {
+                let used: BTreeSet<u32> = self.ids.values().copied().collect();
+                let mut id: u32 = rng.random();
+                while used.contains(&id) {
+                    id = rng.random();
+                }
+                self.ids.insert(target, id);
+                id
+            }
+        };
+        id ^ self.key
+    }
+
+    // label of the dispatcher's JUMPDEST, drawn from `program` the first time
+    pub fn label(&mut self, program: &mut Program) -> usize {
+        *self.label.get_or_insert_with(|| program.fresh_label())
+    }
+
+    // append the dispatcher itself, once every jump through it is in place
+    pub fn append_to(&self, program: &mut Program) {
+        let Some(label) = self.label else {
+            return;
+        };
+        seal_end(program);
+        let mut entry: Instruction = Instruction::new(0x5b);
+        entry.label = Some(label);
+        program.push(entry);
+        // 1) decode
+        program.push(push4(self.key));
+        program.push(Instruction::new(0x18));
+
+        // 2) - 3)
+        let mut blocks: Vec<(u32, usize)> =
+            self.ids.iter().map(|(target, id)| (*id, *target)).collect();
+        blocks.sort();
+        append_node(program, &blocks);
+    }
+}
+
+// origins of the instructions in blocks the analysis reached; unlike indices they stay
+// valid while code is inserted
+pub fn reachable_origins(program: &Program, analysis: &StackAnalysis) -> BTreeSet<usize> {
+    analysis
+        .cfg
+        .blocks
+        .iter()
+        .filter(|block| analysis.entry_states[block.id].is_some())
+        .flat_map(|block| &program.instructions[block.start..block.end])
+        .filter_map(|instruction| instruction.origin)
+        .collect()
+}
+
+/*
+    Flatten the blocks holding an instruction from `reachable`: every edge between two of
+    them goes through the dispatcher, which leaves each block's successors to the state
+    1. `PUSH <l> JUMP` becomes `PUSH4 <state l> PUSH2 <dispatcher> JUMP`
+    2. `PUSH <l> JUMPI` becomes `ISZERO ISZERO PUSH4 <state l ^ state next> MUL PUSH4
+       <state next> XOR PUSH2 <dispatcher> JUMP`, which computes the state of the edge
+       taken without branching
+    3. a block running into the next one gets `PUSH4 <state next> PUSH2 <dispatcher> JUMP`
+       appended
+    the next block gets a JUMPDEST in front when it does not start with one. Jumps to
+    computed destinations (function returns) are left alone; the code pointers they use
+    are redirected through the dispatcher by the redirect passes. Returns the number of
+    flattened edges
+*/
+pub fn flatten_blocks(
+    program: &mut Program,
+    dispatcher: &mut Dispatcher,
+    reachable: &BTreeSet<usize>,
+    rng: &mut dyn RngCore,
+) -> usize {
+    let cfg: Cfg = Cfg::build(program);
+    let flattened: Vec<bool> = cfg
+        .blocks
+        .iter()
+        .map(|block| {
+            program.instructions[block.start..block.end]
+                .iter()
+                .any(|instruction| {
+                    instruction
+                        .origin
+                        .is_some_and(|origin| reachable.contains(&origin))
+                })
+        })
+        .collect();
+
+    let mut edits: Vec<(Range<usize>, Vec<Instruction>)> = Vec::new();
+    let mut count: usize = 0;
+    for block in &cfg.blocks {
+        if !flattened[block.id] {
+            continue;
+        }
+        let next: Option<&BasicBlock> = cfg
+            .blocks
+            .get(block.id + 1)
+            .filter(|next| flattened[next.id] && next.start == block.end);
+        // the labelled PUSH in front of the jump ending the block, and its target
+        let push: Option<(usize, usize)> = block
+            .last()
+            .checked_sub(1)
+            .filter(|push| *push >= block.start)
+            .and_then(|push| {
+                let instruction: &Instruction = &program.instructions[push];
+                match instruction.label {
+                    None => instruction.target.map(|target| (push, target)),
+                    Some(_) => None,
+                }
+            });
+
+        match (block.terminator, push, next) {
+            // 1)
+            (Terminator::Jump, Some((push, target)), _) => {
+                let mut state: Instruction = push4(dispatcher.state(target, rng));
+                state.origin = program.instructions[push].origin;
+                let label: usize = dispatcher.label(program);
+                edits.push((
+                    push..push + 1,
+                    vec![state, Instruction::push_label(label, 2)],
+                ));
+            }
+            // 2)
+            (Terminator::ConditionalJump, Some((push, target)), Some(next)) => {
+                let push_origin: Option<usize> = program.instructions[push].origin;
+                let jumpi_origin: Option<usize> = program.instructions[push + 1].origin;
+                let next_label: usize = entry_label(program, next, &mut edits);
+                let taken: u32 = dispatcher.state(target, rng);
+                let fallthrough: u32 = dispatcher.state(next_label, rng);
+                let mut difference: Instruction = push4(taken ^ fallthrough);
+                difference.origin = push_origin;
+                let mut jump: Instruction = Instruction::new(0x56);
+                jump.origin = jumpi_origin;
+                let label: usize = dispatcher.label(program);
+                edits.push((
+                    push..push + 2,
+                    vec![
+                        Instruction::new(0x15),
+                        Instruction::new(0x15),
+                        difference,
+                        Instruction::new(0x02),
+                        push4(fallthrough),
+                        Instruction::new(0x18),
+                        Instruction::push_label(label, 2),
+                        jump,
+                    ],
+                ));
+            }
+            // 3)
+            (Terminator::Fallthrough, _, Some(next)) => {
+                let next_label: usize = entry_label(program, next, &mut edits);
+                let state: Instruction = push4(dispatcher.state(next_label, rng));
+                let label: usize = dispatcher.label(program);
+                edits.push((
+                    block.end..block.end,
+                    vec![
+                        state,
+                        Instruction::push_label(label, 2),
+                        Instruction::new(0x56),
+                    ],
+                ));
+            }
+            _ => continue,
+        }
+        count += 1;
+    }
+
+    // applied back to front so the ranges of the others stay put; a JUMPDEST put in front
+    // of a block was added before the block's own edit, which the stable sort keeps
+    edits.sort_by_key(|(range, _)| range.start);
+    for (range, instructions) in edits.into_iter().rev() {
+        program.instructions.splice(range, instructions);
+    }
+    program.reindex();
+    count
+}
+
+// label of the JUMPDEST starting `block`, adding an edit that puts one in front of it when
+// it starts with something else
+fn entry_label(
+    program: &mut Program,
+    block: &BasicBlock,
+    edits: &mut Vec<(Range<usize>, Vec<Instruction>)>,
+) -> usize {
+    if program.instructions[block.start].opcode == 0x5b {
+        return program.label_at(block.start);
+    }
+    let label: usize = program.fresh_label();
+    let mut jumpdest: Instruction = Instruction::new(0x5b);
+    jumpdest.label = Some(label);
+    edits.push((block.start..block.start, vec![jumpdest]));
+    label
+}
+
+// the search over `blocks`, sorted by id, starting at the current end of the program
+fn append_node(program: &mut Program, blocks: &[(u32, usize)]) {
+    if let [(_, target)] = blocks {
+        program.push(Instruction::new(0x50));
+        program.push(Instruction::push_label(*target, 2));
+        program.push(Instruction::new(0x56));
+        return;
+    }
+
+    // below the middle id: jump left, otherwise fall through to the right half
+    let middle: usize = blocks.len() / 2;
+    program.push(Instruction::new(0x80));
+    program.push(push4(blocks[middle].0));
+    program.push(Instruction::new(0x11));
+    let left: usize = program.fresh_label();
+    program.push(Instruction::push_label(left, 2));
+    program.push(Instruction::new(0x57));
+    append_node(program, &blocks[middle..]);
+
+    let mut jumpdest: Instruction = Instruction::new(0x5b);
+    jumpdest.label = Some(left);
+    program.push(jumpdest);
+    append_node(program, &blocks[..middle]);
+}
+
+fn push4(value: u32) -> Instruction {
+    let mut push: Instruction = Instruction::new(0x63);
+    push.immediate = value.to_be_bytes().to_vec();
+    push
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::stack::analyze_stack;
+    use crate::evm::interpreter::{Account, Address, Evm, ExecutionResult, address_from_low_u64};
+    use crate::helper::hex::decode_hex;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use ruint::aliases::U256;
+
+    const CONTRACT: Address = address_from_low_u64(0xc0de);
+
+    /*
+        stores 2 without calldata and 3 with some, through each kind of edge:
+        0x00: CALLDATASIZE PUSH1 0x0a JUMPI
+        0x04: PUSH1 2 PUSH1 0x0d JUMP
+        0x09: STOP
+        0x0a: JUMPDEST PUSH1 3            falls through
+        0x0d: JUMPDEST PUSH1 0 SSTORE     runs off the end of the code
+    */
+    fn flattened(seed: u64) -> (Program, usize) {
+        let mut program: Program =
+            Program::decode(&decode_hex("36600a576002600d56005b60035b600055").unwrap());
+        program.mark_origins();
+        let taken: usize = program.label_at(7);
+        program.instructions[1].target = Some(taken);
+        let joined: usize = program.label_at(9);
+        program.instructions[4].target = Some(joined);
+
+        let mut rng: StdRng = StdRng::seed_from_u64(seed);
+        let reachable: BTreeSet<usize> = reachable_origins(&program, &analyze_stack(&program));
+        let mut dispatcher: Dispatcher = Dispatcher::new(&mut rng);
+        let count: usize = flatten_blocks(&mut program, &mut dispatcher, &reachable, &mut rng);
+        dispatcher.append_to(&mut program);
+        program.assemble().unwrap();
+        (program, count)
+    }
+
+    fn stored(program: &Program, calldata: &[u8]) -> U256 {
+        let mut evm: Evm = Evm::new();
+        evm.state.accounts.insert(
+            CONTRACT,
+            Account {
+                code: program.encode(),
+                ..Account::default()
+            },
+        );
+        let result: ExecutionResult = evm.call(CONTRACT, CONTRACT, U256::ZERO, calldata);
+        assert!(result.success, "{:?}", result.error);
+        evm.account(&CONTRACT)
+            .unwrap()
+            .storage
+            .get(&U256::ZERO)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn flattens_every_kind_of_edge() {
+        for seed in 0..8 {
+            let (program, count) = flattened(seed);
+            // the JUMPI, the JUMP and the fallthrough; the last block has no successor
+            assert_eq!(count, 3);
+            let jumps: usize = program
+                .instructions
+                .iter()
+                .filter(|instruction| instruction.opcode == 0x57)
+                .count();
+            // only the dispatcher's search branches, on its 3 ids
+            assert_eq!(jumps, 2);
+            assert_eq!(stored(&program, &[]), U256::from(2));
+            assert_eq!(stored(&program, &[1]), U256::from(3));
+        }
+    }
+}
@@ -1 +1,9 @@
+pub mod artifact;
+pub mod config;
+pub mod dead_code;
+pub mod flatten;
 pub mod obfuscate;
+pub mod opaque;
+pub mod reorder;
+pub mod report;
+pub mod selectors;
@@ -1,79 +1,519 @@
-use crate::analysis::code_type::seperate_bytecode;
-use crate::analysis::jump_seq::{PushPositions, find_jump_seq};
-use crate::analysis::push_codecopy_seq::update_runtime_offset;
+use crate::analysis::code_type::{
+    CreationSplit, InitcodeTrace, SplitHeuristic, split_creation_code, trace_initcode,
+};
+use crate::analysis::fork_support::find_unsupported_opcodes;
+use crate::analysis::immutables::{
+    ImmutableReference, LabelledReference, infer_immutable_references, label_immutable_references,
+    narrow_immutable_write, patch_immutable_writes, relocate_immutable_references,
+};
+use crate::analysis::jump_seq::{
+    PushPositions, find_code_pointer_seq, find_jump_seq, find_jumpi_seq,
+};
+use crate::analysis::metadata::{Metadata, decode_metadata, metadata_length};
+use crate::analysis::push_codecopy_seq::{
+    check_deploy_site, patch_appended_data_offset, patch_deploy_site, update_runtime_offset,
+};
+use crate::analysis::stack::{StackAnalysis, analyze_stack};
 
+use crate::error::ObfuscatorError;
 use crate::helper::bytecode::{
-    append_jumpdest, append_push_jump, get_dead_bytecode, get_last_instruction_position,
-    modify_push_val, rm_zero_x,
+    append_jumpdest, append_push_jump, label_code_pointers, layout_obstacle,
+};
+use crate::helper::link::{
+    LabelledLink, LinkReference, decode_unlinked_hex, label_link_references, link,
+    relocate_link_references,
+};
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use crate::obfuscation::config::{MetadataMode, ObfuscationConfig, Pass};
+use crate::obfuscation::dead_code::generate_dead_code;
+use crate::obfuscation::flatten::{Dispatcher, flatten_blocks, reachable_origins};
+use crate::obfuscation::opaque::{
+    append_guarded_jump, append_skipped, decoy_jumpdests, guard_bogus_edges,
 };
+use crate::obfuscation::reorder::reorder_blocks;
+use crate::obfuscation::report::{ObfuscationReport, SkippedPass};
+use crate::obfuscation::selectors::{SelectorSite, find_selector_sites, mask_selectors};
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use std::collections::BTreeSet;
 
 /*  OBFUSCATION STEPS
-1) seperate out initcode from the runtime bytecode
-2) in the runtime bytecode check for the push-jump seq
-3) for each push-jump, change the push's parameter to newly appended jumpdest
-    3a) append jump dest at the end of the bytecode
-    3b) generate dead bytecode and fix the push-jump param according to the total instructions in the bytecode
-    3c) append deadbytecode at the end
-    3d) append push-jump with correct push value pointing to original jumpdest location.
-4) update runtime length in the initcode
+0) reject input that uses opcodes the target fork does not have
+1) seperate out initcode from the runtime bytecode, and the metadata trailer from the runtime
+    so that new code is inserted in front of it; label the PUSH placeholders of immutables
+    and of library addresses not linked yet
+2) in the runtime bytecode check for the push-jump and push-jumpi seq, and for code pointers
+    pushed further away from their jump (found by abstract stack interpretation), and
+    for the selector comparisons of the function dispatcher
+3) label every code pointer's destination JUMPDEST, so pushes follow their JUMPDEST if code moves
+4) for each push-jump(i), point the push at a newly appended jumpdest
+    4a) append a labelled jump dest at the end of the bytecode
+    4b) generate stack neutral dead bytecode, its internal jumps target labels of its own
+    4c) append deadbytecode at the end, behind an always true opaque predicate jumping over
+        it so it never runs; with opaque predicates its constant guards become opaque ones
+        jumping into real JUMPDESTs
+    4d) append push-jump pointing to the original jumpdest's label, with opaque predicates
+        a JUMPI behind an always true one whose fall through jumps to a real JUMPDEST.
+        when flattening, push the original jumpdest's state and jump to the dispatcher
+        instead
+    4e) mask the selector comparisons, with fake ones in between; skipped like 4g)
+    4f) when flattening, route every edge between the reachable blocks of the code through
+        the dispatcher too, then append the dispatcher; skipped like 4g)
+    4g) when reordering, shuffle the basic blocks of the whole program, appended ones
+        included, with explicit jumps where a block ran into the next one; skipped when
+        some code depends on the layout (unknown jumps, CODECOPY, PC)
+    for a JUMPI the condition is consumed by the original JUMPI, so only the taken edge
+    reaches the trampoline and the fallthrough edge is left untouched; a code pointer is
+    handled like a push-jump whose jump happens later
+5) assemble: write label offsets into the pushes, widening the ones that are too narrow
+6) put the metadata trailer back at the tail, kept, stripped or rewritten
+7) when enabled, apply 2) - 5) to the initcode too
+8) update the runtime offset and length in the initcode, the offset at which the
+    constructor reads its arguments and where it writes immutables, then check that the patched initcode deploys exactly
+    the new runtime
 */
 
-pub fn obfuscate(creation_bytecode: &mut String, max_iterations: usize) {
-    // Remove 0x from the start
-    rm_zero_x(creation_bytecode);
+// number of sites of each kind redirected in one program
+#[derive(Debug, Clone, Default)]
+struct RedirectCounts {
+    jumps: usize,
+    conditional_jumps: usize,
+    pointers: usize,
+    // distinct blocks behind the dispatcher, and edges of the code routed through it
+    dispatched: usize,
+    flattened: usize,
+    // basic blocks moved by the reordering
+    reordered: usize,
+    // masked selector comparisons and fake ones added
+    selectors: usize,
+    fake_selectors: usize,
+    // enabled passes that were not applied, and why
+    skipped: Vec<(Pass, &'static str)>,
+}
+
+impl RedirectCounts {
+    fn total(&self) -> usize {
+        self.jumps + self.conditional_jumps + self.pointers
+    }
+}
+
+// obfuscate with a StdRng seeded from `config.seed`, or from a fresh random seed that
+// is recorded in the report so the exact output can be reproduced later
+pub fn obfuscate(
+    creation_bytecode: &str,
+    config: &ObfuscationConfig,
+) -> Result<ObfuscationReport, ObfuscatorError> {
+    let seed: u64 = config.seed.unwrap_or_else(|| rand::rng().random());
+    let mut rng: StdRng = StdRng::seed_from_u64(seed);
+
+    let mut report: ObfuscationReport = obfuscate_with_rng(creation_bytecode, config, &mut rng)?;
+    report.seed = Some(seed);
+    Ok(report)
+}
+
+// obfuscate drawing every random choice from the given rng
+pub fn obfuscate_with_rng(
+    creation_bytecode: &str,
+    config: &ObfuscationConfig,
+    rng: &mut dyn RngCore,
+) -> Result<ObfuscationReport, ObfuscatorError> {
+    // Decode the hex input (0x prefix is optional) into the instruction IR; library
+    // placeholders decode as the zero address and are linked right away when known
+    let (mut creation_bytes, link_references) = decode_unlinked_hex(creation_bytecode)?;
+    let link_references: Vec<LinkReference> =
+        link(&mut creation_bytes, &link_references, &config.libraries)?;
+
+    // constructor arguments given separately are not code, even when the input carries them
+    let constructor_args: Vec<u8> = config.constructor_args.clone().unwrap_or_default();
+    if !constructor_args.is_empty() && creation_bytes.ends_with(&constructor_args) {
+        creation_bytes.truncate(creation_bytes.len() - constructor_args.len());
+    }
+    if creation_bytes.is_empty() {
+        return Err(ObfuscatorError::EmptyInput);
+    }
+    let creation_program: Program = Program::decode(&creation_bytes);
+
+    // PUSH data cut off by the end of the input is only acceptable inside the metadata trailer
+    if let Some(truncated) = creation_program.truncated_push() {
+        let metadata_start: usize =
+            creation_bytes.len() - metadata_length(&creation_bytes).unwrap_or(0);
+        if truncated.offset < metadata_start {
+            return Err(ObfuscatorError::TruncatedPush {
+                offset: truncated.offset,
+                expected: truncated.push_width(),
+                found: truncated.immediate.len(),
+            });
+        }
+    }
+
+    // 0) the output keeps every original instruction, so it can only run where the input does
+    let unsupported: Vec<Instruction> =
+        find_unsupported_opcodes(&creation_program, config.target_fork);
+    if let Some(instruction) = unsupported.first() {
+        return Err(ObfuscatorError::UnsupportedOpcode {
+            offset: instruction.offset,
+            mnemonic: instruction.name(),
+            fork: config.target_fork,
+        });
+    }
+
+    // 1) seperate init code and runtime code; without a recognisable initcode the whole
+    // input is treated as runtime
+    let split: Option<CreationSplit> = split_creation_code(&creation_program);
+    let split_heuristic: Option<SplitHeuristic> = split.as_ref().map(|split| split.heuristic);
+    let (mut init_code, mut runtime_bytecode, trailing, deploy) = match split {
+        Some(split) => (split.init_code, split.runtime, split.trailing, split.deploy),
+        None => (
+            Program::default(),
+            creation_program.clone(),
+            Vec::new(),
+            None,
+        ),
+    };
+    let original_runtime_length: usize = runtime_bytecode.len();
+    // constructor arguments (and other appended data) start right behind the runtime, and
+    // the constructor finds them relative to there
+    let original_data_offset: usize = init_code.len() + original_runtime_length;
+    let trace: InitcodeTrace = match deploy {
+        Some(_) => trace_initcode(&creation_program),
+        None => InitcodeTrace::default(),
+    };
+
+    // the trailer's bytes only decode as instructions by accident, it is set aside as data;
+    // when the code does not end exactly where it starts everything is treated as code
+    let runtime_bytes: Vec<u8> = runtime_bytecode.encode();
+    let code_end: usize = runtime_bytes.len() - metadata_length(&runtime_bytes).unwrap_or(0);
+    let metadata_bytes: Vec<u8> = match runtime_bytecode.index_at_offset(code_end) {
+        Some(index) => {
+            runtime_bytecode.instructions.truncate(index);
+            runtime_bytes[code_end..].to_vec()
+        }
+        None => Vec::new(),
+    };
+    // source maps have an entry per instruction of the input, metadata excluded
+    init_code.mark_origins();
+    runtime_bytecode.mark_origins();
 
-    // 1) seperate init code and runtime code
-    let (init_code, mut runtime_bytecode) = seperate_bytecode(&creation_bytecode)
-        .unwrap_or_else(|| (String::new(), creation_bytecode.clone()));
+    // immutables are written into the deployed copy at fixed offsets; labelled, their
+    // placeholders can be found again wherever the runtime moves them
+    let immutable_references: Vec<ImmutableReference> =
+        match (&config.immutable_references, &deploy) {
+            (Some(references), _) => references.clone(),
+            (None, Some(deploy)) => {
+                infer_immutable_references(&runtime_bytecode, deploy, &trace.runtime_writes)
+            }
+            (None, None) => Vec::new(),
+        };
+    let immutables: Vec<LabelledReference> =
+        label_immutable_references(&mut runtime_bytecode, &immutable_references)?;
+    // unlinked library addresses are PUSH20s that move with the code like immutables
+    let links: Vec<LabelledLink> =
+        label_link_references(&mut init_code, &mut runtime_bytecode, &link_references)?;
 
-    println!("Initcode and Runtime bytecode seperated");
-    // 2) Get all PUSH-JUMP sequence
-    let push_jump_seq: Vec<PushPositions> = find_jump_seq(&runtime_bytecode.clone());
+    // code in front of an immutable placeholder may only move as far as its write can follow
+    let fixed_layout: Option<&'static str> = match &deploy {
+        Some(deploy)
+            if !immutables.is_empty() && narrow_immutable_write(&init_code, deploy, &trace) =>
+        {
+            Some("the constructor writes an immutable through a PUSH1")
+        }
+        _ => None,
+    };
 
-    // 3) For each sequence, change the push's param to the newly added JUPDEST's instruction position
-    // iterate over all the push-jump seq
+    // 2) - 5) redirect the runtime's jumps
+    let runtime_redirects: RedirectCounts = redirect_jumps(
+        &mut runtime_bytecode,
+        config,
+        config.max_iterations,
+        fixed_layout,
+        rng,
+    )?;
+    let runtime_origins: Vec<Option<usize>> = runtime_bytecode.origins();
 
-    for (index, push_jump) in push_jump_seq.iter().enumerate().take(max_iterations) {
-        println!("Obfuscating iteration: {}", index + 1);
-        // 3a) Append JUMPDEST at the end
+    // 6) append the metadata trailer again; dropping the content hashes leaves only the
+    // compiler version, and an empty map is not emitted at all
+    let metadata_bytes: Vec<u8> = match config.metadata {
+        MetadataMode::Keep => metadata_bytes,
+        MetadataMode::Strip => Vec::new(),
+        MetadataMode::Rewrite => {
+            let rewritten: Option<Metadata> =
+                decode_metadata(&metadata_bytes).map(|metadata| metadata.without_source_hashes());
+            match rewritten {
+                Some(metadata) if !metadata.entries.is_empty() => metadata.encode(),
+                Some(_) => Vec::new(),
+                None => metadata_bytes,
+            }
+        }
+    };
+    runtime_bytecode.extend(Program::decode(&metadata_bytes));
 
-        // Append jumpdest at the end of the bytecode
-        append_jumpdest(&mut runtime_bytecode);
+    // 7) optionally run the same passes over the initcode, with what is left of the budget;
+    // it grows, so the runtime offset has to be known to be patched afterwards
+    let original_initcode_length: usize = init_code.len();
+    let mut initcode_redirects: RedirectCounts = RedirectCounts::default();
+    if config.obfuscate_initcode && !init_code.is_empty() {
+        if deploy.is_none() {
+            return Err(ObfuscatorError::UnexpectedInitcode(String::from(
+                "the runtime offset is unknown, so the initcode cannot grow",
+            )));
+        }
+        let budget: usize = config
+            .max_iterations
+            .saturating_sub(runtime_redirects.total());
+        initcode_redirects = redirect_jumps(&mut init_code, config, budget, None, rng)?;
+    }
+
+    // 8) update runtime offset and length in the initcode
+    let runtime_bytes: Vec<u8> = runtime_bytecode.encode();
+    let mut moved_immutables: usize = 0;
+    if !init_code.is_empty() {
+        match &deploy {
+            Some(deploy) => {
+                patch_deploy_site(&mut init_code, deploy, runtime_bytes.len())?;
+                let data_offset: usize = init_code.len() + runtime_bytes.len();
+                patch_appended_data_offset(
+                    &mut init_code,
+                    &trace,
+                    original_data_offset,
+                    data_offset,
+                )?;
+                moved_immutables = patch_immutable_writes(
+                    &mut init_code,
+                    &runtime_bytecode,
+                    deploy,
+                    &trace,
+                    &immutables,
+                )?;
+            }
+            None if !immutables.is_empty() => {
+                return Err(ObfuscatorError::UnexpectedInitcode(String::from(
+                    "the deploying CODECOPY / RETURN was not found, so immutable writes cannot be moved",
+                )));
+            }
+            // the layout heuristic only knows the PUSH holding the length
+            None => update_runtime_offset(&mut init_code, &runtime_bytecode)?,
+        }
+    }
 
-        // get original jumpdest's position
-        let ideal_jumpdest_position: String = push_jump.value_hex.clone();
+    // concatenate init code, runtime code, whatever followed the runtime and the arguments
+    let mut output: Vec<u8> = init_code.encode();
+    output.extend_from_slice(&runtime_bytes);
+    output.extend_from_slice(&trailing);
+    output.extend_from_slice(&constructor_args);
 
-        // get the byteoffset of appended JUMPDEST
-        let appended_jumpdest_pos: i32 = get_last_instruction_position(&runtime_bytecode);
+    if deploy.is_some() {
+        check_deploy_site(&Program::decode(&output), init_code.len(), &runtime_bytes)?;
+    }
+
+    let skipped_passes: Vec<SkippedPass> =
+        [(&runtime_redirects, false), (&initcode_redirects, true)]
+            .into_iter()
+            .flat_map(|(counts, initcode)| {
+                counts
+                    .skipped
+                    .iter()
+                    .map(move |(pass, reason)| SkippedPass {
+                        pass: *pass,
+                        initcode,
+                        reason,
+                    })
+            })
+            .collect();
 
-        // now change the push value to the newly added JUMPDEST's instruction position
-        modify_push_val(
-            &mut runtime_bytecode,
-            push_jump.byteoffset_decimal,
-            appended_jumpdest_pos,
-            &push_jump.instruction_bits,
-        );
+    let link_references: Vec<LinkReference> =
+        relocate_link_references(&init_code, &runtime_bytecode, &links)?;
+    let immutable_references: Vec<ImmutableReference> =
+        relocate_immutable_references(&runtime_bytecode, &immutables)?;
 
-        // 3b) Generate dead bytecode with correct push values
+    Ok(ObfuscationReport {
+        bytecode: output,
+        original_initcode_length,
+        initcode_length: init_code.len(),
+        original_runtime_length,
+        runtime_length: runtime_bytecode.len(),
+        constructor_args_length: trailing.len() + constructor_args.len(),
+        metadata_length: metadata_bytes.len(),
+        redirected_jumps: runtime_redirects.jumps,
+        redirected_conditional_jumps: runtime_redirects.conditional_jumps,
+        redirected_pointers: runtime_redirects.pointers,
+        initcode_redirects: initcode_redirects.total(),
+        dispatched_blocks: runtime_redirects.dispatched,
+        flattened_edges: runtime_redirects.flattened,
+        reordered_blocks: runtime_redirects.reordered,
+        masked_selectors: runtime_redirects.selectors,
+        fake_selectors: runtime_redirects.fake_selectors,
+        skipped_passes,
+        immutable_references,
+        moved_immutables,
+        initcode_origins: init_code.origins(),
+        runtime_origins,
+        link_references,
+        split: split_heuristic,
+        target_fork: config.target_fork,
+        seed: None,
+    })
+}
 
-        let last_ins_position: i32 = get_last_instruction_position(&runtime_bytecode);
-        let dead_bytecode: String = get_dead_bytecode(last_ins_position);
+// steps 2) - 5) on one program: redirect at most `budget` sites through appended JUMPDESTs,
+// dead code and trampolines, then assemble it; `fixed_layout` is why its code must not
+// move, if the caller knows a reason
+fn redirect_jumps(
+    program: &mut Program,
+    config: &ObfuscationConfig,
+    budget: usize,
+    fixed_layout: Option<&'static str>,
+    rng: &mut dyn RngCore,
+) -> Result<RedirectCounts, ObfuscatorError> {
+    // 2) Get all PUSH-JUMP and PUSH-JUMPI sequences and code pointers
+    let stack_analysis: StackAnalysis = analyze_stack(program);
+    let jumps: Vec<PushPositions> = find_jump_seq(program, &stack_analysis);
+    let conditional_jumps: Vec<PushPositions> = find_jumpi_seq(program, &stack_analysis);
+    let pointers: Vec<PushPositions> = find_code_pointer_seq(program, &stack_analysis);
 
-        // 3c) append dead bytecode at the end
-        runtime_bytecode.push_str(&dead_bytecode);
+    // 3) label the destinations of every known code pointer, redirected or not
+    label_code_pointers(program, &jumps);
+    label_code_pointers(program, &conditional_jumps);
+    label_code_pointers(program, &pointers);
+    let original_instructions: usize = program.instructions.len();
+    let reachable: BTreeSet<usize> = reachable_origins(program, &stack_analysis);
+    let code_obstacle: Option<&'static str> = layout_obstacle(program, &stack_analysis);
+    let layout_obstacle: Option<&'static str> = fixed_layout.or(code_obstacle);
+    // the dispatcher is walked through the labels of its JUMPI destinations
+    let selector_sites: Vec<SelectorSite> = if config.is_enabled(Pass::MaskSelectors) {
+        find_selector_sites(program, &stack_analysis)
+    } else {
+        Vec::new()
+    };
+    let opaque: bool = config.is_enabled(Pass::OpaquePredicates);
+    let decoys: Vec<usize> = if opaque {
+        decoy_jumpdests(program, original_instructions)
+    } else {
+        Vec::new()
+    };
 
-        // 3d) append push-jump at the end jumping to the original JUMPDEST
-        append_push_jump(&mut runtime_bytecode, ideal_jumpdest_position);
+    let mut push_jump_seq: Vec<PushPositions> = Vec::new();
+    if config.is_enabled(Pass::JumpRedirect) {
+        push_jump_seq.extend(jumps);
     }
+    if config.is_enabled(Pass::JumpiRedirect) {
+        push_jump_seq.extend(conditional_jumps);
+    }
+    if config.is_enabled(Pass::PointerRedirect) {
+        push_jump_seq.extend(pointers);
+    }
+    // a constant destination that is not a JUMPDEST has no label and already fails
+    push_jump_seq.retain(|push_jump| {
+        program.instructions[push_jump.instruction_index]
+            .target
+            .is_some()
+    });
+    push_jump_seq.sort_by_key(|push_jump| push_jump.byte_offset);
+
+    // 4) For each sequence, point the push at a newly added JUMPDEST
+    let mut counts: RedirectCounts = RedirectCounts::default();
+    let mut dispatcher: Option<Dispatcher> = if config.is_enabled(Pass::Flatten) {
+        Some(Dispatcher::new(rng))
+    } else {
+        None
+    };
+
+    for push_jump in push_jump_seq.iter().take(budget) {
+        let push_index: usize = push_jump.instruction_index;
+        let original_target: Option<usize> = program.instructions[push_index].target;
+
+        // 4a) Append JUMPDEST at the end and point the push at it
+        let appended_jumpdest: usize = append_jumpdest(program);
+        program.instructions[push_index].target = Some(appended_jumpdest);
+
+        if config.is_enabled(Pass::DeadCode) {
+            // 4b) Generate dead bytecode with labelled jumps
+            let dead_bytecode: Program =
+                generate_dead_code(&config.dead_code, config.target_fork, rng);
 
-    // concatenate init code and runtime code
-    creation_bytecode.clear();
-    creation_bytecode.push_str(&init_code);
-    creation_bytecode.push_str(&runtime_bytecode);
+            // 4c) append dead bytecode at the end, jumped over
+            let start: usize = append_skipped(program, dead_bytecode, config.target_fork, rng);
+            if opaque {
+                guard_bogus_edges(program, start, &decoys, config.target_fork, rng);
+            }
+        }
+
+        // 4d) append push-jump at the end jumping to the original JUMPDEST
+        match (original_target, dispatcher.as_mut()) {
+            (Some(original_target), Some(dispatcher)) => {
+                dispatcher.append_state(program, original_target, rng);
+                let label: usize = dispatcher.label(program);
+                if opaque {
+                    append_guarded_jump(program, label, &decoys, config.target_fork, rng);
+                } else {
+                    append_push_jump(program, label);
+                }
+            }
+            (Some(original_target), None) if opaque => {
+                append_guarded_jump(program, original_target, &decoys, config.target_fork, rng)
+            }
+            (Some(original_target), None) => append_push_jump(program, original_target),
+            (None, _) => {}
+        }
+        match push_jump.next_opcode {
+            0x56 => counts.jumps += 1,
+            0x57 => counts.conditional_jumps += 1,
+            _ => counts.pointers += 1,
+        }
+    }
+
+    // 4e) mask the selectors; the comparisons grow, which moves the code after them
+    if !selector_sites.is_empty() {
+        match layout_obstacle {
+            Some(reason) => counts.skipped.push((Pass::MaskSelectors, reason)),
+            None => {
+                counts.fake_selectors = mask_selectors(program, &selector_sites, rng);
+                counts.selectors = selector_sites.len();
+            }
+        }
+    }
+
+    // 4f) flatten the code's own edges, now that the comparisons are in place
+    if let Some(dispatcher) = dispatcher.as_mut() {
+        match layout_obstacle {
+            Some(reason) => counts.skipped.push((Pass::Flatten, reason)),
+            None => counts.flattened = flatten_blocks(program, dispatcher, &reachable, rng),
+        }
+        dispatcher.append_to(program);
+        counts.dispatched = dispatcher.blocks();
+    }
+
+    // 4g) shuffle the blocks, once every label they jump to is in place
+    if config.is_enabled(Pass::ReorderBlocks) {
+        match layout_obstacle {
+            Some(reason) => counts.skipped.push((Pass::ReorderBlocks, reason)),
+            None => counts.reordered = reorder_blocks(program, rng),
+        }
+    }
+
+    // 5) assemble; widened pushes in the original code move every instruction after them,
+    // which is only safe when nothing in the code depends on its layout (immutable writes
+    // of the constructor are patched afterwards)
+    let original_end: usize = original_code_end(program, original_instructions)?;
+    program.assemble()?;
+    let assembled_end: usize = original_code_end(program, original_instructions)?;
+    if assembled_end != original_end
+        && let Some(reason) = code_obstacle
+    {
+        return Err(ObfuscatorError::FixedLayout(reason.to_string()));
+    }
+    Ok(counts)
+}
 
-    // 4) update runtime length in the initcode
-    println!("Updating runtime offset in initcode");
-    update_runtime_offset(creation_bytecode, &runtime_bytecode);
-    println!("Updated runtime offset in initcode");
-    // println!("{}",bytecode);
+// end offset of the first `count` instructions, the original code the passes appended to
+fn original_code_end(program: &Program, count: usize) -> Result<usize, ObfuscatorError> {
+    let original: &[Instruction] =
+        program
+            .instructions
+            .get(..count)
+            .ok_or(ObfuscatorError::MissingInstructions {
+                expected: count,
+                found: program.instructions.len(),
+            })?;
+    Ok(original.last().map_or(0, |last| last.offset + last.size()))
 }
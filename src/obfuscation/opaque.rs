@@ -0,0 +1,314 @@
+use crate::constant::hardfork::Hardfork;
+use crate::constant::opcodes::opcode_info;
+use crate::helper::bytecode::append_push_jump;
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use rand::{Rng, RngCore};
+
+// opcodes pushing a value the code cannot know in advance; none of them reads storage
+const ENVIRONMENT_VALUES: [u8; 16] = [
+    0x30, // ADDRESS
+    0x32, // ORIGIN
+    0x33, // CALLER
+    0x34, // CALLVALUE
+    0x36, // CALLDATASIZE
+    0x38, // CODESIZE
+    0x3a, // GASPRICE
+    0x3d, // RETURNDATASIZE
+    0x41, // COINBASE
+    0x42, // TIMESTAMP
+    0x43, // NUMBER
+    0x46, // CHAINID
+    0x47, // SELFBALANCE
+    0x48, // BASEFEE
+    0x59, // MSIZE
+    0x5a, // GAS
+];
+
+// opcodes pushing a 160 bit address
+const ADDRESS_VALUES: [u8; 4] = [
+    0x30, // ADDRESS
+    0x32, // ORIGIN
+    0x33, // CALLER
+    0x41, // COINBASE
+];
+
+/*
+    An opaque predicate pushes 1 when `outcome` is true and 0 otherwise, whatever the
+    environment; its value only follows from an identity, so a constant folder cannot
+    remove it. x and y are environment values or calldata words, and the false forms are:
+    1. x * (x + 1) is even:                x DUP1 PUSH1 1 ADD MUL PUSH1 1 AND
+    2. x * x mod 4 is 0 or 1:              x DUP1 MUL PUSH1 3 AND PUSH1 1 LT
+    3. x | y is never below x, x & y never above it:
+                                           x y DUP2 OR LT  /  x y DUP2 AND GT
+    4. x + x is even:                      x DUP1 ADD PUSH1 1 AND
+    5. an address has no bits above 160:   PUSH21 2^160 <address> DIV
+    arithmetic wraps modulo 2^256, which keeps every residue modulo a power of two, so the
+    identities hold for any value. The true form negates a false one with ISZERO
+*/
+pub fn opaque_predicate(outcome: bool, fork: Hardfork, rng: &mut dyn RngCore) -> Program {
+    let mut instructions: Vec<Instruction> = Vec::new();
+    match rng.random_range(0..5) {
+        0 => {
+            instructions.extend(random_operand(fork, rng));
+            instructions.push(Instruction::new(0x80));
+            instructions.push(Instruction::push(1, 1));
+            instructions.push(Instruction::new(0x01));
+            instructions.push(Instruction::new(0x02));
+            instructions.push(Instruction::push(1, 1));
+            instructions.push(Instruction::new(0x16));
+        }
+        1 => {
+            instructions.extend(random_operand(fork, rng));
+            instructions.push(Instruction::new(0x80));
+            instructions.push(Instruction::new(0x02));
+            instructions.push(Instruction::push(3, 1));
+            instructions.push(Instruction::new(0x16));
+            instructions.push(Instruction::push(1, 1));
+            instructions.push(Instruction::new(0x10));
+        }
+        2 => {
+            instructions.extend(random_operand(fork, rng));
+            instructions.extend(random_operand(fork, rng));
+            instructions.push(Instruction::new(0x81));
+            // OR with LT, or AND with GT
+            let (combine, compare): (u8, u8) = if rng.random_bool(0.5) {
+                (0x17, 0x10)
+            } else {
+                (0x16, 0x11)
+            };
+            instructions.push(Instruction::new(combine));
+            instructions.push(Instruction::new(compare));
+        }
+        3 => {
+            instructions.extend(random_operand(fork, rng));
+            instructions.push(Instruction::new(0x80));
+            instructions.push(Instruction::new(0x01));
+            instructions.push(Instruction::push(1, 1));
+            instructions.push(Instruction::new(0x16));
+        }
+        _ => {
+            let mut bound: Instruction = Instruction::new(0x74);
+            bound.immediate = vec![0; 21];
+            bound.immediate[0] = 1;
+            instructions.push(bound);
+            let address: u8 = ADDRESS_VALUES[rng.random_range(0..ADDRESS_VALUES.len())];
+            instructions.push(Instruction::new(address));
+            instructions.push(Instruction::new(0x04));
+        }
+    }
+    if outcome {
+        instructions.push(Instruction::new(0x15));
+    }
+
+    let mut predicate: Program = Program::default();
+    for instruction in instructions {
+        predicate.push(instruction);
+    }
+    predicate
+}
+
+// instructions pushing a value unknown before execution: an environment value available
+// on the fork or a calldata word at a random offset
+fn random_operand(fork: Hardfork, rng: &mut dyn RngCore) -> Vec<Instruction> {
+    let available: Vec<u8> = ENVIRONMENT_VALUES
+        .iter()
+        .copied()
+        .filter(|opcode| opcode_info(*opcode).is_some_and(|info| info.is_available(fork)))
+        .collect();
+    let choice: usize = rng.random_range(0..=available.len());
+    match available.get(choice) {
+        Some(opcode) => vec![Instruction::new(*opcode)],
+        None => vec![
+            Instruction::push(rng.random_range(0..=0xff), 1),
+            Instruction::new(0x35),
+        ],
+    }
+}
+
+// indices of the JUMPDESTs among the first `end` instructions, which bogus edges may
+// point at so they cannot be told apart from real ones by their destination
+pub fn decoy_jumpdests(program: &Program, end: usize) -> Vec<usize> {
+    program.instructions[..end]
+        .iter()
+        .enumerate()
+        .filter(|(_, instruction)| instruction.opcode == 0x5b)
+        .map(|(index, _)| index)
+        .collect()
+}
+
+// label of a random decoy JUMPDEST, None when there is none
+fn pick_decoy(program: &mut Program, decoys: &[usize], rng: &mut dyn RngCore) -> Option<usize> {
+    if decoys.is_empty() {
+        return None;
+    }
+    let index: usize = decoys[rng.random_range(0..decoys.len())];
+    Some(program.label_at(index))
+}
+
+// append a jump to the instruction labelled `target` behind an always true predicate; the
+// fall through is never taken and jumps to a decoy (or hits INVALID without one)
+pub fn append_guarded_jump(
+    program: &mut Program,
+    target: usize,
+    decoys: &[usize],
+    fork: Hardfork,
+    rng: &mut dyn RngCore,
+) {
+    program.extend(opaque_predicate(true, fork, rng));
+    program.push(Instruction::push_label(target, 2));
+    program.push(Instruction::new(0x57));
+    match pick_decoy(program, decoys, rng) {
+        Some(decoy) => append_push_jump(program, decoy),
+        None => {
+            program.push(Instruction::new(0xfe));
+        }
+    }
+}
+
+// append `code` behind an always true predicate that jumps over it, so it looks reachable
+// but never runs; returns the index of its first instruction
+pub fn append_skipped(
+    program: &mut Program,
+    code: Program,
+    fork: Hardfork,
+    rng: &mut dyn RngCore,
+) -> usize {
+    let skip: usize = program.fresh_label();
+    program.extend(opaque_predicate(true, fork, rng));
+    program.push(Instruction::push_label(skip, 2));
+    program.push(Instruction::new(0x57));
+    let start: usize = program.instructions.len();
+    program.extend(code);
+    let mut jumpdest: Instruction = Instruction::new(0x5b);
+    jumpdest.label = Some(skip);
+    program.push(jumpdest);
+    start
+}
+
+/*
+    Disguise the never taken JUMPIs of dead code from instruction `start` on:
+    1. find `PUSH a PUSH b <LT|GT|SLT|SGT|EQ> PUSH dest JUMPI` whose comparison of the two
+       constants is false
+    2. replace the comparison with an always false opaque predicate
+    3. point the JUMPI at a decoy JUMPDEST instead of its own destination
+    returns the number of guards replaced
+*/
+pub fn guard_bogus_edges(
+    program: &mut Program,
+    start: usize,
+    decoys: &[usize],
+    fork: Hardfork,
+    rng: &mut dyn RngCore,
+) -> usize {
+    let mut replaced: usize = 0;
+    let mut index: usize = start;
+    while index + 5 <= program.instructions.len() {
+        let window: &[Instruction] = &program.instructions[index..index + 5];
+        let foldable: bool = window[3].is_push()
+            && window[4].opcode == 0x57
+            && constant_comparison(&window[0], &window[1], window[2].opcode) == Some(false);
+        if !foldable {
+            index += 1;
+            continue;
+        }
+
+        // 2) the predicate takes the place of the two PUSHes and the comparison
+        let predicate: Vec<Instruction> = opaque_predicate(false, fork, rng).instructions;
+        let length: usize = predicate.len();
+        program.instructions.splice(index..index + 3, predicate);
+        // 3) retarget the JUMPI
+        let destination: usize = index + length;
+        if let Some(decoy) = pick_decoy(program, decoys, rng) {
+            program.instructions[destination].target = Some(decoy);
+        }
+        replaced += 1;
+        index = destination + 2;
+    }
+    program.reindex();
+    replaced
+}
+
+// value of `PUSH first PUSH second <comparison>`, None when it is not one
+fn constant_comparison(first: &Instruction, second: &Instruction, comparison: u8) -> Option<bool> {
+    if first.target.is_some() || second.target.is_some() {
+        return None;
+    }
+    // the comparison takes the second PUSH as its left operand; values fitting a usize are
+    // positive as signed words too
+    let left: usize = second.push_value()?;
+    let right: usize = first.push_value()?;
+    match comparison {
+        0x10 | 0x12 => Some(left < right),
+        0x11 | 0x13 => Some(left > right),
+        0x14 => Some(left == right),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::interpreter::{Account, Address, Evm, ExecutionResult, address_from_low_u64};
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use ruint::aliases::U256;
+
+    const CONTRACT: Address = address_from_low_u64(0xc0de);
+
+    // the value `predicate` pushes when called by `caller` with `value` and `calldata`
+    fn evaluate(predicate: Program, caller: Address, value: U256, calldata: &[u8]) -> U256 {
+        let mut program: Program = predicate;
+        // PUSH1 0 MSTORE PUSH1 32 PUSH1 0 RETURN
+        program.push(Instruction::push(0, 1));
+        program.push(Instruction::new(0x52));
+        program.push(Instruction::push(32, 1));
+        program.push(Instruction::push(0, 1));
+        program.push(Instruction::new(0xf3));
+        program.assemble().unwrap();
+
+        let mut evm: Evm = Evm::new();
+        evm.set_balance(caller, value);
+        evm.state.accounts.insert(
+            CONTRACT,
+            Account {
+                code: program.encode(),
+                ..Account::default()
+            },
+        );
+        let result: ExecutionResult = evm.call(caller, CONTRACT, value, calldata);
+        assert!(result.success, "{:?}", result.error);
+        U256::from_be_slice(&result.output)
+    }
+
+    #[test]
+    fn predicates_hold_in_any_environment() {
+        let mut rng: StdRng = StdRng::seed_from_u64(0);
+        for _ in 0..500 {
+            let outcome: bool = rng.random_bool(0.5);
+            let predicate: Program = opaque_predicate(outcome, Hardfork::Prague, &mut rng);
+            let caller: Address = rng.random();
+            let value: U256 = U256::from(rng.random::<u128>());
+            let calldata: Vec<u8> = (0..rng.random_range(0..300))
+                .map(|_| rng.random())
+                .collect();
+            assert_eq!(
+                evaluate(predicate, caller, value, &calldata),
+                U256::from(outcome as u8)
+            );
+        }
+    }
+
+    #[test]
+    fn predicates_only_use_opcodes_of_the_fork() {
+        let mut rng: StdRng = StdRng::seed_from_u64(1);
+        for _ in 0..200 {
+            let predicate: Program = opaque_predicate(true, Hardfork::Frontier, &mut rng);
+            assert!(predicate.instructions.iter().all(|instruction| {
+                instruction
+                    .info()
+                    .is_some_and(|info| info.is_available(Hardfork::Frontier))
+            }));
+        }
+    }
+}
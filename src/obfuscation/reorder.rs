@@ -0,0 +1,159 @@
+use crate::analysis::cfg::{BasicBlock, Cfg, Terminator};
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use rand::RngCore;
+use rand::seq::SliceRandom;
+
+/*
+    Shuffle the basic blocks of a program whose jumps all go through labels:
+    1. cut the program into blocks, the entry block stays first and the rest is shuffled
+    2. a block that ran into the next one (a fallthrough or the untaken side of a JUMPI)
+       and no longer precedes it gets `PUSH <next> JUMP` appended; the next block is
+       labelled, with a JUMPDEST put in front of it when it did not start with one
+    3. a block that ran off the end of the code gets a STOP instead
+    the stack is the same at every block boundary as before, so only gas changes. Returns
+    the number of blocks that moved
+*/
+pub fn reorder_blocks(program: &mut Program, rng: &mut dyn RngCore) -> usize {
+    let cfg: Cfg = Cfg::build(program);
+    let count: usize = cfg.blocks.len();
+    if count < 3 {
+        return 0;
+    }
+    // the blocks are cut back to back, so the last one ending with the code covers it all
+    assert_eq!(
+        cfg.blocks[count - 1].end,
+        program.instructions.len(),
+        "basic blocks do not cover the program"
+    );
+
+    // 1) entry first, everything else in random order
+    let mut order: Vec<usize> = (1..count).collect();
+    order.shuffle(rng);
+    order.insert(0, 0);
+    let mut position: Vec<usize> = vec![0; count];
+    for (index, id) in order.iter().enumerate() {
+        position[*id] = index;
+    }
+    let falls_through: Vec<bool> = cfg
+        .blocks
+        .iter()
+        .map(|block| {
+            matches!(
+                block.terminator,
+                Terminator::Fallthrough | Terminator::ConditionalJump
+            )
+        })
+        .collect();
+    let broken: Vec<bool> = (0..count)
+        .map(|id| falls_through[id] && position.get(id + 1) != Some(&(position[id] + 1)))
+        .collect();
+
+    // 2) label the blocks that are entered through a broken fallthrough; those starting
+    // with a JUMPDEST are labelled in place, the others get a new JUMPDEST below
+    let mut entry_labels: Vec<Option<usize>> = vec![None; count];
+    let mut needs_jumpdest: Vec<bool> = vec![false; count];
+    for id in 0..count - 1 {
+        if !broken[id] {
+            continue;
+        }
+        let start: usize = cfg.blocks[id + 1].start;
+        if program.instructions[start].opcode == 0x5b {
+            entry_labels[id + 1] = Some(program.label_at(start));
+        } else {
+            needs_jumpdest[id + 1] = true;
+        }
+    }
+    for id in 0..count {
+        if needs_jumpdest[id] {
+            entry_labels[id] = Some(program.fresh_label());
+        }
+    }
+
+    let mut instructions: Vec<Instruction> = Vec::with_capacity(program.instructions.len());
+    for id in order.iter().copied() {
+        let block: &BasicBlock = &cfg.blocks[id];
+        if needs_jumpdest[id] {
+            let mut jumpdest: Instruction = Instruction::new(0x5b);
+            jumpdest.label = entry_labels[id];
+            instructions.push(jumpdest);
+        }
+        instructions.extend_from_slice(&program.instructions[block.start..block.end]);
+        if !broken[id] {
+            continue;
+        }
+        match entry_labels.get(id + 1).copied().flatten() {
+            Some(label) => {
+                instructions.push(Instruction::push_label(label, 2));
+                instructions.push(Instruction::new(0x56));
+            }
+            // 3) the last block ran off the end of the code
+            None => instructions.push(Instruction::new(0x00)),
+        }
+    }
+    program.instructions = instructions;
+    program.reindex();
+
+    (0..count).filter(|id| position[*id] != *id).count()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::evm::interpreter::{Account, Address, Evm, ExecutionResult, address_from_low_u64};
+    use crate::helper::hex::decode_hex;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+    use ruint::aliases::U256;
+
+    const CONTRACT: Address = address_from_low_u64(0xc0de);
+
+    // PUSH1 7 JUMP | JUMPDEST STOP | JUMPDEST STOP | JUMPDEST PUSH1 1 PUSH1 0 SSTORE, where
+    // the last block runs off the end of the code
+    fn program() -> Program {
+        let mut program: Program =
+            Program::decode(&decode_hex("6007565b005b005b6001600055").unwrap());
+        let label: usize = program.label_at(6);
+        program.instructions[0].target = Some(label);
+        program
+    }
+
+    // storage slot 0 after running `program`
+    fn run(program: &Program) -> U256 {
+        let mut evm: Evm = Evm::new();
+        evm.state.accounts.insert(
+            CONTRACT,
+            Account {
+                code: program.encode(),
+                ..Account::default()
+            },
+        );
+        let result: ExecutionResult = evm.call(CONTRACT, CONTRACT, U256::ZERO, &[]);
+        assert!(result.success, "{:?}", result.error);
+        evm.account(&CONTRACT)
+            .unwrap()
+            .storage
+            .get(&U256::ZERO)
+            .copied()
+            .unwrap_or_default()
+    }
+
+    #[test]
+    fn keeps_every_block() {
+        for seed in 0..16 {
+            let mut program: Program = program();
+            let original: usize = program.instructions.len();
+            reorder_blocks(&mut program, &mut StdRng::seed_from_u64(seed));
+            program.assemble().unwrap();
+            // the SSTORE block keeps all of its instructions
+            assert!(
+                program
+                    .instructions
+                    .iter()
+                    .any(|instruction| instruction.opcode == 0x55)
+            );
+            assert!(program.instructions.len() >= original);
+            assert_eq!(run(&program), U256::from(1));
+        }
+    }
+}
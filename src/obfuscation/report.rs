@@ -0,0 +1,71 @@
+use crate::analysis::code_type::SplitHeuristic;
+use crate::analysis::immutables::ImmutableReference;
+use crate::constant::hardfork::Hardfork;
+use crate::helper::link::{LinkReference, encode_unlinked_hex};
+use crate::obfuscation::config::Pass;
+
+// Result of an obfuscation run: the new creation code plus what was done to it
+#[derive(Debug, Clone)]
+pub struct ObfuscationReport {
+    // complete obfuscated creation code (initcode followed by runtime)
+    pub bytecode: Vec<u8>,
+    pub original_initcode_length: usize,
+    pub initcode_length: usize,
+    pub original_runtime_length: usize,
+    pub runtime_length: usize,
+    // bytes kept behind the runtime: constructor arguments and any other appended data
+    pub constructor_args_length: usize,
+    // length of the metadata trailer at the end of the obfuscated runtime (0 when stripped)
+    pub metadata_length: usize,
+    // number of PUSH-JUMP sequences redirected through a trampoline
+    pub redirected_jumps: usize,
+    // number of PUSH-JUMPI sequences whose taken edge goes through a trampoline
+    pub redirected_conditional_jumps: usize,
+    // number of code pointers not directly followed by their jump that were redirected
+    pub redirected_pointers: usize,
+    // sites of every kind redirected inside the initcode
+    pub initcode_redirects: usize,
+    // blocks of the runtime only reached through the flattening dispatcher, and edges of
+    // its code rewritten to go through it
+    pub dispatched_blocks: usize,
+    pub flattened_edges: usize,
+    // basic blocks of the runtime moved away from their place in the layout
+    pub reordered_blocks: usize,
+    // selector comparisons of the runtime's dispatcher that were masked, and fake ones added
+    pub masked_selectors: usize,
+    pub fake_selectors: usize,
+    // enabled passes that were not applied because they could change behaviour
+    pub skipped_passes: Vec<SkippedPass>,
+    // immutable placeholders at their offsets in the runtime, and how many of them moved
+    // (their writes were patched)
+    pub immutable_references: Vec<ImmutableReference>,
+    pub moved_immutables: usize,
+    // index each instruction had in the input initcode / runtime, None for inserted code;
+    // the runtime's metadata trailer is not included
+    pub initcode_origins: Vec<Option<usize>>,
+    pub runtime_origins: Vec<Option<usize>>,
+    // library placeholders still to be linked, at their offsets in `bytecode`
+    pub link_references: Vec<LinkReference>,
+    // how the runtime was located, None when the input was treated as runtime code
+    pub split: Option<SplitHeuristic>,
+    pub target_fork: Hardfork,
+    // seed the run was derived from, None when the caller supplied its own rng
+    pub seed: Option<u64>,
+}
+
+// An enabled pass left out for one program, and why
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SkippedPass {
+    pub pass: Pass,
+    // skipped on the initcode rather than the runtime
+    pub initcode: bool,
+    pub reason: &'static str,
+}
+
+impl ObfuscationReport {
+    // obfuscated creation code as lowercase hex without 0x, unlinked libraries written as
+    // their placeholders again
+    pub fn bytecode_hex(&self) -> String {
+        encode_unlinked_hex(&self.bytecode, &self.link_references)
+    }
+}
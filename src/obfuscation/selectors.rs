@@ -0,0 +1,436 @@
+use crate::analysis::cfg::Cfg;
+use crate::analysis::stack::StackAnalysis;
+use crate::ir::instruction::Instruction;
+use crate::ir::program::Program;
+use crate::obfuscation::opaque::decoy_jumpdests;
+use rand::{Rng, RngCore};
+use ruint::aliases::U256;
+use std::collections::BTreeSet;
+
+// A comparison of the function selector on top of the stack with a PUSH4 constant, right
+// before the `PUSH <dest> JUMPI` taking its result
+#[derive(Debug, Clone)]
+pub struct SelectorSite {
+    // first of the three instructions computing the comparison
+    pub index: usize,
+    pub selector: u32,
+    // comparison of the constant (top) with the selector: EQ, XOR, GT or LT
+    opcode: u8,
+}
+
+/*
+    Find the function dispatcher: where the selector is extracted from the calldata by
+    `PUSH1 0xe0 SHR` (or the older `PUSH4 0xffffffff PUSH29 2^224 PUSH1 0 CALLDATALOAD DIV
+    AND`), it is below 2^32 and every following
+    1. `DUP1 PUSH4 <s> EQ|XOR|GT|LT PUSH <dest> JUMPI` (solc) or
+    2. `PUSH4 <s> DUP2 EQ|XOR|GT|LT PUSH <dest> JUMPI` (via-ir, vyper)
+    compares it with a constant and leaves it on top. The chain ends at anything else; the
+    target of a GT / LT (the other half of solc's binary search) or of a XOR (vyper's next
+    check) starts a chain too when it is a JUMPDEST no other block reaches
+*/
+pub fn find_selector_sites(program: &Program, analysis: &StackAnalysis) -> Vec<SelectorSite> {
+    let mut sites: Vec<SelectorSite> = Vec::new();
+    let mut visited: BTreeSet<usize> = BTreeSet::new();
+    for index in 0..program.instructions.len() {
+        if let Some(start) = selector_extracted(program, index) {
+            walk_chain(program, &analysis.cfg, start, &mut sites, &mut visited);
+        }
+    }
+    sites.sort_by_key(|site| site.index);
+    sites
+}
+
+// index right after a selector extraction ending at `index`
+fn selector_extracted(program: &Program, index: usize) -> Option<usize> {
+    let instructions: &[Instruction] = &program.instructions;
+    if instructions[index].opcode == 0x1c {
+        let shift: &Instruction = instructions.get(index.checked_sub(1)?)?;
+        return (shift.push_value() == Some(0xe0)).then_some(index + 1);
+    }
+    if instructions[index].opcode == 0x16 && index >= 5 {
+        let window: &[Instruction] = &instructions[index - 5..index];
+        let divisor: &Instruction = &window[1];
+        let legacy: bool = window[0].push_value() == Some(0xffffffff)
+            && divisor.opcode == 0x7c
+            && divisor.immediate.first() == Some(&1)
+            && divisor.immediate[1..].iter().all(|byte| *byte == 0)
+            && window[2].is_push()
+            && window[3].opcode == 0x35
+            && window[4].opcode == 0x04;
+        return legacy.then_some(index + 1);
+    }
+    None
+}
+
+fn walk_chain(
+    program: &Program,
+    cfg: &Cfg,
+    start: usize,
+    sites: &mut Vec<SelectorSite>,
+    visited: &mut BTreeSet<usize>,
+) {
+    if !visited.insert(start) {
+        return;
+    }
+    let mut index: usize = start;
+    while let Some(site) = match_site(program, index) {
+        let jumpi: usize = index + 4;
+        if site.opcode != 0x14 {
+            // the selector is still on top where this JUMPI goes, if only entered from it
+            let target: Option<usize> = program.instructions[index + 3]
+                .target
+                .and_then(|label| program.label_index(label))
+                .filter(|target| program.instructions[*target].opcode == 0x5b);
+            if let Some(target) = target {
+                let from: Option<usize> = cfg.block_of_instruction(jumpi);
+                let only_entry: bool = cfg.block_of_instruction(target).is_some_and(|block| {
+                    from.is_some_and(|from| cfg.predecessors(block) == [from])
+                });
+                if only_entry {
+                    walk_chain(program, cfg, target + 1, sites, visited);
+                }
+            }
+        }
+        sites.push(site);
+        index = jumpi + 1;
+    }
+}
+
+// the comparison starting at `index`, when it is one of the two shapes
+fn match_site(program: &Program, index: usize) -> Option<SelectorSite> {
+    let window: &[Instruction] = program.instructions.get(index..index + 5)?;
+    if !window[3].is_push() || window[4].opcode != 0x57 {
+        return None;
+    }
+    let (constant, opcode): (&Instruction, u8) = match (window[0].opcode, window[1].opcode) {
+        (0x80, 0x63) => (&window[1], window[2].opcode),
+        // the selector is the left operand: flip the order comparisons
+        (0x63, 0x81) => {
+            let opcode: u8 = match window[2].opcode {
+                0x10 => 0x11,
+                0x11 => 0x10,
+                opcode => opcode,
+            };
+            (&window[0], opcode)
+        }
+        _ => return None,
+    };
+    if !matches!(opcode, 0x10 | 0x11 | 0x14 | 0x18) || constant.target.is_some() {
+        return None;
+    }
+    Some(SelectorSite {
+        index,
+        selector: constant.push_value()? as u32,
+        opcode,
+    })
+}
+
+/*
+    Rewrite every comparison as `DUP1 PUSH32 <k1> <op1> PUSH32 <k2> <op2> PUSH32 <s'>
+    <comparison>`, with fresh constants for each:
+    1. EQ and XOR go through a bijection of the whole word, so they hold exactly when they
+       did: a MUL by an odd k and a XOR or ADD, in either order (modulo 2^256)
+    2. GT and LT map both sides to x * k1 + k2 with 0 < k1 < 2^222 and k2 below 2^254,
+       which cannot overflow a value below 2^32 and so keeps the order
+    two steps, one of them a multiplication, keep s' from sharing its bytes with a mask
+    3. in front of some comparisons, a fake one masks a constant of more than 32 bits and
+       jumps into a real JUMPDEST when equal, which the selector never is
+    the selector stays on top of the stack throughout. Returns the number of fakes
+*/
+pub fn mask_selectors(
+    program: &mut Program,
+    sites: &[SelectorSite],
+    rng: &mut dyn RngCore,
+) -> usize {
+    let decoys: Vec<usize> = decoy_jumpdests(program, program.instructions.len())
+        .into_iter()
+        .map(|index| program.label_at(index))
+        .collect();
+    let mut fakes: usize = 0;
+    for site in sites.iter().rev() {
+        let original: &[Instruction] = &program.instructions[site.index..site.index + 3];
+        // the DUP, the PUSH4 and the comparison keep their place in the source map
+        let (dup, push): (&Instruction, &Instruction) = match original[0].opcode {
+            0x80 => (&original[0], &original[1]),
+            _ => (&original[1], &original[0]),
+        };
+        let origins: [Option<usize>; 3] = [dup.origin, push.origin, original[2].origin];
+
+        let mut replacement: Vec<Instruction> = Vec::new();
+        // 3) a fake comparison
+        if !decoys.is_empty() && rng.random_bool(0.5) {
+            let mut fake: U256 = random_word(rng);
+            if fake < U256::from(1u64 << 32) {
+                fake |= U256::from(1) << 255;
+            }
+            replacement.extend(masked_comparison(fake, 0x14, [None; 3], rng));
+            let decoy: usize = decoys[rng.random_range(0..decoys.len())];
+            replacement.push(Instruction::push_label(decoy, 2));
+            replacement.push(Instruction::new(0x57));
+            fakes += 1;
+        }
+        replacement.extend(masked_comparison(
+            U256::from(site.selector),
+            site.opcode,
+            origins,
+            rng,
+        ));
+        program
+            .instructions
+            .splice(site.index..site.index + 3, replacement);
+    }
+    program.reindex();
+    fakes
+}
+
+// `DUP1 <masking> PUSH32 <constant'> <comparison>` comparing the selector with `constant`;
+// `origins` are those of the DUP, the masked constant and the comparison
+fn masked_comparison(
+    constant: U256,
+    comparison: u8,
+    origins: [Option<usize>; 3],
+    rng: &mut dyn RngCore,
+) -> Vec<Instruction> {
+    let multiplier: U256 = random_word(rng) | U256::from(1);
+    let steps: [(u8, U256); 2] = match comparison {
+        // 1)
+        0x14 | 0x18 => {
+            let other: (u8, U256) = match rng.random_bool(0.5) {
+                true => (0x18, random_word(rng)),
+                false => (0x01, random_word(rng)),
+            };
+            match rng.random_bool(0.5) {
+                true => [(0x02, multiplier), other],
+                false => [other, (0x02, multiplier)],
+            }
+        }
+        // 2)
+        _ => [
+            (0x02, (multiplier >> 34) | U256::from(1)),
+            (0x01, random_word(rng) >> 2),
+        ],
+    };
+
+    let mut instructions: Vec<Instruction> = Vec::new();
+    let mut dup: Instruction = Instruction::new(0x80);
+    dup.origin = origins[0];
+    instructions.push(dup);
+    let mut masked: U256 = constant;
+    for (operation, mask) in steps {
+        masked = match operation {
+            0x02 => masked.wrapping_mul(mask),
+            0x18 => masked ^ mask,
+            _ => masked.wrapping_add(mask),
+        };
+        instructions.push(push_word(mask));
+        instructions.push(Instruction::new(operation));
+    }
+    let mut masked_push: Instruction = push_word(masked);
+    masked_push.origin = origins[1];
+    instructions.push(masked_push);
+    let mut compare: Instruction = Instruction::new(comparison);
+    compare.origin = origins[2];
+    instructions.push(compare);
+    instructions
+}
+
+fn random_word(rng: &mut dyn RngCore) -> U256 {
+    U256::from_limbs([rng.random(), rng.random(), rng.random(), rng.random()])
+}
+
+fn push_word(value: U256) -> Instruction {
+    let mut push: Instruction = Instruction::new(0x7f);
+    push.immediate = value.to_be_bytes::<32>().to_vec();
+    push
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::analysis::stack::analyze_stack;
+    use crate::evm::interpreter::{Account, Address, Evm, ExecutionResult, address_from_low_u64};
+    use crate::helper::hex::decode_hex;
+    use rand::SeedableRng;
+    use rand::rngs::StdRng;
+
+    const CONTRACT: Address = address_from_low_u64(0xc0de);
+
+    fn sites(hex: &str) -> Vec<(usize, u32, u8)> {
+        let program: Program = Program::decode(&decode_hex(hex).unwrap());
+        find_selector_sites(&program, &analyze_stack(&program))
+            .iter()
+            .map(|site| (site.index, site.selector, site.opcode))
+            .collect()
+    }
+
+    fn call(code: Vec<u8>, calldata: &[u8]) -> (Evm, ExecutionResult) {
+        let mut evm: Evm = Evm::new();
+        evm.state.accounts.insert(
+            CONTRACT,
+            Account {
+                code,
+                ..Account::default()
+            },
+        );
+        let result: ExecutionResult =
+            evm.call(address_from_low_u64(1), CONTRACT, U256::ZERO, calldata);
+        assert!(result.success, "{:?}", result.error);
+        (evm, result)
+    }
+
+    // storage slot 0 after calling `code` with `calldata`
+    fn stored(code: &[u8], calldata: &[u8]) -> U256 {
+        let (evm, _) = call(code.to_vec(), calldata);
+        evm.account(&CONTRACT).unwrap().storage[&U256::ZERO]
+    }
+
+    #[test]
+    fn finds_each_shape_of_dispatcher() {
+        // solc: DUP1 PUSH4 EQ and the binary search's GT
+        assert_eq!(
+            sites("5f3560e01c8063aaaaaaaa14610100578063bbbbbbbb11610200575f80fd"),
+            vec![(4, 0xaaaaaaaa, 0x14), (9, 0xbbbbbbbb, 0x11)]
+        );
+        // via-ir compares with the selector as left operand: its LT is a GT of the constant
+        assert_eq!(
+            sites("5f3560e01c63aaaaaaaa811461010057630000000f811061020057fe"),
+            vec![(4, 0xaaaaaaaa, 0x14), (9, 0x0f, 0x11)]
+        );
+        // the extraction of old compilers, by DIV and AND
+        let legacy: String = format!(
+            "63ffffffff7c01{}60003504168063aaaaaaaa1461010057fe",
+            "00".repeat(28)
+        );
+        assert_eq!(sites(&legacy), vec![(6, 0xaaaaaaaa, 0x14)]);
+    }
+
+    #[test]
+    fn needs_an_extracted_selector_and_an_unbroken_chain() {
+        // the whole calldata word is compared
+        assert!(sites("5f358063aaaaaaaa1461010057fe").is_empty());
+        // a POP between the comparisons ends the chain
+        assert_eq!(
+            sites("5f3560e01c8063aaaaaaaa1461010057508063bbbbbbbb1461010057fe"),
+            vec![(4, 0xaaaaaaaa, 0x14)]
+        );
+    }
+
+    #[test]
+    fn masked_comparisons_keep_their_result() {
+        let constant: u32 = 0x8000_0010;
+        for seed in 0..16 {
+            let mut rng: StdRng = StdRng::seed_from_u64(seed);
+            for comparison in [0x10, 0x11, 0x14, 0x18] {
+                for selector in [0, 0x10, constant - 1, constant, constant + 1, u32::MAX] {
+                    // whether the comparison holds, then the selector it left on the stack
+                    let run = |comparison_code: Vec<Instruction>| -> (bool, U256) {
+                        let mut program: Program = Program::default();
+                        program.push(Instruction::push(selector as usize, 4));
+                        for instruction in comparison_code {
+                            program.push(instruction);
+                        }
+                        // ISZERO ISZERO PUSH0 MSTORE PUSH1 0x20 MSTORE, return both words
+                        let mut code: Vec<u8> = program.encode();
+                        code.extend(decode_hex("15155f5260205260405ff3").unwrap());
+                        let (_, result) = call(code, &[]);
+                        let output: Vec<u8> = result.output;
+                        (output[31] == 1, U256::from_be_slice(&output[32..64]))
+                    };
+                    let original: Vec<Instruction> = vec![
+                        Instruction::new(0x80),
+                        Instruction::push(constant as usize, 4),
+                        Instruction::new(comparison),
+                    ];
+                    let masked: Vec<Instruction> =
+                        masked_comparison(U256::from(constant), comparison, [None; 3], &mut rng);
+                    assert_eq!(
+                        run(masked),
+                        run(original),
+                        "opcode {:#x}, selector {:#x}",
+                        comparison,
+                        selector
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn masked_dispatchers_route_every_call_alike() {
+        // stores 1 for 0xaaaaaaaa, 2 for 0x00000001 (below 0xbbbbbbbb, then checked in the
+        // GT target) and 3 for anything else
+        let jumpdest = |label: usize| {
+            let mut jumpdest: Instruction = Instruction::new(0x5b);
+            jumpdest.label = Some(label);
+            jumpdest
+        };
+        let store = |value: usize| {
+            [
+                Instruction::push(value, 1),
+                Instruction::new(0x5f),
+                Instruction::new(0x55),
+                Instruction::new(0x00),
+            ]
+        };
+        let mut program: Program = Program::default();
+        let code: Vec<Instruction> = [
+            Instruction::new(0x5f),
+            Instruction::new(0x35),
+            Instruction::push(0xe0, 1),
+            Instruction::new(0x1c),
+            Instruction::new(0x80),
+            Instruction::push(0xaaaaaaaa, 4),
+            Instruction::new(0x14),
+            Instruction::push_label(0, 2),
+            Instruction::new(0x57),
+            Instruction::new(0x80),
+            Instruction::push(0xbbbbbbbb, 4),
+            Instruction::new(0x11),
+            Instruction::push_label(1, 2),
+            Instruction::new(0x57),
+        ]
+        .into_iter()
+        .chain(store(3))
+        .chain([jumpdest(0)])
+        .chain(store(1))
+        .chain([
+            jumpdest(1),
+            Instruction::new(0x80),
+            Instruction::push(1, 4),
+            Instruction::new(0x14),
+            Instruction::push_label(0, 2),
+            Instruction::new(0x57),
+        ])
+        .chain(store(2))
+        .collect();
+        for instruction in code {
+            program.push(instruction);
+        }
+        program.assemble().unwrap();
+        let original: Vec<u8> = program.encode();
+        let found: Vec<SelectorSite> = find_selector_sites(&program, &analyze_stack(&program));
+        assert_eq!(found.len(), 3);
+
+        for seed in 0..16 {
+            let mut masked: Program = program.clone();
+            mask_selectors(&mut masked, &found, &mut StdRng::seed_from_u64(seed));
+            masked.assemble().unwrap();
+            let code: Vec<u8> = masked.encode();
+            // no selector is left in the clear
+            for selector in [0xaaaaaaaau32, 0xbbbbbbbb] {
+                let bytes: [u8; 4] = selector.to_be_bytes();
+                assert!(!code.windows(4).any(|window| window == bytes));
+            }
+            for selector in [0xaaaaaaaau32, 1, 0xbbbbbbbb, 0xcccccccc, 0] {
+                let calldata: [u8; 4] = selector.to_be_bytes();
+                assert_eq!(
+                    stored(&code, &calldata),
+                    stored(&original, &calldata),
+                    "seed {}, selector {:#x}",
+                    seed,
+                    selector
+                );
+            }
+        }
+    }
+}
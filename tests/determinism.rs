@@ -0,0 +1,45 @@
+use obfuscator_rs::obfuscation::config::ObfuscationConfig;
+use obfuscator_rs::obfuscation::obfuscate::{obfuscate, obfuscate_with_rng};
+use obfuscator_rs::obfuscation::report::ObfuscationReport;
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+const ERC20: &str = include_str!("../samples/erc20_creation_code.hex");
+
+fn seeded(seed: Option<u64>) -> ObfuscationConfig {
+    ObfuscationConfig {
+        seed,
+        obfuscate_initcode: true,
+        ..ObfuscationConfig::default()
+    }
+}
+
+fn bytecode(config: &ObfuscationConfig) -> Vec<u8> {
+    obfuscate(ERC20.trim(), config).unwrap().bytecode
+}
+
+#[test]
+fn same_seed_gives_the_same_bytecode() {
+    for seed in [0, 1, u64::MAX] {
+        assert_eq!(bytecode(&seeded(Some(seed))), bytecode(&seeded(Some(seed))));
+    }
+    // the seeded entry point draws from the same rng as an injected one
+    let injected: ObfuscationReport =
+        obfuscate_with_rng(ERC20.trim(), &seeded(None), &mut StdRng::seed_from_u64(7)).unwrap();
+    assert_eq!(injected.bytecode, bytecode(&seeded(Some(7))));
+}
+
+#[test]
+fn different_seeds_give_different_bytecode() {
+    let outputs: Vec<Vec<u8>> = (0..8).map(|seed| bytecode(&seeded(Some(seed)))).collect();
+    for (index, output) in outputs.iter().enumerate() {
+        assert!(!outputs[index + 1..].contains(output), "seed {}", index);
+    }
+}
+
+#[test]
+fn a_drawn_seed_is_reported_and_reproduces_the_output() {
+    let report: ObfuscationReport = obfuscate(ERC20.trim(), &seeded(None)).unwrap();
+    let seed: u64 = report.seed.unwrap();
+    assert_eq!(bytecode(&seeded(Some(seed))), report.bytecode);
+}
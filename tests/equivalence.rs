@@ -0,0 +1,127 @@
+use obfuscator_rs::error::ObfuscatorError;
+use obfuscator_rs::evm::equivalence::{EquivalenceReport, check_equivalence, default_calls};
+use obfuscator_rs::helper::hex::decode_hex;
+use obfuscator_rs::ir::program::Program;
+use obfuscator_rs::obfuscation::config::{ObfuscationConfig, Pass};
+use obfuscator_rs::obfuscation::dead_code::{DeadCodeConfig, DeadCodeLength};
+use obfuscator_rs::obfuscation::obfuscate::obfuscate;
+use obfuscator_rs::obfuscation::report::ObfuscationReport;
+
+// the runtime CODECOPYs the 3 bytes `600f56` behind its STOP and returns them; they decode
+// as PUSH1 0x0f JUMP but never run
+const DATA_BLOB: &str = "601180600c6000396000f3fe6003600c60003960036000f3600f565b00";
+
+// the runtime jumps over two STOPs to a block that stores 1 and runs off the end of the code
+const RUNS_OFF_ITS_END: &str = "600d80600b6000396000f36007565b005b005b6001600055";
+// the runtime keeps the JUMPDEST offset 0x14 in memory and jumps to it from there, so its
+// code must not move
+const DESTINATION_AS_DATA: &str =
+    "61001b80600c6000396000f3600756000000005b600c56005b601480600052565b600160005500";
+const ERC20: &str = include_str!("../samples/erc20_creation_code.hex");
+
+// obfuscate `creation_code` with `config` and replay the default calls on both versions
+fn assert_equivalent(creation_code: &str, config: &ObfuscationConfig) {
+    let original: Vec<u8> = decode_hex(creation_code).unwrap();
+    let report: ObfuscationReport = obfuscate(creation_code, config).unwrap();
+    let calls: Vec<Vec<u8>> = default_calls(&Program::decode(&original));
+    let equivalence: EquivalenceReport = check_equivalence(&original, &report.bytecode, &calls);
+    assert!(
+        equivalence.is_equivalent(),
+        "passes {:?}, seed {:?}: {:?}",
+        config.passes,
+        config.seed,
+        equivalence.divergence
+    );
+}
+
+#[test]
+fn data_blob_is_not_redirected() {
+    for seed in 0..8 {
+        let config: ObfuscationConfig = ObfuscationConfig {
+            seed: Some(seed),
+            ..ObfuscationConfig::default()
+        };
+        assert_equivalent(DATA_BLOB, &config);
+    }
+}
+
+#[test]
+fn appended_code_is_not_run_into() {
+    let pass_sets: [&[Pass]; 6] = [
+        &[Pass::JumpRedirect],
+        &[Pass::PointerRedirect],
+        &[Pass::JumpRedirect, Pass::DeadCode],
+        &[Pass::JumpRedirect, Pass::DeadCode, Pass::OpaquePredicates],
+        &[Pass::Flatten],
+        &[Pass::JumpRedirect, Pass::Flatten, Pass::ReorderBlocks],
+    ];
+    for passes in pass_sets {
+        for seed in 0..4 {
+            let config: ObfuscationConfig = ObfuscationConfig {
+                passes: passes.to_vec(),
+                seed: Some(seed),
+                ..ObfuscationConfig::default()
+            };
+            assert_equivalent(RUNS_OFF_ITS_END, &config);
+        }
+    }
+}
+
+#[test]
+fn layout_dependent_code_is_not_widened() {
+    let passes: Vec<Pass> = vec![Pass::JumpRedirect, Pass::DeadCode];
+    // the appended JUMPDESTs stay below 0x100 and every PUSH1 keeps its width
+    let config: ObfuscationConfig = ObfuscationConfig {
+        passes: passes.clone(),
+        seed: Some(1),
+        ..ObfuscationConfig::default()
+    };
+    assert_equivalent(DESTINATION_AS_DATA, &config);
+
+    let config: ObfuscationConfig = ObfuscationConfig {
+        passes,
+        seed: Some(1),
+        dead_code: DeadCodeConfig {
+            length: DeadCodeLength { min: 300, max: 300 },
+            ..DeadCodeConfig::default()
+        },
+        ..ObfuscationConfig::default()
+    };
+    assert_eq!(
+        obfuscate(DESTINATION_AS_DATA, &config).err(),
+        Some(ObfuscatorError::FixedLayout(String::from(
+            "some jump destinations are not labelled"
+        )))
+    );
+}
+
+#[test]
+fn every_pass_subset_keeps_erc20_behaviour() {
+    let creation_code: &str = ERC20.trim();
+    for subset in 0..1u32 << Pass::ALL.len() {
+        let passes: Vec<Pass> = Pass::ALL
+            .iter()
+            .enumerate()
+            .filter(|(bit, _)| subset & (1 << bit) != 0)
+            .map(|(_, pass)| *pass)
+            .collect();
+        let config: ObfuscationConfig = ObfuscationConfig {
+            passes,
+            seed: Some(subset as u64),
+            ..ObfuscationConfig::default()
+        };
+        assert_equivalent(creation_code, &config);
+    }
+}
+
+#[test]
+fn default_config_stays_deployable() {
+    // a runtime above the EIP-170 limit fails to deploy, which shows as a divergence
+    for seed in 0..32 {
+        let config: ObfuscationConfig = ObfuscationConfig {
+            seed: Some(seed),
+            ..ObfuscationConfig::default()
+        };
+        assert_equivalent(ERC20.trim(), &config);
+    }
+}